@@ -1,68 +1,209 @@
 // SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
 // SPDX-License-Identifier: MIT
 
-use log::info;
+use chrono::{Local, TimeZone};
+use log::{debug, error, info};
 use rusqlite::Connection;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 use tokio::{
     spawn,
     sync::{Mutex, mpsc},
-    time::{Duration, Instant, interval, sleep_until},
+    time::{Duration, Instant, MissedTickBehavior, interval, sleep, sleep_until},
+};
+
+use crate::{
+    config::Config,
+    database, health,
+    lists::{self, DomainLists, SharedLists},
+    schedule::Job,
 };
 
-use crate::database;
+// Link id, index of the weighted target used (if any), and whether to reset the hits
+pub(crate) type HitUpdate = (i64, Option<usize>, bool);
 
 // Run hit updates every 500ms or once 500 distinct links are pending.
+// Hits aren't counted in maintenance mode, since the links they refer to may be gone after a restore.
 pub(crate) fn spawn_hits_worker(
     writer: Arc<Mutex<Connection>>,
-    mut hits_rx: mpsc::Receiver<(String, bool)>,
+    mut hits_rx: mpsc::Receiver<HitUpdate>,
+    maintenance: Arc<AtomicBool>,
 ) -> tokio::task::JoinHandle<()> {
     spawn({
         async move {
             let mut pending = HashMap::new();
-            fn update_count(link: String, reset: bool, pending: &mut HashMap<String, i64>) {
+            fn update_count(
+                (link, target, reset): HitUpdate,
+                pending: &mut HashMap<(i64, Option<usize>), i64>,
+            ) {
                 if reset {
-                    pending.remove(&link);
+                    pending.retain(|(l, _), _| *l != link);
                 } else {
-                    *pending.entry(link).or_insert(0) += 1;
+                    *pending.entry((link, target)).or_insert(0) += 1;
                 }
             }
             loop {
-                let Some((first, reset)) = hits_rx.recv().await else {
+                let Some(first) = hits_rx.recv().await else {
                     break;
                 };
-                update_count(first, reset, &mut pending);
+                update_count(first, &mut pending);
                 let deadline = Instant::now() + Duration::from_millis(500);
 
                 while pending.len() < 500 {
                     tokio::select! {
-                        Some((link, reset)) = hits_rx.recv() => update_count(link, reset, &mut pending),
+                        Some(update) = hits_rx.recv() => update_count(update, &mut pending),
                         _ = sleep_until(deadline) => break,
                         else => break,
                     }
                 }
                 if !pending.is_empty() {
-                    database::add_hits(std::mem::take(&mut pending), &mut *writer.lock().await);
+                    let pending = std::mem::take(&mut pending);
+                    let mut db = writer.lock().await;
+                    // Checked while holding the lock, so that hits waiting on a restore are dropped
+                    if maintenance.load(Ordering::Relaxed) {
+                        debug!(
+                            "Discarding the hits of {} links in maintenance mode.",
+                            pending.len()
+                        );
+                    } else {
+                        database::add_hits(pending, &mut db);
+                    }
                 }
             }
         }
     })
 }
 
-// Do database cleanup once every hour
-pub(crate) fn spawn_cleaner(
+// Check the health of the destinations periodically, if enabled
+pub(crate) fn spawn_health_checker(
     writer: Arc<Mutex<Connection>>,
-    use_wal_mode: bool,
-) -> tokio::task::JoinHandle<()> {
-    spawn({
+    config: &Config,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let minutes = config.health_check_interval?;
+    let (concurrency, rate) = (config.health_check_concurrency, config.health_check_rate);
+    let allow_private = config.health_check_allow_private;
+    Some(spawn(async move {
+        info!("Starting destination health checker, will run once every {minutes} minutes.");
+        let client = health::build_client(allow_private);
+        let mut interval = interval(Duration::from_secs(minutes * 60));
+        // A slow run shouldn't be followed by a burst of runs
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            health::run_checks(&writer, &client, allow_private, concurrency, rate).await;
+        }
+    }))
+}
+
+// Reload the domain lists when their files change, checking once every minute
+pub(crate) fn spawn_lists_reloader(
+    shared: SharedLists,
+    writer: Arc<Mutex<Connection>>,
+    config: &Config,
+) -> Option<tokio::task::JoinHandle<()>> {
+    if lists::current(&shared).is_empty() {
+        return None;
+    }
+    let config = config.clone();
+    Some(spawn(async move {
+        info!("Watching the domain lists for changes.");
+        let mut interval = interval(Duration::from_secs(60));
+        let mut changed = true;
+        loop {
+            interval.tick().await;
+            if lists::current(&shared).is_stale() {
+                match DomainLists::load(&config) {
+                    Ok(new) => {
+                        info!("Reloaded the domain lists.");
+                        lists::replace(&shared, new);
+                        changed = true;
+                    }
+                    Err(e) => {
+                        error!("Unable to reload the domain lists, keeping the old ones: {e}")
+                    }
+                }
+            }
+            if changed && config.disable_blocked_links {
+                let current = lists::current(&shared);
+                let disabled = database::disable_blocked(&mut *writer.lock().await, |url| {
+                    current.is_blocked(url)
+                });
+                if disabled > 0 {
+                    info!("Disabled {disabled} links matching the blocklist.");
+                }
+            }
+            changed = false;
+        }
+    }))
+}
+
+// Run the maintenance jobs on their schedules, catching up on a run missed while the server was down
+pub(crate) fn spawn_maintenance(
+    writer: Arc<Mutex<Connection>>,
+    config: &Config,
+) -> Vec<tokio::task::JoinHandle<()>> {
+    let mut handles = Vec::new();
+    for (job, schedule) in config.schedules() {
+        let Some(schedule) = schedule.cloned() else {
+            continue;
+        };
         let writer = Arc::clone(&writer);
-        async move {
-            info!("Starting database cleanup service, will run once every hour.");
-            let mut interval = interval(Duration::from_secs(3600));
+        let config = config.clone();
+        handles.push(spawn(async move {
+            let name = job.name();
+            info!(
+                "The {name} job will run on the schedule {}.",
+                schedule.expression
+            );
+            let last_run = database::last_runs(&*writer.lock().await)
+                .remove(name)
+                .and_then(|t| Local.timestamp_opt(t, 0).single());
+            let mut after = Local::now();
+            // The cleanup runs right away if it never ran before, like it used to on every start
+            let mut missed = match last_run {
+                Some(t) => schedule.next_after(t).is_some_and(|t| t <= after),
+                None => matches!(job, Job::Cleanup),
+            };
             loop {
-                interval.tick().await;
-                database::cleanup(&*writer.lock().await, use_wal_mode);
+                if !missed {
+                    let Some(next) = schedule.next_after(after) else {
+                        break;
+                    };
+                    sleep((next - Local::now()).to_std().unwrap_or_default()).await;
+                    after = next;
+                }
+                missed = false;
+                debug!("Running the {name} job.");
+                let db = writer.lock().await;
+                if run_job(job, &db, &config) {
+                    database::record_run(&db, name);
+                }
+                // Runs that were due while this one was running are skipped
+                after = after.max(Local::now());
             }
+        }));
+    }
+    handles
+}
+
+fn run_job(job: Job, db: &Connection, config: &Config) -> bool {
+    match job {
+        Job::Cleanup => {
+            database::cleanup(
+                db,
+                config.use_wal_mode,
+                config.disable_idle_links,
+                config.expiry_grace_period,
+            );
+            true
         }
-    })
+        Job::Backup => database::backup(db, &config.backup_settings),
+        Job::Vacuum => database::vacuum(db),
+        Job::Optimize => database::optimize(db),
+    }
 }
@@ -3,19 +3,30 @@
 
 use log::{info, warn};
 use passwords::{analyzer::analyze, scorer::score};
+use sqids::Sqids;
 use std::{
+    collections::HashSet,
     env::{VarError, var},
     fmt::Display,
     fs,
+    net::IpAddr,
     path::Path,
 };
+use url::Url;
 
-use crate::auth;
+use crate::{
+    auth,
+    database::BackupSettings,
+    schedule::{self, Job, Schedule},
+    slugs::{self, SlugTemplate},
+};
 
+// Styles of randomly generated slugs
 #[derive(Clone)]
 pub(crate) enum SlugStyle {
     Pair,
     Uid,
+    Template(Vec<SlugTemplate>),
 }
 impl Display for SlugStyle {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -25,6 +36,7 @@ impl Display for SlugStyle {
             match self {
                 Self::Pair => "Pair",
                 Self::Uid => "UID",
+                Self::Template(_) => "Template",
             }
         )
     }
@@ -113,15 +125,20 @@ pub(crate) struct Config {
     pub(crate) db_location: String,
     pub(crate) cache_control_header: Option<String>,
     pub(crate) disable_frontend: bool,
+    pub(crate) api_docs: bool,
     pub(crate) site_url: Option<String>,
+    pub(crate) extra_site_urls: Vec<String>,
     pub(crate) public_mode: bool,
     pub(crate) public_mode_expiry_delay: Option<i64>,
     pub(crate) use_temp_redirect: bool,
+    pub(crate) always_preview: bool,
     pub(crate) allowed_protocols: Vec<String>,
     pub(crate) password: Option<String>,
     pub(crate) hash_algorithm: HashAlgorithm,
     pub(crate) api_key: Option<String>,
     pub(crate) slug_style: SlugStyle,
+    // Set when links get sequential slugs, random ones are then only used for suggestions
+    pub(crate) sequential_slugs: Option<Sqids>,
     pub(crate) slug_length: usize,
     pub(crate) try_longer_slug: bool,
     pub(crate) allow_capital_letters: bool,
@@ -129,6 +146,179 @@ pub(crate) struct Config {
     pub(crate) use_wal_mode: bool,
     pub(crate) ensure_acid: bool,
     pub(crate) frontend_page_size: u16,
+    pub(crate) geoip_database: Option<String>,
+    pub(crate) trusted_proxies: Vec<IpAddr>,
+    pub(crate) health_check_interval: Option<u64>,
+    pub(crate) health_check_concurrency: usize,
+    pub(crate) health_check_rate: u32,
+    pub(crate) health_failure_threshold: i64,
+    pub(crate) health_check_allow_private: bool,
+    pub(crate) blocklist: Option<String>,
+    pub(crate) allowlist: Option<String>,
+    pub(crate) public_blocklist: Option<String>,
+    pub(crate) public_allowlist: Option<String>,
+    pub(crate) disable_blocked_links: bool,
+    pub(crate) disable_idle_links: bool,
+    pub(crate) expiry_grace_period: i64,
+    pub(crate) dedupe_links: bool,
+    pub(crate) idempotency_window: i64,
+    pub(crate) cleanup_schedule: Option<Schedule>,
+    pub(crate) backup_schedule: Option<Schedule>,
+    pub(crate) vacuum_schedule: Option<Schedule>,
+    pub(crate) optimize_schedule: Option<Schedule>,
+    pub(crate) backup_settings: BackupSettings,
+    pub(crate) reserved_slugs: ReservedSlugs,
+    pub(crate) case_insensitive_slugs: bool,
+}
+
+// Slugs that can't be used, either because they collide with a route or because they were denied
+#[derive(Clone)]
+pub(crate) struct ReservedSlugs {
+    routes: HashSet<String>,
+    denied: HashSet<String>,
+}
+
+impl ReservedSlugs {
+    // Paths served by the server itself, with the ones from the landing directory when it's used
+    pub(crate) fn new(landing_directory: Option<&str>, denylist: Option<&str>) -> Self {
+        let mut routes: HashSet<String> = ["api", "preview", "static", "assets"]
+            .map(String::from)
+            .into();
+        if let Some(dir) = landing_directory {
+            routes.insert(String::from("admin"));
+            routes.extend(
+                fs::read_dir(dir)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Result::ok)
+                    .filter_map(|e| e.file_name().into_string().ok())
+                    .map(|name| name.to_lowercase()),
+            );
+        }
+        let denied = denylist
+            .map(|path| {
+                fs::read_to_string(path)
+                    .unwrap_or_else(|e| panic!("Unable to read the slug denylist {path}: {e}"))
+                    .lines()
+                    .map(|l| l.trim().to_lowercase())
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                    .collect::<HashSet<_>>()
+            })
+            .unwrap_or_default();
+        if !denied.is_empty() {
+            info!("Loaded {} denied words for slugs.", denied.len());
+        }
+        ReservedSlugs { routes, denied }
+    }
+
+    // Routes have to match the whole slug, while denied words also match any part of it
+    pub(crate) fn is_reserved(&self, slug: &str) -> bool {
+        let slug = slug.to_lowercase();
+        self.routes.contains(&slug)
+            || self.denied.contains(&slug)
+            || slug
+                .split(['-', '_'])
+                .any(|part| self.denied.contains(part))
+    }
+}
+
+// Read the comma separated slug templates, and the word lists given as name=path
+fn read_slug_templates() -> Result<Vec<SlugTemplate>, String> {
+    let split = |name: &str| {
+        var(name)
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_owned())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+    };
+    let files = split("CHHOTO_SLUG_WORDLISTS")
+        .into_iter()
+        .map(|entry| match entry.split_once('=') {
+            Some((name, path)) => Ok((name.trim().to_owned(), path.trim().to_owned())),
+            None => Err(format!(
+                "Invalid word list {entry}, it should be name=path."
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let lists = slugs::load_wordlists(&files)?;
+    let templates = split("CHHOTO_SLUG_TEMPLATE")
+        .iter()
+        .map(|t| SlugTemplate::parse(t, &lists))
+        .collect::<Result<Vec<_>, _>>()?;
+    if templates.is_empty() {
+        return Err(String::from("CHHOTO_SLUG_TEMPLATE is not set."));
+    }
+    Ok(templates)
+}
+
+// Get the domain, i.e. host and non-default port, of a site URL
+pub(crate) fn domain_of(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?.to_lowercase();
+    Some(match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host,
+    })
+}
+
+impl Config {
+    // The maintenance jobs, along with their schedules if they're enabled
+    pub(crate) fn schedules(&self) -> [(Job, Option<&Schedule>); 4] {
+        [
+            (Job::Cleanup, self.cleanup_schedule.as_ref()),
+            (Job::Backup, self.backup_schedule.as_ref()),
+            (Job::Vacuum, self.vacuum_schedule.as_ref()),
+            (Job::Optimize, self.optimize_schedule.as_ref()),
+        ]
+    }
+
+    // Custom slugs may have capital letters if they are allowed, or if the case is ignored anyway
+    pub(crate) fn allow_mixed_case(&self) -> bool {
+        self.allow_capital_letters || self.case_insensitive_slugs
+    }
+
+    // Get the public URL for a domain, the default domain being represented by an empty string
+    pub(crate) fn site_url_for(&self, domain: &str) -> String {
+        if let Some(url) = self
+            .extra_site_urls
+            .iter()
+            .find(|u| !domain.is_empty() && domain_of(u).as_deref() == Some(domain))
+        {
+            return url.to_owned();
+        }
+        if let Some(url) = &self.site_url {
+            url.to_owned()
+        } else {
+            let protocol = if self.port == 443 { "https" } else { "http" };
+            let port_text = if [80, 443].contains(&self.port) {
+                String::new()
+            } else {
+                format!(":{}", self.port)
+            };
+            format!("{protocol}://localhost{port_text}")
+        }
+    }
+
+    // Map a requested domain (or Host header) to the stored domain
+    // Returns None if the domain is not served by this instance
+    pub(crate) fn normalize_domain(&self, domain: &str) -> Option<String> {
+        let domain = domain.trim().to_lowercase();
+        if domain.is_empty()
+            || self
+                .site_url
+                .as_deref()
+                .and_then(domain_of)
+                .is_some_and(|d| d == domain)
+        {
+            Some(String::new())
+        } else {
+            self.extra_site_urls
+                .iter()
+                .filter_map(|u| domain_of(u))
+                .find(|d| *d == domain)
+        }
+    }
 }
 
 pub(crate) fn read() -> Config {
@@ -162,6 +352,10 @@ pub(crate) fn read() -> Config {
     if disable_frontend {
         info!("Frontend is disabled.")
     };
+    let api_docs = var("CHHOTO_API_DOCS").is_ok_and(|s| s.trim() == "True");
+    if api_docs {
+        info!("Serving the API documentation at /api/docs.");
+    }
 
     // If an API key is set, check the security
     let api_key = read_config_wrapper("CHHOTO_API_KEY", "api_key").ok();
@@ -202,6 +396,10 @@ pub(crate) fn read() -> Config {
     } else {
         info!("Using Permanent redirection (default).")
     }
+    let always_preview = var("CHHOTO_ALWAYS_PREVIEW").is_ok_and(|s| s.trim() == "True");
+    if always_preview {
+        info!("A preview page will be shown before every redirection.");
+    }
 
     let mut allowed_protocols: Vec<String> = ["http", "https", "ftp", "magnet"]
         .iter()
@@ -273,27 +471,21 @@ pub(crate) fn read() -> Config {
         None
     };
 
-    let slug_length = read_config_wrapper("CHHOTO_SLUG_LENGTH", "slug_length")
-        .ok()
-        .and_then(|s| s.parse::<usize>().ok())
-        .filter(|&s| s >= 4)
-        .unwrap_or(8);
-    let try_longer_slug = read_config_wrapper("CHHOTO_TRY_LONGER_SLUG", "try_longer_slug")
-        .is_ok_and(|s| s.trim() == "True");
-    let slug_style =
-        match read_config_wrapper("CHHOTO_SLUG_STYLE", "slug_style").map(|s| s.trim().to_owned()) {
-            Ok(style) if style == "UID" => {
-                info!("Using UID slugs with length {slug_length}.");
-                if try_longer_slug {
-                    info!("Will retry with a longer slug upon collision.");
-                }
-                SlugStyle::Uid
-            }
-            _ => {
-                info!("Using adjective-noun pair slugs.");
-                SlugStyle::Pair
+    // Additional site URLs, each of which is served as a separate short domain
+    let mut extra_site_urls: Vec<String> = Vec::new();
+    if let Ok(urls) = var("CHHOTO_EXTRA_SITE_URLS") {
+        for url in urls.split(&[',', ' ']).filter(|u| !u.is_empty()) {
+            let url = url.trim_end_matches('/');
+            if domain_of(url).is_some() {
+                extra_site_urls.push(url.to_owned());
+            } else {
+                warn!("Skipping malformed site URL: {url}.")
             }
-        };
+        }
+    }
+    if !extra_site_urls.is_empty() {
+        info!("Extra site URLs: {:?}", extra_site_urls);
+    }
 
     let allow_capital_letters =
         read_config_wrapper("CHHOTO_ALLOW_CAPITAL_LETTERS", "allow_capital_letters")
@@ -304,6 +496,70 @@ pub(crate) fn read() -> Config {
         info!("Capital letters won't be allowed in links.");
     }
 
+    let case_insensitive_slugs =
+        var("CHHOTO_CASE_INSENSITIVE_SLUGS").is_ok_and(|s| s.trim() == "True");
+
+    let slug_length = read_config_wrapper("CHHOTO_SLUG_LENGTH", "slug_length")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .filter(|&s| s >= 4)
+        .unwrap_or(8);
+    let try_longer_slug = read_config_wrapper("CHHOTO_TRY_LONGER_SLUG", "try_longer_slug")
+        .is_ok_and(|s| s.trim() == "True");
+    let (slug_style, sequential_slugs) = match read_config_wrapper(
+        "CHHOTO_SLUG_STYLE",
+        "slug_style",
+    )
+    .map(|s| s.trim().to_owned())
+    {
+        Ok(style) if style == "UID" => {
+            info!("Using UID slugs with length {slug_length}.");
+            if try_longer_slug {
+                info!("Will retry with a longer slug upon collision.");
+            }
+            (SlugStyle::Uid, None)
+        }
+        Ok(style) if style == "Sequential" => match var("CHHOTO_SLUG_ALPHABET")
+            .map_err(|_| String::from("CHHOTO_SLUG_ALPHABET is required for sequential slugs."))
+            .and_then(|a| slugs::sequential_encoder(&a, slug_length, allow_capital_letters))
+        {
+            Ok(sqids) => {
+                info!("Using sequential slugs with a minimum length of {slug_length}.");
+                (SlugStyle::Uid, Some(sqids))
+            }
+            Err(e) => {
+                warn!("{e} Using adjective-noun pair slugs instead.");
+                (SlugStyle::Pair, None)
+            }
+        },
+        Ok(style) if style == "Template" => match read_slug_templates() {
+            Ok(templates) => {
+                for t in &templates {
+                    let space = t.collision_space();
+                    info!(
+                        "Slug template {} can generate {space} different slugs.",
+                        t.pattern
+                    );
+                    if space < 1_000_000 {
+                        warn!(
+                            "Slug template {} is small, collisions will become frequent as links are added.",
+                            t.pattern
+                        );
+                    }
+                }
+                (SlugStyle::Template(templates), None)
+            }
+            Err(e) => {
+                warn!("{e} Using adjective-noun pair slugs instead.");
+                (SlugStyle::Pair, None)
+            }
+        },
+        _ => {
+            info!("Using adjective-noun pair slugs.");
+            (SlugStyle::Pair, None)
+        }
+    };
+
     let use_wal_mode = read_config_wrapper("CHHOTO_SQLITE_USE_WAL_MODE", "use_wal_mode")
         .is_ok_and(|s| s.trim() == "True");
     if use_wal_mode {
@@ -340,21 +596,149 @@ pub(crate) fn read() -> Config {
         .inspect(|s| info!("Frontend page size is set to {s}."))
         .unwrap_or(10);
 
+    let geoip_database = var("CHHOTO_GEOIP_DATABASE")
+        .ok()
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty())
+        .inspect(|s| info!("GeoIP database location is set to {s}."));
+    let mut trusted_proxies = Vec::new();
+    if let Ok(proxies) = var("CHHOTO_TRUSTED_PROXIES") {
+        for proxy in proxies.split(&[',', ' ']).filter(|p| !p.is_empty()) {
+            match proxy.parse::<IpAddr>() {
+                Ok(ip) => trusted_proxies.push(ip),
+                Err(_) => warn!("Skipping malformed proxy address: {proxy}."),
+            }
+        }
+    }
+    if !trusted_proxies.is_empty() {
+        info!(
+            "Client addresses are taken from X-Forwarded-For for requests from {trusted_proxies:?}."
+        );
+    }
+
+    let health_check_interval = var("CHHOTO_HEALTH_CHECK_INTERVAL")
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .filter(|&m| m >= 1)
+        .inspect(|m| info!("Destination health checks will run every {m} minutes."));
+    let health_check_concurrency = var("CHHOTO_HEALTH_CHECK_CONCURRENCY")
+        .ok()
+        .and_then(|s| s.trim().parse::<usize>().ok())
+        .filter(|&c| c >= 1)
+        .unwrap_or(4);
+    let health_check_rate = var("CHHOTO_HEALTH_CHECK_RATE")
+        .ok()
+        .and_then(|s| s.trim().parse::<u32>().ok())
+        .filter(|&r| r >= 1)
+        .unwrap_or(2);
+    let health_failure_threshold = var("CHHOTO_HEALTH_FAILURE_THRESHOLD")
+        .ok()
+        .and_then(|s| s.trim().parse::<i64>().ok())
+        .filter(|&t| t >= 1)
+        .unwrap_or(3);
+    let health_check_allow_private =
+        var("CHHOTO_HEALTH_CHECK_ALLOW_PRIVATE").is_ok_and(|s| s.trim() == "True");
+    if health_check_allow_private {
+        info!("Health checks are allowed to reach private and local addresses.");
+    }
+    let read_path = |name: &str| {
+        var(name)
+            .ok()
+            .map(|s| s.trim().to_owned())
+            .filter(|s| !s.is_empty())
+    };
+    let blocklist = read_path("CHHOTO_BLOCKLIST");
+    let allowlist = read_path("CHHOTO_ALLOWLIST");
+    let public_blocklist = read_path("CHHOTO_PUBLIC_BLOCKLIST");
+    let public_allowlist = read_path("CHHOTO_PUBLIC_ALLOWLIST");
+    let disable_blocked_links =
+        var("CHHOTO_DISABLE_BLOCKED_LINKS").is_ok_and(|s| s.trim() == "True");
+    if disable_blocked_links {
+        info!("Existing links matching the blocklist will be disabled.");
+    }
+    let disable_idle_links = var("CHHOTO_DISABLE_IDLE_LINKS").is_ok_and(|s| s.trim() == "True");
+    if disable_idle_links {
+        info!("Idle links will be disabled instead of being deleted.");
+    }
+
+    let expiry_grace_period = var("CHHOTO_EXPIRY_GRACE_PERIOD")
+        .ok()
+        .and_then(|s| s.trim().parse::<i64>().ok())
+        .map(|p| p.clamp(0, 157_784_760))
+        .unwrap_or(0);
+    if expiry_grace_period > 0 {
+        info!("Expired links will be kept for {expiry_grace_period} seconds before being deleted.");
+    }
+
+    let dedupe_links = var("CHHOTO_DEDUPE_LINKS").is_ok_and(|s| s.trim() == "True");
+    if dedupe_links {
+        info!("Existing links will be reused for duplicate destinations by default.");
+    }
+
+    let idempotency_window = var("CHHOTO_IDEMPOTENCY_WINDOW")
+        .ok()
+        .and_then(|s| s.trim().parse::<i64>().ok())
+        .filter(|&w| w >= 0)
+        .unwrap_or(86400);
+    if idempotency_window == 0 {
+        info!("Idempotency keys are disabled.");
+    }
+
+    let cleanup_schedule = read_schedule("CHHOTO_CLEANUP_SCHEDULE", Some("0 * * * *"));
+    let backup_schedule = read_schedule("CHHOTO_BACKUP_SCHEDULE", Some("0 3 * * *"));
+    let vacuum_schedule = read_schedule("CHHOTO_VACUUM_SCHEDULE", None);
+    let optimize_schedule = read_schedule("CHHOTO_OPTIMIZE_SCHEDULE", Some("0 * * * *"));
+    let read_count = |name: &str, default: usize| {
+        var(name)
+            .ok()
+            .and_then(|s| s.trim().parse::<usize>().ok())
+            .unwrap_or(default)
+    };
+    let backup_settings = BackupSettings {
+        init: read_count("CHHOTO_INIT_BACKUPS", 3),
+        daily: read_count("CHHOTO_DAILY_BACKUPS", 7),
+        weekly: read_count("CHHOTO_WEEKLY_BACKUPS", 0),
+        monthly: read_count("CHHOTO_MONTHLY_BACKUPS", 0),
+        compress: var("CHHOTO_COMPRESS_BACKUPS").is_ok_and(|s| s.trim() == "True"),
+    };
+    if backup_settings.compress {
+        info!("Backups will be compressed using gzip.");
+    }
+
+    let slug_denylist = var("CHHOTO_SLUG_DENYLIST")
+        .ok()
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty());
+    let reserved_slugs = ReservedSlugs::new(
+        custom_landing_directory.as_deref(),
+        slug_denylist.as_deref(),
+    );
+
+    if health_check_interval.is_some() {
+        info!(
+            "Health checks use {health_check_concurrency} connections, at most {health_check_rate} requests per second, and mark links as broken after {health_failure_threshold} failures."
+        );
+    }
+
     Config {
         listen_address,
         port,
         db_location,
         cache_control_header,
         disable_frontend,
+        api_docs,
         site_url,
+        extra_site_urls,
         public_mode,
         public_mode_expiry_delay,
         use_temp_redirect,
+        always_preview,
         allowed_protocols,
         password,
         hash_algorithm,
         api_key,
         slug_style,
+        sequential_slugs,
         slug_length,
         try_longer_slug,
         allow_capital_letters,
@@ -362,5 +746,48 @@ pub(crate) fn read() -> Config {
         use_wal_mode,
         ensure_acid,
         frontend_page_size,
+        geoip_database,
+        trusted_proxies,
+        health_check_interval,
+        health_check_concurrency,
+        health_check_rate,
+        health_failure_threshold,
+        health_check_allow_private,
+        blocklist,
+        allowlist,
+        public_blocklist,
+        public_allowlist,
+        disable_blocked_links,
+        disable_idle_links,
+        expiry_grace_period,
+        dedupe_links,
+        idempotency_window,
+        cleanup_schedule,
+        backup_schedule,
+        vacuum_schedule,
+        optimize_schedule,
+        backup_settings,
+        reserved_slugs,
+        case_insensitive_slugs,
+    }
+}
+
+// Read the schedule of a maintenance job, which can be turned off using "off"
+fn read_schedule(name: &str, default: Option<&str>) -> Option<Schedule> {
+    let value = var(name)
+        .ok()
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty());
+    let expression = match value.as_deref() {
+        Some("off") => return None,
+        Some(expression) => expression,
+        None => default?,
+    };
+    match schedule::parse(expression) {
+        Ok(schedule) => Some(schedule),
+        Err(e) => {
+            warn!("{e} Using the default schedule instead.");
+            default.and_then(|d| schedule::parse(d).ok())
+        }
     }
 }
@@ -0,0 +1,401 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+use chrono::{DateTime, Datelike, Local, Utc};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use log::{error, info, warn};
+use nanoid::nanoid;
+use rusqlite::{
+    Connection, OpenFlags,
+    backup::{Backup, Progress},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::database::utils::{APPLICATION_ID, USER_VERSION};
+
+// Enum for backup types
+#[derive(Clone, Copy)]
+enum BackupType {
+    Init,
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+// The number of backups of each type that are kept, and whether they're compressed
+#[derive(Clone)]
+pub(crate) struct BackupSettings {
+    pub(crate) init: usize,
+    pub(crate) daily: usize,
+    pub(crate) weekly: usize,
+    pub(crate) monthly: usize,
+    pub(crate) compress: bool,
+}
+
+// Details about a backup, stored next to it with a .json suffix
+#[derive(Serialize, Deserialize)]
+pub(crate) struct BackupManifest {
+    pub(crate) sha256: String,
+    pub(crate) compressed: bool,
+    pub(crate) links: i64,
+    pub(crate) user_version: u32,
+    pub(crate) created_at: i64,
+}
+
+// A backup may be a plain SQLite file, or a compressed one, along with its manifest
+const BACKUP_FILE_SUFFIXES: [&str; 3] = ["", ".gz", ".json"];
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    path.into()
+}
+
+// Backups are kept in a directory next to the database
+pub(crate) fn backup_dir(db_path: &Path) -> PathBuf {
+    db_path
+        .parent()
+        .expect("Database should have a parent directory.")
+        .join("backups")
+}
+
+// Create a backup when the server starts
+pub(super) fn init_backup(db: &Connection, settings: &BackupSettings) {
+    manage_backups(db, &[(BackupType::Init, settings.init)], settings.compress);
+}
+
+// Create a daily backup, along with weekly and monthly ones if they're due
+pub(crate) fn backup(db: &Connection, settings: &BackupSettings) -> bool {
+    manage_backups(
+        db,
+        &[
+            (BackupType::Daily, settings.daily),
+            (BackupType::Weekly, settings.weekly),
+            (BackupType::Monthly, settings.monthly),
+        ],
+        settings.compress,
+    )
+}
+
+// The whole database is copied in a single step, so that the source is never locked for long
+fn take_snapshot(db: &Connection, path: &Path) -> Result<(), String> {
+    let mut dest =
+        Connection::open(path).map_err(|e| format!("Unable to create the backup file: {e}"))?;
+    Backup::new(db, &mut dest)
+        .and_then(|backup| backup.run_to_completion(i32::MAX, Duration::ZERO, None))
+        .map_err(|e| format!("There was an error while creating the backup: {e}"))
+}
+
+// Snapshots are kept in rollback journal mode, so that they're a single file
+fn prepare_snapshot(path: &Path) -> Result<(), String> {
+    Connection::open(path)
+        .and_then(|db| db.pragma_update(None, "journal_mode", "DELETE"))
+        .map_err(|e| format!("Unable to prepare the backup: {e}"))
+}
+
+fn gzip(source: &Path, dest: &Path) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(File::create(dest)?, Compression::default());
+    io::copy(&mut File::open(source)?, &mut encoder)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+fn sha256(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// Run the integrity check on a database file, and return its link count and user_version
+fn verify(path: &Path) -> Result<(i64, u32), String> {
+    let db = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Unable to open the database: {e}"))?;
+    let check: String = db
+        .query_one("PRAGMA integrity_check", (), |row| row.get(0))
+        .map_err(|e| format!("Unable to check the database: {e}"))?;
+    if check != "ok" {
+        return Err(format!("The database failed the integrity check: {check}"));
+    }
+    let user_version = db
+        .query_one("SELECT user_version FROM pragma_user_version", (), |row| {
+            row.get(0)
+        })
+        .map_err(|e| format!("Unable to read the user_version: {e}"))?;
+    // A new database doesn't have any tables yet
+    let links = db
+        .query_one("SELECT COUNT(id) FROM urls", (), |row| row.get(0))
+        .unwrap_or_default();
+    Ok((links, user_version))
+}
+
+// Create backups, and rotate the older ones
+fn manage_backups(db: &Connection, backups: &[(BackupType, usize)], compress: bool) -> bool {
+    let path = db.path().expect("The database path should exist.");
+
+    let db_path = PathBuf::from(path);
+
+    let parent = db_path
+        .parent()
+        .expect("Database should have a parent directory.");
+
+    let db_name = db_path
+        .file_name()
+        .expect("Database should have a file name.")
+        .to_string_lossy();
+
+    let backup_dir = backup_dir(&db_path);
+
+    if let Err(e) = fs::create_dir_all(&backup_dir) {
+        error!("Failed to create backup directory: {e}");
+        return false;
+    }
+
+    // Migrate legacy backups
+    if backups.iter().any(|(t, _)| matches!(t, BackupType::Daily)) {
+        for path in fs::read_dir(parent).into_iter().flatten().flatten() {
+            let path = path.path();
+            if path
+                .file_name()
+                .and_then(|f| f.to_str())
+                .is_some_and(|f| f.starts_with(&format!("{db_name}.bak")))
+            {
+                let filename = path.file_name().unwrap().to_string_lossy();
+                let daily_name = filename.replacen(".bak", ".daily", 1);
+                if let Err(e) = fs::rename(&path, backup_dir.join(daily_name)) {
+                    error!("There was an error migrating legacy backup: {e}");
+                }
+            }
+        }
+    }
+
+    // A single verified snapshot is copied into each type of backup that is due
+    let snapshot = backup_dir.join(format!("{db_name}.tmp"));
+    let stored = if compress {
+        with_suffix(&snapshot, ".gz")
+    } else {
+        snapshot.clone()
+    };
+    let manifest = take_snapshot(db, &snapshot)
+        .and_then(|()| prepare_snapshot(&snapshot))
+        .and_then(|()| verify(&snapshot))
+        .and_then(|(links, user_version)| {
+            let write = || -> io::Result<String> {
+                if compress {
+                    gzip(&snapshot, &stored)?;
+                }
+                sha256(&stored)
+            };
+            Ok(BackupManifest {
+                sha256: write().map_err(|e| format!("Unable to write the backup: {e}"))?,
+                compressed: compress,
+                links,
+                user_version,
+                created_at: Utc::now().timestamp(),
+            })
+        });
+    let manifest = match manifest {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            error!("The backup was discarded. {e}");
+            for path in [&snapshot, &stored] {
+                let _ = fs::remove_file(path);
+            }
+            return false;
+        }
+    };
+
+    let now = Local::now();
+    let mut success = true;
+    for &(backup_type, retain) in backups {
+        let suffix = match backup_type {
+            BackupType::Init => "init",
+            BackupType::Daily => "daily",
+            BackupType::Weekly => "weekly",
+            BackupType::Monthly => "monthly",
+        };
+        let backup_path = |idx: usize| backup_dir.join(format!("{db_name}.{suffix}{idx}"));
+        let remove_backup = |idx: usize| {
+            BACKUP_FILE_SUFFIXES
+                .iter()
+                .filter(|s| fs::remove_file(with_suffix(&backup_path(idx), s)).is_ok())
+                .count()
+                > 0
+        };
+
+        // Weekly and monthly backups are only made once per week or month
+        let latest = fs::metadata(with_suffix(&backup_path(1), ".json"))
+            .or_else(|_| fs::metadata(backup_path(1)))
+            .and_then(|m| m.modified())
+            .ok()
+            .map(DateTime::<Local>::from);
+        let due = match backup_type {
+            BackupType::Init | BackupType::Daily => true,
+            BackupType::Weekly => latest.is_none_or(|t| t.iso_week() != now.iso_week()),
+            BackupType::Monthly => {
+                latest.is_none_or(|t| (t.year(), t.month()) != (now.year(), now.month()))
+            }
+        };
+        if retain == 0 || !due {
+            continue;
+        }
+        info!("Creating a {suffix} backup of the database.");
+
+        remove_backup(0);
+        let extension = if compress { ".gz" } else { "" };
+        if let Err(e) = fs::copy(&stored, with_suffix(&backup_path(0), extension)).and_then(|_| {
+            let json = serde_json::to_string_pretty(&manifest)?;
+            fs::write(with_suffix(&backup_path(0), ".json"), json)
+        }) {
+            error!("There was an error while creating the {suffix} backup: {e}");
+            remove_backup(0);
+            success = false;
+            continue;
+        }
+
+        for i in (0..retain).rev() {
+            remove_backup(i + 1);
+            for s in BACKUP_FILE_SUFFIXES {
+                if let Err(e) = fs::rename(
+                    with_suffix(&backup_path(i), s),
+                    with_suffix(&backup_path(i + 1), s),
+                ) && e.kind() != io::ErrorKind::NotFound
+                {
+                    error!("Failed to rotate backup {i}: {e}");
+                }
+            }
+        }
+        // Backups beyond the retention count are left over when it's lowered
+        for i in retain + 1.. {
+            if !remove_backup(i) {
+                break;
+            }
+        }
+    }
+
+    for path in [&snapshot, &stored] {
+        if let Err(e) = fs::remove_file(path)
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            warn!("Unable to remove the temporary backup: {e}");
+        }
+    }
+    success
+}
+
+// Replace the contents of the database with those of a backup, after checking it
+// The current database is kept as an init backup, and older schema versions are only allowed if they'll be migrated
+pub(crate) fn restore(
+    db: &mut Connection,
+    backup: &Path,
+    settings: &BackupSettings,
+    allow_older: bool,
+) -> Result<i64, String> {
+    let manifest_path = match backup.to_str().and_then(|p| p.strip_suffix(".gz")) {
+        Some(path) => with_suffix(Path::new(path), ".json"),
+        None => with_suffix(backup, ".json"),
+    };
+    if let Ok(manifest) = fs::read_to_string(&manifest_path) {
+        let manifest: BackupManifest = serde_json::from_str(&manifest)
+            .map_err(|e| format!("The manifest of the backup is invalid: {e}"))?;
+        let checksum = sha256(backup).map_err(|e| format!("Unable to read the backup: {e}"))?;
+        if checksum != manifest.sha256 {
+            return Err("The backup doesn't match the checksum in its manifest.".to_owned());
+        }
+    }
+
+    // Compressed backups are extracted next to the database first
+    let mut magic = [0; 2];
+    File::open(backup)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .map_err(|e| format!("Unable to read the backup: {e}"))?;
+    let db_path = PathBuf::from(db.path().expect("The database path should exist."));
+    let extracted = with_suffix(&db_path, ".restore");
+    let source = if magic == [0x1f, 0x8b] {
+        File::open(backup)
+            .and_then(|f| io::copy(&mut GzDecoder::new(f), &mut File::create(&extracted)?))
+            .map_err(|e| format!("Unable to extract the backup: {e}"))?;
+        extracted.as_path()
+    } else {
+        backup
+    };
+
+    let result = check_restorable(source, allow_older).and_then(|links| {
+        init_backup(db, settings);
+        db.restore("main", source, None::<fn(Progress)>)
+            .map_err(|e| format!("Unable to restore the backup: {e}"))?;
+        Ok(links)
+    });
+    if source == extracted.as_path() {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(with_suffix(&extracted, suffix));
+        }
+    }
+    result
+}
+
+fn check_restorable(path: &Path, allow_older: bool) -> Result<i64, String> {
+    let db = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Unable to open the backup: {e}"))?;
+    let application_id: i32 = db
+        .query_one(
+            "SELECT application_id FROM pragma_application_id",
+            (),
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Unable to read the application_id: {e}"))?;
+    if application_id != APPLICATION_ID {
+        return Err("The backup doesn't belong to Chhoto URL.".to_owned());
+    }
+    let (links, user_version) = verify(path)?;
+    if user_version > USER_VERSION || (user_version < USER_VERSION && !allow_older) {
+        return Err(format!(
+            "The backup has schema version {user_version}, but version {USER_VERSION} is needed."
+        ));
+    }
+    Ok(links)
+}
+
+// Take a snapshot of the database for downloading, into a temporary file in the backups directory
+// The rest is done by open_snapshot
+pub(crate) fn snapshot_for_download(db: &Connection) -> Result<PathBuf, String> {
+    let db_path = PathBuf::from(db.path().expect("The database path should exist."));
+    let backup_dir = backup_dir(&db_path);
+    fs::create_dir_all(&backup_dir)
+        .map_err(|e| format!("Failed to create backup directory: {e}"))?;
+    let db_name = db_path
+        .file_name()
+        .expect("Database should have a file name.")
+        .to_string_lossy();
+    let path = backup_dir.join(format!("{db_name}.download-{}", nanoid!(8)));
+    take_snapshot(db, &path).inspect_err(|_| {
+        let _ = fs::remove_file(&path);
+    })?;
+    Ok(path)
+}
+
+// Open the snapshot, after compressing it if needed
+// The temporary files are removed right away, but stay readable until the returned file is closed
+pub(crate) fn open_snapshot(path: &Path, compress: bool) -> Result<File, String> {
+    let compressed = with_suffix(path, ".gz");
+    let result = prepare_snapshot(path).and_then(|()| {
+        let stored = if compress {
+            gzip(path, &compressed).map_err(|e| format!("Unable to compress the backup: {e}"))?;
+            &compressed
+        } else {
+            path
+        };
+        File::open(stored).map_err(|e| format!("Unable to read the backup: {e}"))
+    });
+    for path in [path, &compressed] {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
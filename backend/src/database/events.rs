@@ -2,29 +2,84 @@
 // SPDX-License-Identifier: MIT
 
 use log::{debug, error, warn};
-use rusqlite::{Connection, fallible_iterator::FallibleIterator, named_params};
+use rusqlite::{
+    Connection, OptionalExtension, ToSql, fallible_iterator::FallibleIterator, named_params,
+};
 use serde::Serialize;
 use std::{collections::HashMap, rc::Rc};
 use tokio::sync::mpsc;
+use utoipa::ToSchema;
 
 use crate::{
+    background::HitUpdate,
     database::queries,
-    services::types::ChhotoError::{self, ClientError, ServerError},
-    utils::NewURLRequest,
+    preview::OpenGraph,
+    redirect::{self, RedirectRule, WeightedTarget},
+    search::Search,
+    services::types::{
+        ChhotoError::{self, Conflict, NotFound, ServerError},
+        NewURLRequest, SortBy,
+    },
 };
 
 // Struct for encoding a DB row
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub(crate) struct DBRow {
-    shortlink: String,
+    pub(crate) shortlink: String,
+    pub(crate) domain: String,
     pub(crate) longlink: String,
     pub(crate) hits: i64,
     pub(crate) expiry_time: i64,
     pub(crate) notes: String,
+    pub(crate) rules: Vec<RedirectRule>,
+    pub(crate) targets: Vec<WeightedTarget>,
+    pub(crate) sticky: bool,
+    pub(crate) created_at: i64,
+    pub(crate) updated_at: i64,
+    pub(crate) last_accessed_at: i64,
+    pub(crate) idle_ttl: i64,
+    pub(crate) preview: bool,
+    pub(crate) opengraph: OpenGraph,
+    pub(crate) health_status: Option<i64>,
+    pub(crate) health_checked_at: i64,
+    pub(crate) health_failures: i64,
+    pub(crate) disabled: bool,
+    pub(crate) aliases: Vec<String>,
+    // The matching part of the link, when ordering search results by relevance
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) snippet: Option<String>,
+}
+
+// Struct for a link resolved for redirection
+pub(crate) struct FoundLink {
+    pub(crate) id: i64,
+    pub(crate) long_url: String,
+    pub(crate) expiry_time: i64,
+    pub(crate) notes: String,
+    pub(crate) rules: Vec<RedirectRule>,
+    pub(crate) targets: Vec<WeightedTarget>,
+    pub(crate) sticky: bool,
+    pub(crate) created_at: i64,
+    pub(crate) preview: bool,
+    pub(crate) opengraph: OpenGraph,
+}
+
+// The key used for looking up a slug, which ignores the case when case-insensitive slugs are enabled
+pub(crate) fn slug_key(shortlink: &str, nocase: bool) -> String {
+    if nocase {
+        shortlink.to_ascii_lowercase()
+    } else {
+        shortlink.to_owned()
+    }
 }
 
 // Find a single URL for /api/expand
-pub(crate) fn find_url(shortlink: &str, db: &Connection) -> Result<DBRow, ChhotoError> {
+pub(crate) fn find_url(
+    shortlink: &str,
+    domain: &str,
+    nocase: bool,
+    db: &Connection,
+) -> Result<DBRow, ChhotoError> {
     // Long link, hits, expiry time
     let now = chrono::Utc::now().timestamp();
     let Ok(mut statement) = db.prepare_cached(queries::FIND_URL) else {
@@ -32,83 +87,181 @@ pub(crate) fn find_url(shortlink: &str, db: &Connection) -> Result<DBRow, Chhoto
         return Err(ServerError);
     };
     statement
-        .query_row(named_params! {":short": shortlink, ":now": now}, |row| {
-            Ok(DBRow {
-                shortlink: String::new(),
-                longlink: row.get("long_url")?,
-                hits: row.get("hits")?,
-                expiry_time: row.get("expiry_time").unwrap_or_default(),
-                notes: row.get("notes").unwrap_or_default(),
-            })
-        })
+        .query_row(
+            named_params! {":key": slug_key(shortlink, nocase), ":domain": domain, ":now": now},
+            |row| {
+                Ok(DBRow {
+                    shortlink: row.get("short_url")?,
+                    domain: domain.to_owned(),
+                    longlink: row.get("long_url")?,
+                    hits: row.get("hits")?,
+                    expiry_time: row.get("expiry_time").unwrap_or_default(),
+                    notes: row.get("notes").unwrap_or_default(),
+                    rules: redirect::parse_json(row.get("rules")?),
+                    targets: redirect::parse_json(row.get("targets")?),
+                    sticky: row.get("sticky")?,
+                    created_at: row.get("created_at").unwrap_or_default(),
+                    updated_at: row.get("updated_at").unwrap_or_default(),
+                    last_accessed_at: row.get("last_accessed_at").unwrap_or_default(),
+                    idle_ttl: row.get("idle_ttl").unwrap_or_default(),
+                    preview: row.get("preview")?,
+                    opengraph: redirect::parse_json(row.get("opengraph")?),
+                    health_status: row.get("health_status")?,
+                    health_checked_at: row.get("health_checked_at").unwrap_or_default(),
+                    health_failures: row.get("health_failures")?,
+                    disabled: row.get("disabled")?,
+                    aliases: redirect::parse_json(row.get("aliases")?),
+                    snippet: None,
+                })
+            },
+        )
         .inspect(|_| {
             debug!("Expanded link: {shortlink}.");
         })
-        .map_err(|_| ChhotoError::ClientError {
+        .map_err(|_| ChhotoError::NotFound {
             reason: "The shortlink does not exist on the server!".to_owned(),
         })
 }
 
+// Bounds on the timestamps of the links listed by getall, the lower bounds are inclusive
+#[derive(Default)]
+pub(crate) struct TimeFilters {
+    pub(crate) created: (Option<i64>, Option<i64>),
+    pub(crate) updated: (Option<i64>, Option<i64>),
+    pub(crate) accessed: (Option<i64>, Option<i64>),
+    // Links that expired less than this many seconds ago are also listed
+    pub(crate) expired_within: Option<i64>,
+}
+
+// Orders in which getall can list the links, besides the default one
+pub(crate) enum LinkOrder {
+    Column(SortBy, bool),
+    Relevance,
+}
+
 // Get all URLs in DB
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn getall(
     db: &Connection,
-    page_after: Option<&str>,
+    page_after: Option<(&str, &str)>,
     page_no: Option<i64>,
     page_size: Option<i64>,
-    filter: Option<String>,
+    search: Option<Search>,
+    broken: Option<bool>,
+    threshold: i64,
+    order: Option<LinkOrder>,
+    times: &TimeFilters,
 ) -> Rc<[DBRow]> {
-    let now = chrono::Utc::now().timestamp();
+    let expired_after = chrono::Utc::now().timestamp() - times.expired_within.unwrap_or(0);
 
     let has_cursor = page_after.is_some();
-    let has_filter = filter.is_some();
+    let has_filter = search.is_some();
     let paginated = has_cursor || page_no.is_some();
 
     let size = page_size.unwrap_or(if paginated { 10 } else { -1 });
     let offset = page_no.map(|n| (n - 1) * size).unwrap_or(0);
 
-    let (query, params) = match (has_cursor, has_filter) {
-        (false, false) => (
-            queries::GETALL_QUERIES[0],
-            named_params! {
-                ":now": now,
-                ":size": size,
-                ":offset": offset,
-            },
-        ),
-        (true, false) => (
-            queries::GETALL_QUERIES[1],
-            named_params! {
-                ":now": now,
-                ":size": size,
-                ":pos": page_after,
-            },
-        ),
-        (false, true) => (
-            queries::GETALL_QUERIES[2],
-            named_params! {
-                ":now": now,
-                ":size": size,
-                ":offset": offset,
-                ":filter": filter,
-            },
-        ),
-        (true, true) => (
-            queries::GETALL_QUERIES[3],
-            named_params! {
-                ":now": now,
-                ":size": size,
-                ":pos": page_after,
-                ":filter": filter,
-            },
+    // Without a sort, the newest links are picked and then listed oldest first
+    let mut columns = (String::new(), ", NULL AS snippet".to_owned());
+    let (inner_order, outer_order, cursor) = match &order {
+        None => (
+            "t.id DESC".to_owned(),
+            "id ASC".to_owned(),
+            "t.id < u.id".to_owned(),
         ),
+        Some(LinkOrder::Column(column, descending)) => {
+            let column = match column {
+                SortBy::CreatedAt => "created_at",
+                SortBy::UpdatedAt => "updated_at",
+                SortBy::LastAccessedAt => "last_accessed_at",
+                SortBy::Hits => "hits",
+            };
+            let (dir, cmp) = if *descending {
+                ("DESC", "<")
+            } else {
+                ("ASC", ">")
+            };
+            // Links that were never accessed sort as if last accessed at 0
+            (
+                format!("COALESCE(t.{column}, 0) {dir}, t.id {dir}"),
+                format!("COALESCE({column}, 0) {dir}, id {dir}"),
+                format!("(COALESCE(t.{column}, 0), t.id) {cmp} (COALESCE(u.{column}, 0), u.id)"),
+            )
+        }
+        Some(LinkOrder::Relevance) => {
+            // A lower bm25 score is better, and the shorter slugs are closer to a prefix without any text
+            let has_ranking = search.as_ref().is_some_and(|s| s.ranking.is_some());
+            let rank = |alias: &str| {
+                if has_ranking {
+                    queries::GETALL_RANK.replace("{alias}", alias)
+                } else {
+                    format!("length({alias}.short_url)")
+                }
+            };
+            columns.0 = format!(", {} AS rank", rank("t"));
+            if has_ranking {
+                columns.1 = queries::GETALL_SNIPPET.to_owned();
+            }
+            let (rank_t, rank_u) = (rank("t"), rank("u"));
+            (
+                "rank ASC, t.id DESC".to_owned(),
+                "rank ASC, id DESC".to_owned(),
+                format!("({rank_t} > {rank_u} OR ({rank_t} = {rank_u} AND t.id < u.id))"),
+            )
+        }
     };
+    let query = queries::GETALL_QUERIES[usize::from(has_cursor) + 2 * usize::from(has_filter)]
+        .replace("{inner_columns}", &columns.0)
+        .replace("{outer_columns}", &columns.1)
+        .replace("{inner_order}", &inner_order)
+        .replace("{outer_order}", &outer_order)
+        .replace("{cursor}", &cursor)
+        .replace(
+            "{search}",
+            search.as_ref().map_or("", |s| s.condition.as_str()),
+        );
 
-    let Ok(mut statement) = db.prepare_cached(query) else {
+    let pos = page_after.map(|(s, _)| s);
+    let pos_domain = page_after.map(|(_, d)| d);
+    let mut params: Vec<(&str, &dyn ToSql)> = vec![
+        (":expired_after", &expired_after),
+        (":size", &size),
+        (":broken", &broken),
+        (":threshold", &threshold),
+        (":created_after", &times.created.0),
+        (":created_before", &times.created.1),
+        (":updated_after", &times.updated.0),
+        (":updated_before", &times.updated.1),
+        (":accessed_after", &times.accessed.0),
+        (":accessed_before", &times.accessed.1),
+    ];
+    if has_cursor {
+        params.push((":pos", &pos));
+        params.push((":pos_domain", &pos_domain));
+    } else {
+        params.push((":offset", &offset));
+    }
+    for (name, value) in search.iter().flat_map(|s| &s.params) {
+        params.push((name, value));
+    }
+    let ranking = search.as_ref().and_then(|s| s.ranking.as_ref());
+    if let (Some(LinkOrder::Relevance), Some(ranking)) = (&order, &ranking) {
+        params.push((":ranking", ranking));
+    }
+
+    // Filters and sorts make the query different every time, so caching it would only evict the hot statements
+    let (mut cached, mut uncached) = (None, None);
+    let prepared = if has_filter || order.is_some() {
+        db.prepare(&query).map(|s| uncached.insert(s))
+    } else {
+        db.prepare_cached(&query).map(|s| &mut **cached.insert(s))
+    };
+    let Ok(statement) = prepared else {
         error!("Error preparing SQL statement for getall.");
         return [].into();
     };
 
-    let raw_data = statement.query(params);
+    let raw_data = statement.query(&*params);
 
     let Ok(data) = raw_data else {
         error!("Error running SQL statement for getall.");
@@ -119,10 +272,26 @@ pub(crate) fn getall(
         .map(|row| {
             Ok(DBRow {
                 shortlink: row.get("short_url")?,
+                domain: row.get("domain")?,
                 longlink: row.get("long_url")?,
                 hits: row.get("hits")?,
                 expiry_time: row.get("expiry_time").unwrap_or_default(),
                 notes: row.get("notes").unwrap_or_default(),
+                rules: redirect::parse_json(row.get("rules")?),
+                targets: redirect::parse_json(row.get("targets")?),
+                sticky: row.get("sticky")?,
+                created_at: row.get("created_at").unwrap_or_default(),
+                updated_at: row.get("updated_at").unwrap_or_default(),
+                last_accessed_at: row.get("last_accessed_at").unwrap_or_default(),
+                idle_ttl: row.get("idle_ttl").unwrap_or_default(),
+                preview: row.get("preview")?,
+                opengraph: redirect::parse_json(row.get("opengraph")?),
+                health_status: row.get("health_status")?,
+                health_checked_at: row.get("health_checked_at").unwrap_or_default(),
+                health_failures: row.get("health_failures")?,
+                disabled: row.get("disabled")?,
+                aliases: redirect::parse_json(row.get("aliases")?),
+                snippet: row.get("snippet")?,
             })
         })
         .collect()
@@ -132,51 +301,76 @@ pub(crate) fn getall(
         });
 
     debug!(
-        "Path getall was accessed with page_no: {:?}, page_after: {:?}, page_size: {:?}, filter: {:?}",
-        page_no, page_after, page_size, filter
+        "Path getall was accessed with page_no: {:?}, page_after: {:?}, page_size: {:?}, search: {:?}, broken: {:?}, sorted: {}",
+        page_no,
+        page_after,
+        page_size,
+        search.map(|s| s.condition),
+        broken,
+        order.is_some()
     );
     links
 }
 
-// Resolve site and add link to add_hit queue
-pub(crate) async fn find_and_add_hit(
+// Resolve site for redirection
+pub(crate) fn find_link(
     shortlink: &str,
+    domain: &str,
+    nocase: bool,
     db: &Connection,
-    hits_tx: &mpsc::Sender<(String, bool)>,
-) -> Result<String, ()> {
+) -> Result<FoundLink, ()> {
     let now = chrono::Utc::now().timestamp();
     let Ok(mut statement) = db.prepare_cached(queries::FIND_LINK) else {
         error!("Error preparing SQL statement for find link.");
         return Err(());
     };
-    let Ok(long_url) = statement
-        .query_one(named_params! {":short": shortlink, ":now": now}, |row| {
-            row.get("long_url")
-        })
-    else {
-        return Err(());
-    };
+    statement
+        .query_one(
+            named_params! {":key": slug_key(shortlink, nocase), ":domain": domain, ":now": now},
+            |row| {
+                Ok(FoundLink {
+                    id: row.get("id")?,
+                    long_url: row.get("long_url")?,
+                    expiry_time: row.get("expiry_time").unwrap_or_default(),
+                    notes: row.get("notes").unwrap_or_default(),
+                    rules: redirect::parse_json(row.get("rules")?),
+                    targets: redirect::parse_json(row.get("targets")?),
+                    sticky: row.get("sticky")?,
+                    created_at: row.get("created_at").unwrap_or_default(),
+                    preview: row.get("preview")?,
+                    opengraph: redirect::parse_json(row.get("opengraph")?),
+                })
+            },
+        )
+        .inspect(|_| debug!("Accessed link: {shortlink}."))
+        .map_err(drop)
+}
 
-    debug!("Accessed link: {shortlink}.");
-    if let Err(err) = hits_tx.send((shortlink.to_owned(), false)).await {
+// Add link to add_hit queue
+pub(crate) async fn add_hit(id: i64, target: Option<usize>, hits_tx: &mpsc::Sender<HitUpdate>) {
+    if let Err(err) = hits_tx.send((id, target, false)).await {
         error!("Failed to enqueue hit update after access: {err}");
     }
-    Ok(long_url)
 }
+
 // Add hits
-pub(crate) fn add_hits(shortlinks: HashMap<String, i64>, db: &mut Connection) {
+pub(crate) fn add_hits(links: HashMap<(i64, Option<usize>), i64>, db: &mut Connection) {
     let Ok(tx) = db.transaction() else {
         warn!("Unable to start a transaction for add hit.");
         return;
     };
+    let now = chrono::Utc::now().timestamp();
     {
         let Ok(mut statement) = tx.prepare_cached(queries::ADD_HIT) else {
             warn!("Error preparing SQL statement for add hit.");
             return;
         };
-        for (link, count) in shortlinks.iter() {
+        for ((link, target), count) in links.iter() {
+            let target = target.map(|t| t as i64);
             let _ = statement
-                .execute(named_params! {":short": link, ":count": count})
+                .execute(
+                    named_params! {":id": link, ":target": target, ":count": count, ":now": now},
+                )
                 .inspect_err(|e| {
                     warn!("Unable to update hit for {link}: {e}");
                 });
@@ -186,24 +380,265 @@ pub(crate) fn add_hits(shortlinks: HashMap<String, i64>, db: &mut Connection) {
         warn!("Add hit commit failed: {e}");
         warn!(
             "Dropped a total of {} hit increments, with {} distinct links.",
-            shortlinks.values().sum::<i64>(),
-            shortlinks.len()
+            links.values().sum::<i64>(),
+            links.len()
+        );
+    }
+}
+
+// Get the links that are due for a health check, least recently checked first
+pub(crate) fn health_check_links(db: &Connection) -> Vec<(i64, String)> {
+    let now = chrono::Utc::now().timestamp();
+    let Ok(mut statement) = db.prepare_cached(queries::HEALTH_CHECK_LINKS) else {
+        error!("Error preparing SQL statement for health check links.");
+        return Vec::new();
+    };
+    statement
+        .query_map(named_params! {":now": now}, |row| {
+            Ok((row.get("id")?, row.get("long_url")?))
+        })
+        .and_then(Iterator::collect)
+        .unwrap_or_else(|err| {
+            error!("Error fetching links for health checks: {err}");
+            Vec::new()
+        })
+}
+
+// Store the results of health checks, a missing status means that the destination was unreachable
+pub(crate) fn update_health(results: Vec<(i64, String, Option<u16>)>, db: &mut Connection) {
+    let now = chrono::Utc::now().timestamp();
+    let Ok(tx) = db.transaction() else {
+        warn!("Unable to start a transaction for update health.");
+        return;
+    };
+    {
+        let Ok(mut statement) = tx.prepare_cached(queries::UPDATE_HEALTH) else {
+            warn!("Error preparing SQL statement for update health.");
+            return;
+        };
+        for (id, long_url, status) in results.iter() {
+            let failed = status.is_none_or(|s| s >= 400);
+            let _ = statement
+                .execute(named_params! {
+                    ":id": id,
+                    ":long": long_url,
+                    ":status": status,
+                    ":failed": failed,
+                    ":now": now,
+                })
+                .inspect_err(|e| warn!("Unable to update health for {id}: {e}"));
+        }
+    }
+    if let Err(e) = tx.commit() {
+        warn!(
+            "Update health commit failed, dropped {} results: {e}",
+            results.len()
         );
     }
 }
 
+// Struct for a link that failed its recent health checks
+#[derive(Serialize, ToSchema)]
+pub(crate) struct BrokenLink {
+    shortlink: String,
+    domain: String,
+    longlink: String,
+    health_status: Option<i64>,
+    health_checked_at: i64,
+    health_failures: i64,
+}
+
+// Struct for the summary of the health checks
+#[derive(Serialize, ToSchema)]
+pub(crate) struct HealthReport {
+    total: i64,
+    checked: i64,
+    broken: i64,
+    last_checked: i64,
+    links: Vec<BrokenLink>,
+}
+
+// Summarize the health of the destinations, along with the broken links
+pub(crate) fn health_report(db: &Connection, threshold: i64) -> Result<HealthReport, ChhotoError> {
+    let now = chrono::Utc::now().timestamp();
+    let params = named_params! {":now": now, ":threshold": threshold};
+    let (Ok(mut summary), Ok(mut broken)) = (
+        db.prepare_cached(queries::HEALTH_SUMMARY),
+        db.prepare_cached(queries::BROKEN_LINKS),
+    ) else {
+        error!("Error preparing SQL statements for health report.");
+        return Err(ServerError);
+    };
+    let links = broken
+        .query_map(params, |row| {
+            Ok(BrokenLink {
+                shortlink: row.get("short_url")?,
+                domain: row.get("domain")?,
+                longlink: row.get("long_url")?,
+                health_status: row.get("health_status")?,
+                health_checked_at: row.get("health_checked_at").unwrap_or_default(),
+                health_failures: row.get("health_failures")?,
+            })
+        })
+        .and_then(Iterator::collect);
+    summary
+        .query_row(params, |row| {
+            Ok(HealthReport {
+                total: row.get("total")?,
+                checked: row.get("checked")?,
+                broken: row.get("broken")?,
+                last_checked: row.get("last_checked").unwrap_or_default(),
+                links: links?,
+            })
+        })
+        .map_err(|err| {
+            error!("Error creating the health report: {err}");
+            ServerError
+        })
+}
+
+// Disable the links with a destination that is now blocked, returning how many were disabled
+pub(crate) fn disable_blocked(db: &mut Connection, is_blocked: impl Fn(&str) -> bool) -> usize {
+    let Ok(tx) = db.transaction() else {
+        error!("Unable to start a transaction for disable blocked.");
+        return 0;
+    };
+    let mut disabled = 0;
+    {
+        let (Ok(mut select), Ok(mut update)) = (
+            tx.prepare_cached(queries::LINK_DESTINATIONS),
+            tx.prepare_cached(queries::DISABLE_LINK),
+        ) else {
+            error!("Error preparing SQL statements for disable blocked.");
+            return 0;
+        };
+        let links = select
+            .query_map((), |row| {
+                let rules: Vec<RedirectRule> = redirect::parse_json(row.get("rules")?);
+                let targets: Vec<WeightedTarget> = redirect::parse_json(row.get("targets")?);
+                let long_url: String = row.get("long_url")?;
+                let blocked = is_blocked(&long_url)
+                    || rules.iter().any(|r| is_blocked(&r.target))
+                    || targets.iter().any(|t| is_blocked(&t.url));
+                Ok((row.get::<_, i64>("id")?, blocked))
+            })
+            .and_then(Iterator::collect::<Result<Vec<_>, _>>);
+        let Ok(links) = links else {
+            error!("Error fetching link destinations for disable blocked.");
+            return 0;
+        };
+        for (id, _) in links.iter().filter(|(_, blocked)| *blocked) {
+            match update.execute(named_params! {":id": id}) {
+                Ok(_) => disabled += 1,
+                Err(e) => warn!("Unable to disable link {id}: {e}"),
+            }
+        }
+    }
+    if let Err(e) = tx.commit() {
+        error!("Disable blocked commit failed: {e}");
+        return 0;
+    }
+    disabled
+}
+
+// Struct for returning a successfully added link
+#[derive(Clone)]
+pub(crate) struct AddedLink {
+    pub(crate) shortlink: String,
+    pub(crate) domain: String,
+    pub(crate) expiry_time: i64,
+    pub(crate) reused: bool,
+}
+
+// Find an active plain link to the same destination, as an alternative to adding a new one
+pub(crate) fn find_duplicate(
+    longlink: &str,
+    normalized: &str,
+    domain: &str,
+    db: &Connection,
+) -> Option<AddedLink> {
+    let Ok(mut statement) = db.prepare_cached(queries::FIND_DUPLICATE) else {
+        error!("Error preparing SQL statement for find duplicate.");
+        return None;
+    };
+    statement
+        .query_row(
+            named_params! {
+                ":long": longlink,
+                ":normalized": normalized,
+                ":domain": domain,
+            },
+            |row| {
+                Ok(AddedLink {
+                    shortlink: row.get("short_url")?,
+                    domain: domain.to_owned(),
+                    expiry_time: row.get("expiry_time").unwrap_or_default(),
+                    reused: true,
+                })
+            },
+        )
+        .optional()
+        .inspect_err(|e| error!("Error looking for a duplicate of {longlink}: {e}"))
+        .ok()
+        .flatten()
+}
+
+// How a slug is currently being used
+pub(crate) struct SlugUsage {
+    pub(crate) expired: bool,
+    // Expired links keep their slug until the grace period ends
+    pub(crate) in_grace_period: bool,
+    pub(crate) alias: bool,
+}
+
+// Check whether a slug is held by a link or an alias, including expired links
+pub(crate) fn slug_usage(
+    shortlink: &str,
+    domain: &str,
+    nocase: bool,
+    grace_period: i64,
+    db: &Connection,
+) -> Result<Option<SlugUsage>, ChhotoError> {
+    let now = chrono::Utc::now().timestamp();
+    let Ok(mut statement) = db.prepare_cached(queries::SLUG_USAGE) else {
+        error!("Error preparing SQL statement for slug usage.");
+        return Err(ServerError);
+    };
+    statement
+        .query_row(
+            named_params! {":key": slug_key(shortlink, nocase), ":domain": domain},
+            |row| {
+                let expiry_time: Option<i64> = row.get("expiry_time")?;
+                Ok(SlugUsage {
+                    expired: expiry_time.is_some_and(|t| t <= now),
+                    in_grace_period: expiry_time
+                        .is_some_and(|t| t <= now && t > now - grace_period),
+                    alias: row.get("alias")?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| {
+            error!("Error checking the usage of {shortlink}: {e}");
+            ServerError
+        })
+}
+
 // Insert a new link
-type AddLinksReturnType = Vec<(usize, Result<(String, i64), ChhotoError>)>;
+type AddLinksReturnType = Vec<(usize, Result<AddedLink, ChhotoError>)>;
 pub(crate) fn add_links(
     requests: Vec<(usize, NewURLRequest)>,
     db: &mut Connection,
     return_rejected: bool,
+    nocase: bool,
+    grace_period: i64,
+    encode: Option<&dyn Fn(i64) -> Option<String>>,
 ) -> (AddLinksReturnType, Option<Vec<(usize, NewURLRequest)>>) {
     if requests.is_empty() {
         return (Vec::new(), None);
     }
     let now = chrono::Utc::now().timestamp();
-    let in_use_error = ClientError {
+    let in_use_error = Conflict {
         reason: "Short URL is already in use!".to_owned(),
     };
     let mut output = Vec::with_capacity(requests.len());
@@ -226,43 +661,112 @@ pub(crate) fn add_links(
 
             for (i, req) in chunk {
                 let expiry_time = req.expiry_delay.map(|delay| now + delay);
-                output.push(match statement.execute(
-                named_params! {
-                    ":long": req.longlink,
-                    ":short": req.shortlink,
-                    ":expiry": expiry_time,
-                    ":now": now,
-                    ":notes" : req.notes
-                },
-            ) {
-                Ok(1) => {
-                    debug!(
-                        "Added link with shortlink: {}, longlink: {}, expiry_delay: {:?}, notes: {:?}",
-                        req.shortlink, req.longlink, req.expiry_delay, req.notes
-                    );
-                    (*i, Ok((req.shortlink.to_owned(), expiry_time.unwrap_or_default())))
+                let rules = req
+                    .rules
+                    .as_ref()
+                    .filter(|r| !r.is_empty())
+                    .and_then(|r| serde_json::to_string(r).ok());
+                let targets = req
+                    .targets
+                    .as_ref()
+                    .filter(|t| !t.is_empty())
+                    .and_then(|t| serde_json::to_string(t).ok());
+                let opengraph = req
+                    .opengraph
+                    .as_ref()
+                    .filter(|o| !o.is_empty())
+                    .and_then(|o| serde_json::to_string(o).ok());
+                let mut insert = |id: Option<i64>, shortlink: &str| {
+                    statement.execute(named_params! {
+                        ":id": id,
+                        ":long": req.longlink,
+                        ":short": shortlink,
+                        ":key": slug_key(shortlink, nocase),
+                        ":domain": req.domain,
+                        ":expiry": expiry_time,
+                        ":now": now,
+                        ":reclaim_before": now - grace_period,
+                        ":notes" : req.notes,
+                        ":rules": rules,
+                        ":targets": targets,
+                        ":sticky": req.sticky.unwrap_or_default(),
+                        ":preview": req.preview.unwrap_or_default(),
+                        ":opengraph": opengraph,
+                        ":idle_ttl": req.idle_ttl,
+                    })
+                };
+                // A link is only kept if all of its aliases could be added
+                if req.aliases.is_some()
+                    && let Err(e) = tx.execute_batch("SAVEPOINT link")
+                {
+                    error!("Unable to create a savepoint for adding aliases: {e}");
+                    output.push((*i, Err(ServerError)));
+                    continue;
                 }
-                Ok(0) => {
-                    debug!("Duplicate insertion attempted for {}.", req.shortlink);
+                let (shortlink, result) = match encode.filter(|_| req.shortlink.is_empty()) {
+                    Some(encode) => insert_sequential(&tx, encode, &mut insert),
+                    None => (req.shortlink.to_owned(), insert(None, &req.shortlink)),
+                };
+                // A revived expired link shouldn't keep the aliases of the old one
+                if let Ok(1) = result
+                    && let Err(e) = tx.execute(
+                        queries::CLEAR_ALIASES_FOR_SLUG,
+                        named_params! {":key": slug_key(&shortlink, nocase), ":domain": req.domain},
+                    )
+                {
+                    error!("Unable to clear aliases of {shortlink}: {e}");
+                }
+                if let Some(aliases) = &req.aliases {
+                    let added = match result {
+                        Ok(1) => set_aliases(&shortlink, &req.domain, aliases, nocase, &tx),
+                        _ => Ok(()),
+                    };
+                    let release = match added {
+                        Ok(()) => "RELEASE link",
+                        Err(_) => "ROLLBACK TO link; RELEASE link",
+                    };
+                    if let Err(e) = tx.execute_batch(release) {
+                        error!("Unable to release the savepoint for adding aliases: {e}");
+                    }
+                    if let Err(e) = added {
+                        output.push((*i, Err(e)));
+                        continue;
+                    }
+                }
+                output.push(match result {
+                    Ok(1) => {
+                        debug!(
+                            "Added link with shortlink: {}, longlink: {}, expiry_delay: {:?}, notes: {:?}",
+                            shortlink, req.longlink, req.expiry_delay, req.notes
+                        );
+                        (*i, Ok(AddedLink {
+                            shortlink,
+                            domain: req.domain.to_owned(),
+                            expiry_time: expiry_time.unwrap_or_default(),
+                            reused: false,
+                        }))
+                    }
+                    Ok(0) => {
+                        debug!("Duplicate insertion attempted for {}.", shortlink);
                         if return_rejected {
                             rejected.push((*i, req.to_owned()));
                             (0, Err(ServerError)) // Placeholder; add_links_helper ignores errors in this mode.
                         } else {
                             (*i, Err(in_use_error.to_owned()))
                         }
-                }
-                Ok(n) => {
-                    error!("Unexpected row count while adding link {}: {}", req.shortlink, n);
-                    (*i, Err(ServerError))
-                }
-                Err(e) => {
-                    error!(
-                        "There was some error while adding the link ({}, {}, {:?}): {}",
-                        req.shortlink, req.longlink, req.expiry_delay, e
-                    );
-                    (*i, Err(ServerError))
-                }
-            });
+                    }
+                    Ok(n) => {
+                        error!("Unexpected row count while adding link {}: {}", shortlink, n);
+                        (*i, Err(ServerError))
+                    }
+                    Err(e) => {
+                        error!(
+                            "There was some error while adding the link ({}, {}, {:?}): {}",
+                            shortlink, req.longlink, req.expiry_delay, e
+                        );
+                        (*i, Err(ServerError))
+                    }
+                });
             }
         }
         if let Err(e) = tx.commit() {
@@ -275,14 +779,48 @@ pub(crate) fn add_links(
     (output, Some(rejected).filter(|_| return_rejected))
 }
 
+// Reserve an id and insert the link using its encoded slug
+// Ids whose slug is reserved or already taken by a custom slug are skipped
+fn insert_sequential(
+    tx: &Connection,
+    encode: &dyn Fn(i64) -> Option<String>,
+    insert: &mut impl FnMut(Option<i64>, &str) -> rusqlite::Result<usize>,
+) -> (String, rusqlite::Result<usize>) {
+    let mut last = (String::new(), Ok(0));
+    for _ in 0..16 {
+        let id = match tx.query_one(queries::NEXT_LINK_ID, (), |row| row.get::<_, i64>(0)) {
+            Ok(id) => id,
+            Err(e) => return (String::new(), Err(e)),
+        };
+        let Some(shortlink) = encode(id) else {
+            continue;
+        };
+        let result = insert(Some(id), &shortlink);
+        if !matches!(result, Ok(0)) {
+            return (shortlink, result);
+        }
+        last = (shortlink, result);
+    }
+    last
+}
+
 // Edit an existing link
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn edit_link(
     shortlink: &str,
+    domain: &str,
     longlink: &str,
     reset_hits: bool,
     expiry_time: Option<i64>,
     notes: Option<&str>,
-    hits_tx: &mpsc::Sender<(String, bool)>,
+    rules: Option<&[RedirectRule]>,
+    targets: Option<&[WeightedTarget]>,
+    sticky: Option<bool>,
+    preview: Option<bool>,
+    opengraph: Option<&OpenGraph>,
+    idle_ttl: Option<i64>,
+    nocase: bool,
+    hits_tx: &mpsc::Sender<HitUpdate>,
     db: &Connection,
 ) -> Result<usize, ()> {
     let now = chrono::Utc::now().timestamp();
@@ -290,45 +828,215 @@ pub(crate) async fn edit_link(
         error!("Error preparing SQL statement for edit_link.");
         return Err(());
     };
-    if reset_hits && let Err(err) = hits_tx.send((shortlink.to_owned(), true)).await {
-        error!("Failed to enqueue hit update after edit: {err}");
-    }
-    statement
-        .execute(named_params! {
-            ":long": longlink,
-            ":short": shortlink,
-            ":now": now,
-            ":hits": reset_hits.then_some(0),
-            ":notes": notes,
-            ":expiry": expiry_time,
-        })
+    let id = statement
+        .query_row(
+            named_params! {
+                ":long": longlink,
+                ":key": slug_key(shortlink, nocase),
+                ":domain": domain,
+                ":now": now,
+                ":hits": reset_hits.then_some(0),
+                ":notes": notes,
+                ":expiry": expiry_time,
+                ":rules": rules.and_then(|r| serde_json::to_string(r).ok()),
+                ":targets": targets.and_then(|t| serde_json::to_string(t).ok()),
+                ":sticky": sticky,
+                ":preview": preview,
+                ":opengraph": opengraph.and_then(|o| serde_json::to_string(o).ok()),
+                ":idle_ttl": idle_ttl,
+            },
+            |row| row.get::<_, i64>("id"),
+        )
+        .optional()
         .inspect_err(|err| {
             error!(
                 "Got an error while editing link ({shortlink}, {longlink}, {reset_hits}): {err}"
             );
         })
-        .inspect(|_| {
-            debug!(
-                "Link {} was edited using longlink: {}, reset_hits: {}, expiry_time: {:?}, notes: {:?}.",
-                shortlink, longlink, reset_hits, expiry_time, notes
-            );
-        })
-        .map_err(drop)
+        .map_err(drop)?;
+    let Some(id) = id else {
+        return Ok(0);
+    };
+    debug!(
+        "Link {} was edited using longlink: {}, reset_hits: {}, expiry_time: {:?}, notes: {:?}.",
+        shortlink, longlink, reset_hits, expiry_time, notes
+    );
+    if reset_hits && let Err(err) = hits_tx.send((id, None, true)).await {
+        error!("Failed to enqueue hit update after edit: {err}");
+    }
+    Ok(1)
 }
 
 // Delete an existing link
-pub(crate) fn delete_link(shortlink: &str, db: &Connection) -> Result<(), ChhotoError> {
+pub(crate) fn delete_link(
+    shortlink: &str,
+    domain: &str,
+    nocase: bool,
+    db: &Connection,
+) -> Result<(), ChhotoError> {
     let Ok(mut statement) = db.prepare_cached(queries::DELETE_LINK) else {
         error!("Error preparing SQL statement for delete_link.");
         return Err(ServerError);
     };
-    match statement.execute(named_params! {":short" : shortlink}) {
+    let key = slug_key(shortlink, nocase);
+    match statement.execute(named_params! {":key" : key, ":domain": domain}) {
         Ok(delta) if delta > 0 => {
             debug!("Deleted link {shortlink}.");
             Ok(())
         }
-        _ => Err(ClientError {
+        // Deleting an alias leaves the link alone
+        Ok(_)
+            if db
+                .prepare_cached(queries::DELETE_ALIAS)
+                .and_then(|mut s| s.execute(named_params! {":key" : key, ":domain": domain}))
+                .is_ok_and(|delta| delta > 0) =>
+        {
+            debug!("Deleted alias {shortlink}.");
+            Ok(())
+        }
+        _ => Err(NotFound {
             reason: "The shortlink was not found, and could not be deleted.".to_owned(),
         }),
     }
 }
+
+// Give a link that expired within the grace period a new expiry time
+pub(crate) fn revive_link(
+    shortlink: &str,
+    domain: &str,
+    expiry_time: Option<i64>,
+    nocase: bool,
+    grace_period: i64,
+    db: &Connection,
+) -> Result<(), ChhotoError> {
+    let now = chrono::Utc::now().timestamp();
+    let Ok(mut statement) = db.prepare_cached(queries::REVIVE_LINK) else {
+        error!("Error preparing SQL statement for revive_link.");
+        return Err(ServerError);
+    };
+    match statement.execute(named_params! {
+        ":key": slug_key(shortlink, nocase),
+        ":domain": domain,
+        ":expiry": expiry_time,
+        ":now": now,
+        ":cutoff": now - grace_period,
+    }) {
+        Ok(1) => {
+            debug!("Revived link {shortlink}.");
+            Ok(())
+        }
+        Ok(_) => Err(NotFound {
+            reason: "No recently expired link was found with that shortlink.".to_owned(),
+        }),
+        Err(e) => {
+            error!("Error reviving {shortlink}: {e}");
+            Err(ServerError)
+        }
+    }
+}
+
+// A response stored for an idempotency key
+pub(crate) struct StoredResponse {
+    pub(crate) request_hash: String,
+    pub(crate) status: u16,
+    pub(crate) content_type: String,
+    pub(crate) body: String,
+}
+
+// Find the stored response for an idempotency key that hasn't expired yet
+pub(crate) fn find_idempotency_key(key: &str, db: &Connection) -> Option<StoredResponse> {
+    let now = chrono::Utc::now().timestamp();
+    let Ok(mut statement) = db.prepare_cached(queries::FIND_IDEMPOTENCY_KEY) else {
+        error!("Error preparing SQL statement for find idempotency key.");
+        return None;
+    };
+    statement
+        .query_row(named_params! {":key": key, ":now": now}, |row| {
+            Ok(StoredResponse {
+                request_hash: row.get("request_hash")?,
+                status: row.get("status")?,
+                content_type: row.get("content_type")?,
+                body: row.get("body")?,
+            })
+        })
+        .optional()
+        .inspect_err(|e| error!("Error looking up idempotency key {key}: {e}"))
+        .ok()
+        .flatten()
+}
+
+// Store the response for an idempotency key, replacing an expired entry if there is one
+pub(crate) fn add_idempotency_key(
+    key: &str,
+    response: &StoredResponse,
+    window: i64,
+    db: &Connection,
+) {
+    let expiry_time = chrono::Utc::now().timestamp() + window;
+    if let Err(e) = db
+        .prepare_cached(queries::ADD_IDEMPOTENCY_KEY)
+        .and_then(|mut statement| {
+            statement.execute(named_params! {
+                ":key": key,
+                ":hash": response.request_hash,
+                ":status": response.status,
+                ":content_type": response.content_type,
+                ":body": response.body,
+                ":expiry_time": expiry_time,
+            })
+        })
+    {
+        error!("Error storing idempotency key {key}: {e}");
+    }
+}
+
+// Replace the aliases of a link, failing if any of them is taken
+// Should be run inside a transaction, which is to be rolled back on failure
+pub(crate) fn set_aliases(
+    shortlink: &str,
+    domain: &str,
+    aliases: &[String],
+    nocase: bool,
+    tx: &Connection,
+) -> Result<(), ChhotoError> {
+    let now = chrono::Utc::now().timestamp();
+    let id: i64 = tx
+        .query_one(
+            queries::FIND_LINK_ID,
+            named_params! {":key": slug_key(shortlink, nocase), ":domain": domain, ":now": now},
+            |row| row.get("id"),
+        )
+        .map_err(|_| NotFound {
+            reason: "The shortlink does not exist on the server!".to_owned(),
+        })?;
+    let run = || -> rusqlite::Result<Option<&String>> {
+        tx.execute(queries::CLEAR_ALIASES, named_params! {":id": id})?;
+        let mut statement = tx.prepare_cached(queries::ADD_ALIAS)?;
+        for alias in aliases {
+            let added = statement.execute(named_params! {
+                ":alias": alias,
+                ":key": slug_key(alias, nocase),
+                ":domain": domain,
+                ":id": id,
+                ":now": now,
+            })?;
+            if added == 0 {
+                return Ok(Some(alias));
+            }
+        }
+        Ok(None)
+    };
+    match run() {
+        Ok(None) => {
+            debug!("Set aliases of {shortlink} to {aliases:?}.");
+            Ok(())
+        }
+        Ok(Some(alias)) => Err(Conflict {
+            reason: format!("Alias {alias} is already in use!"),
+        }),
+        Err(e) => {
+            error!("Error setting aliases of {shortlink}: {e}");
+            Err(ServerError)
+        }
+    }
+}
@@ -1,9 +1,11 @@
 // SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
 // SPDX-License-Identifier: MIT
 
+mod backups;
 mod events;
 mod queries;
 mod utils;
 
+pub(crate) use self::backups::*;
 pub(crate) use self::events::*;
 pub(crate) use self::utils::*;
@@ -2,16 +2,27 @@
 // SPDX-License-Identifier: MIT
 
 pub(super) const FIND_URL: &str = "
-SELECT long_url, hits, expiry_time, notes FROM urls
-  WHERE short_url = :short
+SELECT short_url, long_url, hits, expiry_time, notes, rules, targets, sticky, created_at, preview,
+  opengraph, health_status, health_checked_at, health_failures, disabled, updated_at, last_accessed_at,
+  idle_ttl, (SELECT json_group_array(alias) FROM aliases WHERE link_id = urls.id) AS aliases
+  FROM urls
+  WHERE (
+      (short_url_key = :key AND domain = :domain)
+      OR id = (SELECT link_id FROM aliases WHERE alias_key = :key AND domain = :domain)
+    )
     AND (
       expiry_time IS NULL 
       OR expiry_time > :now
     )";
 
 pub(super) const FIND_LINK: &str = "
-SELECT long_url FROM urls 
-  WHERE short_url = :short 
+SELECT id, long_url, expiry_time, notes, rules, targets, sticky, created_at, preview, opengraph
+  FROM urls 
+  WHERE (
+      (short_url_key = :key AND domain = :domain)
+      OR id = (SELECT link_id FROM aliases WHERE alias_key = :key AND domain = :domain)
+    )
+    AND NOT disabled
     AND (
       expiry_time IS NULL 
       OR expiry_time > :now
@@ -19,22 +30,196 @@ SELECT long_url FROM urls
 
 pub(super) const ADD_HIT: &str = "
 UPDATE urls 
-  SET hits = hits + :count
-  WHERE short_url = :short";
+  SET hits = hits + :count,
+    last_accessed_at = :now,
+    targets = CASE
+      WHEN :target IS NULL OR json_type(targets, '$[' || :target || ']') IS NULL THEN targets
+      ELSE json_set(
+        targets,
+        '$[' || :target || '].hits',
+        COALESCE(json_extract(targets, '$[' || :target || '].hits'), 0) + :count
+      )
+    END
+  WHERE id = :id";
 
 pub(super) const ADD_LINK: &str = "
 INSERT INTO urls
-  (long_url, short_url, hits, expiry_time, notes)
-  VALUES (:long, :short, 0, :expiry, :notes)
-ON CONFLICT(short_url) DO UPDATE 
-  SET long_url = :long, hits = 0, expiry_time = :expiry, notes = :notes
-  WHERE short_url = :short 
-    AND expiry_time <= :now
+  (id, long_url, short_url, short_url_key, domain, hits, expiry_time, notes, rules, targets,
+    sticky, created_at, updated_at, preview, opengraph, idle_ttl)
+  SELECT :id, :long, :short, :key, :domain, 0, :expiry, :notes, :rules, :targets, :sticky, :now,
+    :now, :preview, :opengraph, :idle_ttl
+  WHERE NOT EXISTS (SELECT 1 FROM aliases WHERE alias_key = :key AND domain = :domain)
+ON CONFLICT(short_url_key, domain) DO UPDATE 
+  SET short_url = :short, long_url = :long, hits = 0, expiry_time = :expiry, notes = :notes, rules = :rules,
+    targets = :targets, sticky = :sticky, created_at = :now, updated_at = :now,
+    last_accessed_at = NULL, preview = :preview, opengraph = :opengraph, health_status = NULL,
+    health_checked_at = NULL, health_failures = 0, disabled = 0, idle_ttl = :idle_ttl
+  WHERE short_url_key = :key
+    AND domain = :domain
+    AND expiry_time <= :reclaim_before
     AND expiry_time IS NOT NULL";
 
-pub(super) const DELETE_LINK: &str = "DELETE FROM urls WHERE short_url = :short";
+pub(super) const HEALTH_CHECK_LINKS: &str = "
+SELECT id, long_url FROM urls
+  WHERE NOT disabled
+    AND (
+      expiry_time IS NULL
+      OR expiry_time > :now
+    )
+  ORDER BY health_checked_at ASC, id ASC";
+
+pub(super) const UPDATE_HEALTH: &str = "
+UPDATE urls
+  SET health_status = :status,
+    health_checked_at = :now,
+    health_failures = CASE WHEN :failed THEN health_failures + 1 ELSE 0 END
+  WHERE id = :id
+    AND long_url = :long";
+
+pub(super) const HEALTH_SUMMARY: &str = "
+SELECT COUNT(*) AS total,
+  COUNT(health_checked_at) AS checked,
+  COALESCE(SUM(health_failures >= :threshold), 0) AS broken,
+  MAX(health_checked_at) AS last_checked
+FROM urls
+  WHERE expiry_time IS NULL
+    OR expiry_time > :now";
+
+pub(super) const BROKEN_LINKS: &str = "
+SELECT short_url, domain, long_url, health_status, health_checked_at, health_failures FROM urls
+  WHERE health_failures >= :threshold
+    AND (
+      expiry_time IS NULL
+      OR expiry_time > :now
+    )
+  ORDER BY health_failures DESC, id ASC";
+
+pub(super) const LINK_DESTINATIONS: &str =
+    "SELECT id, long_url, rules, targets FROM urls WHERE NOT disabled";
+
+pub(super) const DISABLE_LINK: &str = "UPDATE urls SET disabled = 1 WHERE id = :id";
+
+pub(super) const FIND_DUPLICATE: &str = "
+SELECT short_url, expiry_time FROM urls
+  WHERE long_url IN (:long, :normalized)
+    AND domain = :domain
+    AND NOT disabled
+    AND rules IS NULL
+    AND targets IS NULL
+    AND NOT preview
+    AND opengraph IS NULL
+    AND idle_ttl IS NULL
+    AND (
+      expiry_time IS NULL
+      OR expiry_time = 0
+    )
+  ORDER BY id DESC
+  LIMIT 1";
+
+pub(super) const ALIASES_TABLE_SCHEMA: &str = "
+CREATE TABLE aliases (
+  alias TEXT NOT NULL,
+  alias_key TEXT NOT NULL,
+  domain TEXT NOT NULL DEFAULT '',
+  link_id INTEGER NOT NULL,
+  created_at INTEGER NOT NULL
+);
+CREATE UNIQUE INDEX idx_alias ON aliases (alias_key, domain);
+CREATE INDEX idx_alias_link ON aliases (link_id);
+CREATE TRIGGER urls_delete_aliases
+AFTER DELETE ON urls BEGIN
+  DELETE FROM aliases WHERE link_id = old.id;
+END;";
+
+pub(super) const FIND_LINK_ID: &str = "
+SELECT id FROM urls
+  WHERE short_url_key = :key
+    AND domain = :domain
+    AND (expiry_time IS NULL OR expiry_time > :now)";
+
+// Only links that expired within the grace period can be revived
+pub(super) const REVIVE_LINK: &str = "
+UPDATE urls
+  SET expiry_time = :expiry, updated_at = :now
+  WHERE short_url_key = :key
+    AND domain = :domain
+    AND expiry_time <= :now
+    AND expiry_time > :cutoff";
+
+pub(super) const CLEAR_ALIASES: &str = "DELETE FROM aliases WHERE link_id = :id";
+
+pub(super) const CLEAR_ALIASES_FOR_SLUG: &str = "
+DELETE FROM aliases
+  WHERE link_id = (SELECT id FROM urls WHERE short_url_key = :key AND domain = :domain)";
+
+// Aliases can't take a slug used by any link, even an expired one
+pub(super) const ADD_ALIAS: &str = "
+INSERT INTO aliases (alias, alias_key, domain, link_id, created_at)
+  SELECT :alias, :key, :domain, :id, :now
+  WHERE NOT EXISTS (SELECT 1 FROM urls WHERE short_url_key = :key AND domain = :domain)
+ON CONFLICT DO NOTHING";
+
+// Expired links still hold their slug until they are cleaned up or replaced
+pub(super) const SLUG_USAGE: &str = "
+SELECT expiry_time, FALSE AS alias FROM urls
+  WHERE short_url_key = :key AND domain = :domain
+UNION ALL
+SELECT urls.expiry_time, TRUE FROM aliases
+  JOIN urls ON urls.id = aliases.link_id
+  WHERE alias_key = :key AND aliases.domain = :domain";
+
+pub(super) const DELETE_ALIAS: &str =
+    "DELETE FROM aliases WHERE alias_key = :key AND domain = :domain";
+
+pub(super) const DELETE_LINK: &str =
+    "DELETE FROM urls WHERE short_url_key = :key AND domain = :domain";
+
+// Slugs are looked up using the key, which is lowercase when case-insensitive slugs are enabled
+pub(super) const SYNC_SLUG_KEYS: &str = "
+UPDATE urls SET short_url_key = IIF(:nocase, lower(short_url), short_url)
+  WHERE short_url_key IS NOT IIF(:nocase, lower(short_url), short_url)";
+
+pub(super) const SYNC_ALIAS_KEYS: &str = "
+UPDATE aliases SET alias_key = IIF(:nocase, lower(alias), alias)
+  WHERE alias_key IS NOT IIF(:nocase, lower(alias), alias)";
+
+pub(super) const CASE_CONFLICTS: &str = "
+SELECT lower(short_url) AS key, domain FROM (
+  SELECT short_url, domain FROM urls
+  UNION ALL
+  SELECT alias, domain FROM aliases
+)
+  GROUP BY key, domain
+  HAVING COUNT(*) > 1
+  LIMIT 10";
 
 pub(super) const URLS_TABLE_SCHEMA: &str = "
+CREATE TABLE urls (
+  id INTEGER PRIMARY KEY,
+  short_url TEXT NOT NULL,
+  long_url TEXT NOT NULL,
+  hits INTEGER NOT NULL,
+  expiry_time INTEGER,
+  notes TEXT,
+  domain TEXT NOT NULL DEFAULT '',
+  rules TEXT,
+  targets TEXT,
+  sticky INTEGER NOT NULL DEFAULT 0,
+  created_at INTEGER,
+  preview INTEGER NOT NULL DEFAULT 0,
+  opengraph TEXT,
+  health_status INTEGER,
+  health_checked_at INTEGER,
+  health_failures INTEGER NOT NULL DEFAULT 0,
+  disabled INTEGER NOT NULL DEFAULT 0,
+  short_url_key TEXT,
+  updated_at INTEGER,
+  last_accessed_at INTEGER,
+  idle_ttl INTEGER
+)";
+
+// The schema as of migration 3, later migrations add columns on top of this
+pub(super) const URLS_MIGRATION_3_SCHEMA: &str = "
 CREATE TABLE urls (
   id INTEGER PRIMARY KEY,
   short_url TEXT NOT NULL,
@@ -44,8 +229,79 @@ CREATE TABLE urls (
   notes TEXT
 )";
 
+pub(super) const BACKFILL_CREATED_AT: &str = "
+UPDATE urls
+  SET created_at = COALESCE(later.created_at, ?1)
+  FROM (
+    SELECT id, MIN(CASE WHEN created_at > 0 THEN created_at END) OVER (
+      ORDER BY id DESC ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING
+    ) AS created_at
+    FROM urls
+  ) AS later
+  WHERE urls.id = later.id
+    AND (urls.created_at IS NULL OR urls.created_at <= 0)";
+
+// Expired links are kept until their grace period ends
 pub(super) const CLEANUP: &str =
-    "DELETE FROM urls WHERE :now >= expiry_time AND expiry_time IS NOT NULL";
+    "DELETE FROM urls WHERE :cutoff >= expiry_time AND expiry_time IS NOT NULL";
+
+pub(super) const IDEMPOTENCY_TABLE_SCHEMA: &str = "
+CREATE TABLE idempotency_keys (
+  key TEXT PRIMARY KEY,
+  request_hash TEXT NOT NULL,
+  status INTEGER NOT NULL,
+  content_type TEXT NOT NULL,
+  body TEXT NOT NULL,
+  expiry_time INTEGER NOT NULL
+)";
+
+pub(super) const FIND_IDEMPOTENCY_KEY: &str = "
+SELECT request_hash, status, content_type, body FROM idempotency_keys
+  WHERE key = :key AND expiry_time > :now";
+
+pub(super) const ADD_IDEMPOTENCY_KEY: &str = "
+INSERT OR REPLACE INTO idempotency_keys (key, request_hash, status, content_type, body, expiry_time)
+  VALUES (:key, :hash, :status, :content_type, :body, :expiry_time)";
+
+// Links are idle once they haven't been visited or changed for longer than their idle_ttl
+pub(super) const CLEANUP_IDLE: &str = "
+DELETE FROM urls
+  WHERE idle_ttl IS NOT NULL
+    AND MAX(COALESCE(last_accessed_at, 0), COALESCE(updated_at, 0)) + idle_ttl <= :now";
+
+pub(super) const DISABLE_IDLE: &str = "
+UPDATE urls SET disabled = 1
+  WHERE idle_ttl IS NOT NULL
+    AND NOT disabled
+    AND MAX(COALESCE(last_accessed_at, 0), COALESCE(updated_at, 0)) + idle_ttl <= :now";
+
+pub(super) const CLEANUP_IDEMPOTENCY_KEYS: &str =
+    "DELETE FROM idempotency_keys WHERE :now >= expiry_time";
+
+pub(super) const MAINTENANCE_TABLE_SCHEMA: &str = "
+CREATE TABLE maintenance_runs (
+  job TEXT PRIMARY KEY,
+  last_run INTEGER NOT NULL
+)";
+
+pub(super) const RECORD_RUN: &str =
+    "INSERT OR REPLACE INTO maintenance_runs (job, last_run) VALUES (:job, :now)";
+
+pub(super) const LAST_RUNS: &str = "SELECT job, last_run FROM maintenance_runs";
+
+pub(super) const COUNTERS_TABLE_SCHEMA: &str = "
+CREATE TABLE counters (
+  name TEXT PRIMARY KEY,
+  value INTEGER NOT NULL
+)";
+
+// Ids are never handed out twice, even if the link with the largest id gets deleted
+pub(super) const NEXT_LINK_ID: &str = "
+INSERT INTO counters (name, value)
+  VALUES ('link_id', (SELECT COALESCE(MAX(id), 0) + 1 FROM urls))
+ON CONFLICT(name) DO UPDATE
+  SET value = MAX(value, (SELECT COALESCE(MAX(id), 0) FROM urls)) + 1
+RETURNING value";
 
 pub(super) const TABLE_LIST: &str = "
 SELECT type, name FROM sqlite_master
@@ -94,81 +350,162 @@ UPDATE urls
     long_url = :long,
     hits = COALESCE(:hits, hits),
     notes = COALESCE(:notes, notes),
-    expiry_time = COALESCE(:expiry, expiry_time)
-  WHERE short_url = :short
+    expiry_time = COALESCE(:expiry, expiry_time),
+    rules = NULLIF(COALESCE(:rules, rules), '[]'),
+    targets = CASE
+      WHEN :targets IS NOT NULL THEN NULLIF(:targets, '[]')
+      WHEN :hits IS NOT NULL AND targets IS NOT NULL THEN (
+        SELECT json_group_array(json_set(value, '$.hits', 0)) FROM json_each(targets)
+      )
+      ELSE targets
+    END,
+    sticky = COALESCE(:sticky, sticky),
+    preview = COALESCE(:preview, preview),
+    opengraph = NULLIF(COALESCE(:opengraph, opengraph), '{}'),
+    health_status = CASE WHEN long_url = :long THEN health_status END,
+    health_checked_at = CASE WHEN long_url = :long THEN health_checked_at END,
+    health_failures = CASE WHEN long_url = :long THEN health_failures ELSE 0 END,
+    disabled = 0,
+    updated_at = :now,
+    idle_ttl = NULLIF(COALESCE(:idle_ttl, idle_ttl), 0)
+  WHERE short_url_key = :key
+    AND domain = :domain
     AND (expiry_time IS NULL OR expiry_time > :now)
-";
+RETURNING id";
 
+// Aliases have no id column, so the id in their subquery refers to the link
+// The extra columns, the ordering, the cursor condition and the search condition are filled in by getall
 pub(super) const GETALL_QUERIES: [&str; 4] = [
     // 0 => standard
     "
-SELECT short_url, long_url, hits, expiry_time, notes FROM (
-  SELECT t.id, t.short_url, t.long_url, t.hits, t.expiry_time, t.notes
+SELECT short_url, domain, long_url, hits, expiry_time, notes, rules, targets, sticky, created_at,
+  preview, opengraph, health_status, health_checked_at, health_failures, disabled, updated_at,
+  last_accessed_at, idle_ttl{outer_columns},
+  (SELECT json_group_array(alias) FROM aliases WHERE link_id = id) AS aliases FROM (
+  SELECT t.id, t.short_url, t.domain, t.long_url, t.hits, t.expiry_time, t.notes, t.rules,
+    t.targets, t.sticky, t.created_at, t.preview, t.opengraph, t.health_status,
+    t.health_checked_at, t.health_failures, t.disabled, t.updated_at, t.last_accessed_at, t.idle_ttl
+    {inner_columns}
   FROM urls AS t
   WHERE (
     t.expiry_time IS NULL
-    OR t.expiry_time > :now
+    OR t.expiry_time > :expired_after
   ) 
-  ORDER BY t.id DESC
+  AND (:broken IS NULL OR (t.health_failures >= :threshold) = :broken)
+  AND (:created_after IS NULL OR t.created_at >= :created_after)
+  AND (:created_before IS NULL OR t.created_at < :created_before)
+  AND (:updated_after IS NULL OR t.updated_at >= :updated_after)
+  AND (:updated_before IS NULL OR t.updated_at < :updated_before)
+  AND (:accessed_after IS NULL OR t.last_accessed_at >= :accessed_after)
+  AND (:accessed_before IS NULL OR COALESCE(t.last_accessed_at, 0) < :accessed_before)
+  ORDER BY {inner_order}
   LIMIT :size OFFSET :offset
 ) 
-ORDER BY id ASC",
+ORDER BY {outer_order}",
     // 1 => cursor
     "
-SELECT short_url, long_url, hits, expiry_time, notes FROM (
-  SELECT t.id, t.short_url, t.long_url, t.hits, t.expiry_time, t.notes
+SELECT short_url, domain, long_url, hits, expiry_time, notes, rules, targets, sticky, created_at,
+  preview, opengraph, health_status, health_checked_at, health_failures, disabled, updated_at,
+  last_accessed_at, idle_ttl{outer_columns},
+  (SELECT json_group_array(alias) FROM aliases WHERE link_id = id) AS aliases FROM (
+  SELECT t.id, t.short_url, t.domain, t.long_url, t.hits, t.expiry_time, t.notes, t.rules,
+    t.targets, t.sticky, t.created_at, t.preview, t.opengraph, t.health_status,
+    t.health_checked_at, t.health_failures, t.disabled, t.updated_at, t.last_accessed_at, t.idle_ttl
+    {inner_columns}
   FROM urls AS t
   JOIN urls AS u
     ON u.short_url = :pos
+    AND u.domain = :pos_domain
   WHERE
-    t.id < u.id
+    {cursor}
   AND (
     t.expiry_time IS NULL
-    OR t.expiry_time > :now
+    OR t.expiry_time > :expired_after
   ) 
-  ORDER BY t.id DESC
+  AND (:broken IS NULL OR (t.health_failures >= :threshold) = :broken)
+  AND (:created_after IS NULL OR t.created_at >= :created_after)
+  AND (:created_before IS NULL OR t.created_at < :created_before)
+  AND (:updated_after IS NULL OR t.updated_at >= :updated_after)
+  AND (:updated_before IS NULL OR t.updated_at < :updated_before)
+  AND (:accessed_after IS NULL OR t.last_accessed_at >= :accessed_after)
+  AND (:accessed_before IS NULL OR COALESCE(t.last_accessed_at, 0) < :accessed_before)
+  ORDER BY {inner_order}
   LIMIT :size
-) ORDER BY id ASC",
+) ORDER BY {outer_order}",
     // 2 => standard + fts
     "
-SELECT short_url, long_url, hits, expiry_time, notes FROM (
-  SELECT t.id, t.short_url, t.long_url, t.hits, t.expiry_time, t.notes
+SELECT short_url, domain, long_url, hits, expiry_time, notes, rules, targets, sticky, created_at,
+  preview, opengraph, health_status, health_checked_at, health_failures, disabled, updated_at,
+  last_accessed_at, idle_ttl{outer_columns},
+  (SELECT json_group_array(alias) FROM aliases WHERE link_id = id) AS aliases FROM (
+  SELECT t.id, t.short_url, t.domain, t.long_url, t.hits, t.expiry_time, t.notes, t.rules,
+    t.targets, t.sticky, t.created_at, t.preview, t.opengraph, t.health_status,
+    t.health_checked_at, t.health_failures, t.disabled, t.updated_at, t.last_accessed_at, t.idle_ttl
+    {inner_columns}
   FROM urls AS t
-  JOIN urls_fts AS f
-    ON t.id = f.rowid
   WHERE (
     t.expiry_time IS NULL
-    OR t.expiry_time > :now
+    OR t.expiry_time > :expired_after
     )
-  AND urls_fts MATCH :filter
-  ORDER BY t.id DESC
+  AND (:broken IS NULL OR (t.health_failures >= :threshold) = :broken)
+  AND (:created_after IS NULL OR t.created_at >= :created_after)
+  AND (:created_before IS NULL OR t.created_at < :created_before)
+  AND (:updated_after IS NULL OR t.updated_at >= :updated_after)
+  AND (:updated_before IS NULL OR t.updated_at < :updated_before)
+  AND (:accessed_after IS NULL OR t.last_accessed_at >= :accessed_after)
+  AND (:accessed_before IS NULL OR COALESCE(t.last_accessed_at, 0) < :accessed_before)
+  AND {search}
+  ORDER BY {inner_order}
   LIMIT :size OFFSET :offset
 )
-ORDER BY id ASC",
+ORDER BY {outer_order}",
     // 3 => cursor + fts
     "
-SELECT short_url, long_url, hits, expiry_time, notes FROM (
-  SELECT t.id, t.short_url, t.long_url, t.hits, t.expiry_time, t.notes
+SELECT short_url, domain, long_url, hits, expiry_time, notes, rules, targets, sticky, created_at,
+  preview, opengraph, health_status, health_checked_at, health_failures, disabled, updated_at,
+  last_accessed_at, idle_ttl{outer_columns},
+  (SELECT json_group_array(alias) FROM aliases WHERE link_id = id) AS aliases FROM (
+  SELECT t.id, t.short_url, t.domain, t.long_url, t.hits, t.expiry_time, t.notes, t.rules,
+    t.targets, t.sticky, t.created_at, t.preview, t.opengraph, t.health_status,
+    t.health_checked_at, t.health_failures, t.disabled, t.updated_at, t.last_accessed_at, t.idle_ttl
+    {inner_columns}
   FROM urls AS t
   JOIN urls AS u
     ON u.short_url = :pos
-  JOIN urls_fts AS f
-    ON t.id = f.rowid
+    AND u.domain = :pos_domain
   WHERE
-    t.id < u.id
+    {cursor}
     AND (
       t.expiry_time IS NULL
-      OR t.expiry_time > :now
+      OR t.expiry_time > :expired_after
     )
-    AND urls_fts MATCH :filter
-  ORDER BY t.id DESC
+    AND (:broken IS NULL OR (t.health_failures >= :threshold) = :broken)
+    AND (:created_after IS NULL OR t.created_at >= :created_after)
+    AND (:created_before IS NULL OR t.created_at < :created_before)
+    AND (:updated_after IS NULL OR t.updated_at >= :updated_after)
+    AND (:updated_before IS NULL OR t.updated_at < :updated_before)
+    AND (:accessed_after IS NULL OR t.last_accessed_at >= :accessed_after)
+    AND (:accessed_before IS NULL OR COALESCE(t.last_accessed_at, 0) < :accessed_before)
+    AND {search}
+  ORDER BY {inner_order}
   LIMIT :size
 )
-ORDER BY id ASC",
+ORDER BY {outer_order}",
 ];
 
+// Used by getall for ordering by relevance, filled in with the alias of the urls table
+pub(super) const GETALL_RANK: &str = "COALESCE((
+    SELECT bm25(urls_fts) FROM urls_fts WHERE urls_fts MATCH :ranking AND rowid = {alias}.id
+  ), 0)";
+
+pub(super) const GETALL_SNIPPET: &str = ", (
+    SELECT snippet(urls_fts, -1, '<mark>', '</mark>', '…', 32) FROM urls_fts
+      WHERE urls_fts MATCH :ranking AND rowid = id
+  ) AS snippet";
+
 pub(super) const Z_STAT1_INIT: &str = "ANALYZE sqlite_schema;DELETE FROM sqlite_stat1;
 INSERT INTO sqlite_stat1(tbl,idx,stat)VALUES('urls_fts_config','urls_fts_config','1 1'),
 ('urls_fts_docsize',NULL,'110003'),('urls_fts_idx','urls_fts_idx','5977 352 1'),
-('urls','idx_expiry_time','110003 809'),('urls','idx_short_url','110003 1'),
+('urls','idx_expiry_time','110003 809'),('urls','idx_short_url','110003 1 1'),
+('urls','idx_long_url','110003 2'),
 ('urls_fts_data',NULL,'6842');ANALYZE sqlite_schema;";
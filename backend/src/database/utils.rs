@@ -1,36 +1,33 @@
 // SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
 // SPDX-License-Identifier: MIT
 
-use chrono::{Local, Timelike, Utc};
+use chrono::Utc;
 use log::{debug, error, info, warn};
 use rusqlite::{Connection, OpenFlags, named_params};
-use std::{collections::HashSet, fs, path::PathBuf};
+use std::collections::{HashMap, HashSet};
 
-use crate::database::queries;
+use crate::database::{
+    backups::{BackupSettings, init_backup},
+    queries,
+};
 
 // Some constants
-const APPLICATION_ID: i32 = i32::from_be_bytes(*b"chht"); // MUST NEVER BE CHANGED
-const USER_VERSION: u32 = 4; // Should be incremented on change of schema
-
-// Enum for backup types
-enum BackupType {
-    Daily,
-    Init,
-}
-
-// Clean expired links
-pub(crate) fn cleanup(db: &Connection, use_wal_mode: bool) {
+pub(super) const APPLICATION_ID: i32 = i32::from_be_bytes(*b"chht"); // MUST NEVER BE CHANGED
+pub(super) const USER_VERSION: u32 = 14; // Should be incremented on change of schema
+
+// Clean expired links, and the idle ones
+pub(crate) fn cleanup(
+    db: &Connection,
+    use_wal_mode: bool,
+    disable_idle_links: bool,
+    grace_period: i64,
+) {
     let now = Utc::now().timestamp();
     debug!("Starting database cleanup.");
 
-    if Local::now().hour() == 3 {
-        info!("Doing a scheduled daily backup.");
-        manage_backups(db, BackupType::Daily);
-    }
-
     db.prepare_cached(queries::CLEANUP)
         .expect("Error preparing SQL statement for cleanup.")
-        .execute(named_params! {":now" : now})
+        .execute(named_params! {":cutoff" : now - grace_period})
         .inspect(|&u| match u {
             0 => (),
             1 => info!("1 expired link was deleted."),
@@ -38,6 +35,31 @@ pub(crate) fn cleanup(db: &Connection, use_wal_mode: bool) {
         })
         .expect("Error cleaning expired links.");
 
+    let (query, action) = if disable_idle_links {
+        (queries::DISABLE_IDLE, "disabled")
+    } else {
+        (queries::CLEANUP_IDLE, "deleted")
+    };
+    db.prepare_cached(query)
+        .expect("Error preparing SQL statement for idle link cleanup.")
+        .execute(named_params! {":now" : now})
+        .inspect(|&u| match u {
+            0 => (),
+            1 => info!("1 idle link was {action}."),
+            _ => info!("{u} idle links were {action}."),
+        })
+        .expect("Error cleaning idle links.");
+
+    db.prepare_cached(queries::CLEANUP_IDEMPOTENCY_KEYS)
+        .expect("Error preparing SQL statement for idempotency key cleanup.")
+        .execute(named_params! {":now" : now})
+        .inspect(|&u| {
+            if u > 0 {
+                debug!("{u} expired idempotency keys were deleted.")
+            }
+        })
+        .expect("Error cleaning expired idempotency keys.");
+
     if use_wal_mode {
         db.query_one("PRAGMA wal_checkpoint(RESTART)", (), |row| {
             row.get::<usize, isize>(1)
@@ -55,86 +77,61 @@ pub(crate) fn cleanup(db: &Connection, use_wal_mode: bool) {
     if freelist_count > 5000 {
         db.execute("VACUUM", ()).expect("failed to vacuum database");
     }
+}
+
+// Rebuild the database file, reclaiming all the free space
+pub(crate) fn vacuum(db: &Connection) -> bool {
+    db.execute("VACUUM", ())
+        .inspect(|_| info!("Vacuumed the database."))
+        .inspect_err(|e| error!("Unable to vacuum the database: {e}"))
+        .is_ok()
+}
+
+// Small databases keep using the bootstrap statistics
+pub(crate) fn optimize(db: &Connection) -> bool {
     let link_count: i64 = db
         .query_one("SELECT COUNT(id) FROM urls", (), |row| row.get(0))
         .unwrap_or_default();
-    if link_count > 2000 {
-        db.execute("PRAGMA optimize", ())
-            .expect("Unable to optimize database.");
-        debug!("Optimized database.");
+    if link_count <= 2000 {
+        return true;
     }
+    db.execute("PRAGMA optimize", ())
+        .inspect(|_| debug!("Optimized database."))
+        .inspect_err(|e| error!("Unable to optimize the database: {e}"))
+        .is_ok()
 }
 
-// Create backups
-fn manage_backups(db: &Connection, backup_type: BackupType) {
-    let path = db.path().expect("The database path should exist.");
-
-    let db_path = PathBuf::from(path);
-
-    let parent = db_path
-        .parent()
-        .expect("Database should have a parent directory.");
-
-    let db_name = db_path
-        .file_name()
-        .expect("Database should have a file name.")
-        .to_string_lossy();
-
-    let backup_dir = parent.join("backups");
-
-    if let Err(e) = fs::create_dir_all(&backup_dir) {
-        error!("Failed to create backup directory: {e}");
-        return;
-    }
-
-    let (suffix, retain) = match backup_type {
-        BackupType::Init => {
-            info!("Creating an init backup of the database.");
-            ("init", 3)
-        }
-        BackupType::Daily => {
-            info!("Creating a daily backup of the database.");
-            ("daily", 7)
-        }
-    };
-
-    let backup_path = |idx: usize| backup_dir.join(format!("{db_name}.{suffix}{idx}"));
-
-    if let Err(e) = db.backup("main", backup_path(0).to_string_lossy().as_ref(), None) {
-        error!("There was an error while creating the backup: {e}");
-        return;
-    }
-
-    // Migrate legacy backups
-    if matches!(backup_type, BackupType::Daily) {
-        for path in fs::read_dir(parent).into_iter().flatten().flatten() {
-            let path = path.path();
-            if path
-                .file_name()
-                .and_then(|f| f.to_str())
-                .is_some_and(|f| f.starts_with(&format!("{db_name}.bak")))
-            {
-                let filename = path.file_name().unwrap().to_string_lossy();
-                let daily_name = filename.replacen(".bak", ".daily", 1);
-                if let Err(e) = fs::rename(&path, backup_dir.join(daily_name)) {
-                    error!("There was an error migrating legacy backup: {e}");
-                }
-            }
-        }
+// Note down when a maintenance job last succeeded
+pub(crate) fn record_run(db: &Connection, job: &str) {
+    if let Err(e) = db.execute(
+        queries::RECORD_RUN,
+        named_params! {":job": job, ":now": Utc::now().timestamp()},
+    ) {
+        error!("Unable to record the run of the {job} job: {e}");
     }
+}
 
-    for i in (0..retain).rev() {
-        if let Err(e) = fs::rename(backup_path(i), backup_path(i + 1))
-            && e.kind() != std::io::ErrorKind::NotFound
-        {
-            error!("Failed to rotate backup {i}: {e}");
-        }
-    }
+// The time of the last successful run of each maintenance job
+pub(crate) fn last_runs(db: &Connection) -> HashMap<String, i64> {
+    db.prepare_cached(queries::LAST_RUNS)
+        .and_then(|mut statement| {
+            statement
+                .query_map((), |row| Ok((row.get("job")?, row.get("last_run")?)))?
+                .collect()
+        })
+        .inspect_err(|e| error!("Unable to read the maintenance runs: {e}"))
+        .unwrap_or_default()
 }
 
 // Initialize the database
-pub(crate) fn init_db(db: &mut Connection, use_wal_mode: bool, ensure_acid: bool) {
-    manage_backups(db, BackupType::Init);
+pub(crate) fn init_db(
+    db: &mut Connection,
+    use_wal_mode: bool,
+    ensure_acid: bool,
+    case_insensitive: bool,
+    backups: &BackupSettings,
+) {
+    init_backup(db, backups);
 
     info!("Initializing database.");
     let (mut tables, mut indices) = db
@@ -240,7 +237,7 @@ pub(crate) fn init_db(db: &mut Connection, use_wal_mode: bool, ensure_acid: bool
             .expect("Unable to create transaction for migration 2.");
         tx.execute("ALTER TABLE urls RENAME TO urls_old", ())
             .expect("Unable to temporarily rename urls to urls_old.");
-        tx.execute(queries::URLS_TABLE_SCHEMA, ())
+        tx.execute(queries::URLS_MIGRATION_3_SCHEMA, ())
             .expect("Unable to create new urls table.");
         tx.execute(queries::URLS_MIGRATION_3, ())
             .expect("Unable to clone data to the new table.");
@@ -258,13 +255,227 @@ pub(crate) fn init_db(db: &mut Connection, use_wal_mode: bool, ensure_acid: bool
         db.execute("VACUUM", ())
             .expect("failed to vacuum database after migration 3.");
     }
+    // Migration 4: Add domain, and make short_url unique per domain
+    if current_user_version < 5 {
+        info!("Applying migration 4: Add domain column to urls.");
+        let tx = db
+            .transaction()
+            .expect("Unable to create transaction for migration 4.");
+        tx.execute(
+            "ALTER TABLE urls ADD COLUMN domain TEXT NOT NULL DEFAULT ''",
+            (),
+        )
+        .expect("Unable to apply migration 4.");
+        tx.execute("DROP INDEX IF EXISTS idx_short_url", ())
+            .expect("Unable to drop old index on short_url.");
+        indices.remove("idx_short_url");
+        tx.pragma_update(None, "user_version", 5)
+            .expect("Unable to set pragma: user_version.");
+        tx.commit()
+            .expect("Unable to commit transaction for migration 4.");
+    }
+    // Migration 5: Add conditional redirect rules
+    if current_user_version < 6 {
+        info!("Applying migration 5: Add rules column to urls.");
+        let tx = db
+            .transaction()
+            .expect("Unable to create transaction for migration 5.");
+        tx.execute("ALTER TABLE urls ADD COLUMN rules TEXT", ())
+            .expect("Unable to apply migration 5.");
+        tx.pragma_update(None, "user_version", 6)
+            .expect("Unable to set pragma: user_version.");
+        tx.commit()
+            .expect("Unable to commit transaction for migration 5.");
+    }
+    // Migration 6: Add weighted targets
+    if current_user_version < 7 {
+        info!("Applying migration 6: Add targets and sticky columns to urls.");
+        let tx = db
+            .transaction()
+            .expect("Unable to create transaction for migration 6.");
+        tx.execute("ALTER TABLE urls ADD COLUMN targets TEXT", ())
+            .expect("Unable to apply migration 6.");
+        tx.execute(
+            "ALTER TABLE urls ADD COLUMN sticky INTEGER NOT NULL DEFAULT 0",
+            (),
+        )
+        .expect("Unable to apply migration 6.");
+        tx.pragma_update(None, "user_version", 7)
+            .expect("Unable to set pragma: user_version.");
+        tx.commit()
+            .expect("Unable to commit transaction for migration 6.");
+    }
+    // Migration 7: Add creation time and the preview flag
+    if current_user_version < 8 {
+        info!("Applying migration 7: Add created_at and preview columns to urls.");
+        let tx = db
+            .transaction()
+            .expect("Unable to create transaction for migration 7.");
+        tx.execute("ALTER TABLE urls ADD COLUMN created_at INTEGER", ())
+            .expect("Unable to apply migration 7.");
+        tx.execute(
+            "ALTER TABLE urls ADD COLUMN preview INTEGER NOT NULL DEFAULT 0",
+            (),
+        )
+        .expect("Unable to apply migration 7.");
+        tx.pragma_update(None, "user_version", 8)
+            .expect("Unable to set pragma: user_version.");
+        tx.commit()
+            .expect("Unable to commit transaction for migration 7.");
+    }
+    // Migration 8: Add OpenGraph metadata
+    if current_user_version < 9 {
+        info!("Applying migration 8: Add opengraph column to urls.");
+        let tx = db
+            .transaction()
+            .expect("Unable to create transaction for migration 8.");
+        tx.execute("ALTER TABLE urls ADD COLUMN opengraph TEXT", ())
+            .expect("Unable to apply migration 8.");
+        tx.pragma_update(None, "user_version", 9)
+            .expect("Unable to set pragma: user_version.");
+        tx.commit()
+            .expect("Unable to commit transaction for migration 8.");
+    }
+    // Migration 9: Add destination health tracking
+    if current_user_version < 10 {
+        info!("Applying migration 9: Add health columns to urls.");
+        let tx = db
+            .transaction()
+            .expect("Unable to create transaction for migration 9.");
+        tx.execute("ALTER TABLE urls ADD COLUMN health_status INTEGER", ())
+            .expect("Unable to apply migration 9.");
+        tx.execute("ALTER TABLE urls ADD COLUMN health_checked_at INTEGER", ())
+            .expect("Unable to apply migration 9.");
+        tx.execute(
+            "ALTER TABLE urls ADD COLUMN health_failures INTEGER NOT NULL DEFAULT 0",
+            (),
+        )
+        .expect("Unable to apply migration 9.");
+        tx.pragma_update(None, "user_version", 10)
+            .expect("Unable to set pragma: user_version.");
+        tx.commit()
+            .expect("Unable to commit transaction for migration 9.");
+    }
+    // Migration 10: Allow disabling links
+    if current_user_version < 11 {
+        info!("Applying migration 10: Add disabled column to urls.");
+        let tx = db
+            .transaction()
+            .expect("Unable to create transaction for migration 10.");
+        tx.execute(
+            "ALTER TABLE urls ADD COLUMN disabled INTEGER NOT NULL DEFAULT 0",
+            (),
+        )
+        .expect("Unable to apply migration 10.");
+        tx.pragma_update(None, "user_version", 11)
+            .expect("Unable to set pragma: user_version.");
+        tx.commit()
+            .expect("Unable to commit transaction for migration 10.");
+    }
+    // Migration 11: Look up slugs using a separate key, so that the case can be ignored
+    if current_user_version < 12 {
+        info!("Applying migration 11: Add short_url_key column to urls.");
+        let tx = db
+            .transaction()
+            .expect("Unable to create transaction for migration 11.");
+        tx.execute_batch(
+            "ALTER TABLE urls ADD COLUMN short_url_key TEXT;
+            UPDATE urls SET short_url_key = short_url;
+            DROP INDEX IF EXISTS idx_short_url;",
+        )
+        .expect("Unable to apply migration 11.");
+        tx.pragma_update(None, "user_version", 12)
+            .expect("Unable to set pragma: user_version.");
+        tx.commit()
+            .expect("Unable to commit transaction for migration 11.");
+        indices.remove("idx_short_url");
+    }
+    // Migration 12: Track when links were last updated and accessed
+    if current_user_version < 13 {
+        info!("Applying migration 12: Add updated_at and last_accessed_at columns to urls.");
+        let tx = db
+            .transaction()
+            .expect("Unable to create transaction for migration 12.");
+        tx.execute_batch(
+            "ALTER TABLE urls ADD COLUMN updated_at INTEGER;
+            ALTER TABLE urls ADD COLUMN last_accessed_at INTEGER;",
+        )
+        .expect("Unable to apply migration 12.");
+        // Links created before created_at existed can't be newer than the next link with a known
+        // creation time, or the time of this migration if there is none
+        tx.execute(queries::BACKFILL_CREATED_AT, [Utc::now().timestamp()])
+            .expect("Unable to apply migration 12.");
+        tx.execute("UPDATE urls SET updated_at = created_at", ())
+            .expect("Unable to apply migration 12.");
+        tx.pragma_update(None, "user_version", 13)
+            .expect("Unable to set pragma: user_version.");
+        tx.commit()
+            .expect("Unable to commit transaction for migration 12.");
+    }
+    // Migration 13: Allow links to expire after a period of inactivity
+    if current_user_version < 14 {
+        info!("Applying migration 13: Add idle_ttl column to urls.");
+        let tx = db
+            .transaction()
+            .expect("Unable to create transaction for migration 13.");
+        tx.execute("ALTER TABLE urls ADD COLUMN idle_ttl INTEGER", ())
+            .expect("Unable to apply migration 13.");
+        tx.pragma_update(None, "user_version", 14)
+            .expect("Unable to set pragma: user_version.");
+        tx.commit()
+            .expect("Unable to commit transaction for migration 13.");
+    }
 
-    // Create index on short_url for faster lookups
+    // Create index on short_url_key for faster lookups
     if !indices.contains("idx_short_url") {
-        info!("Creating index idx_short_url on urls(short_url).");
-        db.execute("CREATE UNIQUE INDEX idx_short_url ON urls (short_url)", ())
-            .expect("Unable to create index on short_url.");
+        info!("Creating index idx_short_url on urls(short_url_key, domain).");
+        db.execute(
+            "CREATE UNIQUE INDEX idx_short_url ON urls (short_url_key, domain)",
+            (),
+        )
+        .expect("Unable to create index on short_url_key.");
+    }
+
+    // Create the table for extra slugs pointing to links
+    if !tables.contains("aliases") {
+        info!("Creating table aliases.");
+        db.execute_batch(queries::ALIASES_TABLE_SCHEMA)
+            .expect("Unable to create aliases table.");
+    }
+
+    // Slugs differing only by case can't coexist with case-insensitive slugs
+    if case_insensitive {
+        let conflicts: Vec<String> = db
+            .prepare(queries::CASE_CONFLICTS)
+            .expect("Error preparing statement for case conflicts query.")
+            .query_map((), |row| {
+                let (key, domain): (String, String) = (row.get("key")?, row.get("domain")?);
+                Ok(if domain.is_empty() {
+                    key
+                } else {
+                    format!("{domain}/{key}")
+                })
+            })
+            .expect("Error executing case conflicts query.")
+            .filter_map(Result::ok)
+            .collect();
+        assert!(
+            conflicts.is_empty(),
+            "Case-insensitive slugs can't be enabled, as some slugs only differ by case: {}",
+            conflicts.join(", ")
+        );
+        info!("Slugs will be resolved case-insensitively.");
     }
+    db.execute(
+        queries::SYNC_SLUG_KEYS,
+        named_params! {":nocase": case_insensitive},
+    )
+    .expect("Unable to update the slug keys.");
+    db.execute(
+        queries::SYNC_ALIAS_KEYS,
+        named_params! {":nocase": case_insensitive},
+    )
+    .expect("Unable to update the alias keys.");
 
     // Create index on expiry_time for faster lookups
     if !indices.contains("idx_expiry_time") {
@@ -273,6 +484,34 @@ pub(crate) fn init_db(db: &mut Connection, use_wal_mode: bool, ensure_acid: bool
             .expect("Unable to create index on expiry_time.");
     }
 
+    // Create index on long_url for finding duplicates
+    if !indices.contains("idx_long_url") {
+        info!("Creating index idx_long_url on urls(long_url).");
+        db.execute("CREATE INDEX idx_long_url ON urls (long_url)", ())
+            .expect("Unable to create index on long_url.");
+    }
+
+    // Create the table storing responses for idempotency keys
+    if !tables.contains("idempotency_keys") {
+        info!("Creating table idempotency_keys.");
+        db.execute(queries::IDEMPOTENCY_TABLE_SCHEMA, ())
+            .expect("Unable to create idempotency_keys table.");
+    }
+
+    // Create the table keeping track of the maintenance jobs
+    if !tables.contains("maintenance_runs") {
+        info!("Creating table maintenance_runs.");
+        db.execute(queries::MAINTENANCE_TABLE_SCHEMA, ())
+            .expect("Unable to create maintenance_runs table.");
+    }
+
+    // Create the table keeping track of reserved ids
+    if !tables.contains("counters") {
+        info!("Creating table counters.");
+        db.execute(queries::COUNTERS_TABLE_SCHEMA, ())
+            .expect("Unable to create counters table.");
+    }
+
     // Create FTS5 table if it doesn't exist, and also create triggers
     if !tables.contains("urls_fts") {
         info!("Creating FTS table urls_fts, and adding triggers.");
@@ -0,0 +1,168 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+use log::{debug, info};
+use reqwest::{
+    Client, StatusCode, Url,
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect::Policy,
+};
+use rusqlite::Connection;
+use std::{net::IpAddr, sync::Arc};
+use tokio::{
+    net::lookup_host,
+    sync::{Mutex, Semaphore},
+    task::JoinSet,
+    time::{Duration, MissedTickBehavior, interval},
+};
+
+use crate::database;
+
+// Results are written to the database in batches of this size
+const BATCH_SIZE: usize = 100;
+
+// Check if an address is reachable from the public internet
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast())
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+// Hosts given as addresses skip the resolver, so they are checked separately
+fn is_allowed(url: &Url) -> bool {
+    match url.host() {
+        Some(url::Host::Ipv4(ip)) => is_public(IpAddr::V4(ip)),
+        Some(url::Host::Ipv6(ip)) => is_public(IpAddr::V6(ip)),
+        Some(url::Host::Domain(_)) => true,
+        None => false,
+    }
+}
+
+// Resolves names to their public addresses only, so that checks can't reach internal services
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<_> = lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public addresses", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+// Build the HTTP client used for the health checks
+// Unless allowed, private and local addresses are refused, including on redirects
+pub(crate) fn build_client(allow_private: bool) -> Client {
+    let builder = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .user_agent(format!(
+            "Chhoto URL/{} (destination health checker)",
+            env!("CARGO_PKG_VERSION")
+        ));
+    let builder = if allow_private {
+        builder.redirect(Policy::limited(10))
+    } else {
+        builder
+            .dns_resolver(PublicResolver)
+            .redirect(Policy::custom(|attempt| {
+                if attempt.previous().len() > 10 {
+                    attempt.error("too many redirects")
+                } else if !is_allowed(attempt.url()) {
+                    attempt.error("redirected to a private address")
+                } else {
+                    attempt.follow()
+                }
+            }))
+    };
+    builder
+        .build()
+        .expect("Unable to build the HTTP client for health checks.")
+}
+
+// Check a destination, returning the status code, or None if it couldn't be reached
+pub(crate) async fn check_url(client: &Client, allow_private: bool, url: &str) -> Option<u16> {
+    if !allow_private && !Url::parse(url).is_ok_and(|url| is_allowed(&url)) {
+        return None;
+    }
+    // Some servers don't support HEAD requests, so fall back to GET for those
+    let status = match client.head(url).send().await {
+        Ok(resp)
+            if !matches!(
+                resp.status(),
+                StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+            ) =>
+        {
+            resp.status()
+        }
+        Err(err) if err.is_timeout() => return None,
+        _ => client.get(url).send().await.ok()?.status(),
+    };
+    Some(status.as_u16())
+}
+
+// Check every active link once, respecting the concurrency and rate limits
+pub(crate) async fn run_checks(
+    writer: &Mutex<Connection>,
+    client: &Client,
+    allow_private: bool,
+    concurrency: usize,
+    rate: u32,
+) {
+    let links = database::health_check_links(&*writer.lock().await);
+    info!("Running health checks for {} links.", links.len());
+
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let mut ticker = interval(Duration::from_secs(1) / rate);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut tasks = JoinSet::new();
+    let mut results = Vec::with_capacity(BATCH_SIZE);
+    for (id, url) in links {
+        ticker.tick().await;
+        let Ok(permit) = Arc::clone(&semaphore).acquire_owned().await else {
+            break;
+        };
+        let client = client.clone();
+        tasks.spawn(async move {
+            let status = check_url(&client, allow_private, &url).await;
+            drop(permit);
+            debug!("Health check for {url} returned {status:?}.");
+            (id, url, status)
+        });
+        while let Some(Ok(result)) = tasks.try_join_next() {
+            results.push(result);
+        }
+        if results.len() >= BATCH_SIZE {
+            database::update_health(std::mem::take(&mut results), &mut *writer.lock().await);
+        }
+    }
+    while let Some(result) = tasks.join_next().await {
+        if let Ok(result) = result {
+            results.push(result);
+        }
+    }
+    if !results.is_empty() {
+        database::update_health(results, &mut *writer.lock().await);
+    }
+    info!("Finished running health checks.");
+}
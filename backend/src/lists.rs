@@ -0,0 +1,140 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+use log::{info, warn};
+use regex::Regex;
+use std::{
+    collections::HashSet,
+    fs, iter,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+use url::Url;
+
+use crate::config::Config;
+
+// The lists currently in use, swapped out as a whole on reload
+pub(crate) type SharedLists = Arc<RwLock<Arc<DomainLists>>>;
+
+// Domains and patterns read from a list file
+struct ListFile {
+    path: String,
+    modified: Option<SystemTime>,
+    domains: HashSet<String>,
+    patterns: Vec<Regex>,
+}
+
+impl ListFile {
+    // Read a list with one domain or regex:<pattern> per line, and # for comments
+    fn read(path: &str) -> Result<Self, String> {
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+        let mut domains = HashSet::new();
+        let mut patterns = Vec::new();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(pattern) = line.strip_prefix("regex:") {
+                match Regex::new(pattern.trim()) {
+                    Ok(re) => patterns.push(re),
+                    Err(e) => warn!("Ignoring invalid pattern in {path}: {e}"),
+                }
+            } else {
+                domains.insert(line.trim_end_matches('.').to_lowercase());
+            }
+        }
+        info!(
+            "Loaded {} domains and {} patterns from {path}.",
+            domains.len(),
+            patterns.len()
+        );
+        Ok(ListFile {
+            path: path.to_owned(),
+            modified,
+            domains,
+            patterns,
+        })
+    }
+
+    // A domain also matches all of its subdomains, while patterns are matched against the whole URL
+    fn matches(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or_default().trim_end_matches('.');
+        iter::successors(Some(host), |h| h.split_once('.').map(|(_, rest)| rest))
+            .any(|d| self.domains.contains(d))
+            || self.patterns.iter().any(|p| p.is_match(url.as_str()))
+    }
+
+    fn is_stale(&self) -> bool {
+        fs::metadata(&self.path).and_then(|m| m.modified()).ok() != self.modified
+    }
+}
+
+// Block and allow lists for the destinations, the public ones only apply to public mode submissions
+pub(crate) struct DomainLists {
+    block: Option<ListFile>,
+    allow: Option<ListFile>,
+    public_block: Option<ListFile>,
+    public_allow: Option<ListFile>,
+}
+
+impl DomainLists {
+    pub(crate) fn load(config: &Config) -> Result<Self, String> {
+        let read = |path: &Option<String>| path.as_deref().map(ListFile::read).transpose();
+        Ok(DomainLists {
+            block: read(&config.blocklist)?,
+            allow: read(&config.allowlist)?,
+            public_block: read(&config.public_blocklist)?,
+            public_allow: read(&config.public_allowlist)?,
+        })
+    }
+
+    fn files(&self) -> impl Iterator<Item = &ListFile> {
+        [
+            &self.block,
+            &self.allow,
+            &self.public_block,
+            &self.public_allow,
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.files().next().is_none()
+    }
+
+    // Check if any of the files were changed since they were loaded
+    pub(crate) fn is_stale(&self) -> bool {
+        self.files().any(ListFile::is_stale)
+    }
+
+    // Check if a destination may be used, public submissions need to pass both sets of lists
+    pub(crate) fn is_allowed(&self, url: &str, public: bool) -> bool {
+        let Ok(url) = Url::parse(url) else {
+            return false;
+        };
+        let passes = |block: &Option<ListFile>, allow: &Option<ListFile>| {
+            !block.as_ref().is_some_and(|b| b.matches(&url))
+                && allow.as_ref().is_none_or(|a| a.matches(&url))
+        };
+        passes(&self.block, &self.allow)
+            && (!public || passes(&self.public_block, &self.public_allow))
+    }
+
+    // Check if a destination matches the blocklist
+    pub(crate) fn is_blocked(&self, url: &str) -> bool {
+        Url::parse(url).is_ok_and(|url| self.block.as_ref().is_some_and(|b| b.matches(&url)))
+    }
+}
+
+// Get the lists currently in use
+pub(crate) fn current(lists: &SharedLists) -> Arc<DomainLists> {
+    Arc::clone(&lists.read().expect("The domain lists lock is poisoned."))
+}
+
+// Replace the lists in use
+pub(crate) fn replace(lists: &SharedLists, new: DomainLists) {
+    *lists.write().expect("The domain lists lock is poisoned.") = Arc::new(new);
+}
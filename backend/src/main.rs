@@ -9,20 +9,31 @@ use actix_web::{
     middleware,
     web::{self, Redirect},
 };
-use log::info;
+use log::{error, info};
+use maxminddb::Reader;
 use rusqlite::Connection;
 use std::{
     io::Result,
-    sync::{Arc, Once},
+    path::Path,
+    sync::{Arc, Once, RwLock, atomic::AtomicBool},
 };
 use tokio::sync::{Mutex, mpsc};
+use utoipa::OpenApi;
+use utoipa_scalar::{Scalar, Servable};
 
 // Import modules
 mod auth;
 mod background;
 mod config;
 mod database;
+mod health;
+mod lists;
+mod preview;
+mod redirect;
+mod schedule;
+mod search;
 mod services;
+mod slugs;
 
 use services::utils;
 
@@ -32,10 +43,13 @@ mod tests;
 
 // This struct represents state
 struct AppState {
-    hits_tx: mpsc::Sender<(String, bool)>,
+    hits_tx: mpsc::Sender<background::HitUpdate>,
     reader: Connection,
     writer: Arc<Mutex<Connection>>,
     config: config::Config,
+    geoip: Option<Arc<Reader<Vec<u8>>>>,
+    lists: lists::SharedLists,
+    maintenance: Arc<AtomicBool>,
 }
 
 static LOGGER: Once = Once::new();
@@ -79,17 +93,52 @@ async fn main() -> Result<()> {
 
     // Read config from env vars
     let conf = config::read();
+    // Restore a backup and exit, when run as chhoto-url restore <backup>
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    if let [command, backup] = &args[..]
+        && command == "restore"
+    {
+        let mut db = database::open_db(&conf.db_location, false);
+        // Older backups are migrated when the server starts
+        match database::restore(&mut db, Path::new(backup), &conf.backup_settings, true) {
+            Ok(links) => info!("Restored the backup with {links} links."),
+            Err(e) => {
+                error!("Unable to restore the backup. {e}");
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
     // ArcMutex is necessary since the writer is shared across threads
     let writer = Arc::new(Mutex::new(database::open_db(&conf.db_location, false)));
 
     // Initialize the database and perform migrations
-    let use_wal_mode = conf.use_wal_mode;
-    database::init_db(&mut *writer.lock().await, use_wal_mode, conf.ensure_acid);
-    // Spawn cleaner
-    background::spawn_cleaner(Arc::clone(&writer), use_wal_mode);
+    database::init_db(
+        &mut *writer.lock().await,
+        conf.use_wal_mode,
+        conf.ensure_acid,
+        conf.case_insensitive_slugs,
+        &conf.backup_settings,
+    );
+    // Spawn the maintenance jobs
+    background::spawn_maintenance(Arc::clone(&writer), &conf);
+    // Maintenance mode is shared across workers, and is off when the server starts
+    let maintenance = Arc::new(AtomicBool::new(false));
     // Spawn hit updater
-    let (hits_tx, hits_rx) = mpsc::channel::<(String, bool)>(1024);
-    background::spawn_hits_worker(Arc::clone(&writer), hits_rx);
+    let (hits_tx, hits_rx) = mpsc::channel::<background::HitUpdate>(1024);
+    background::spawn_hits_worker(Arc::clone(&writer), hits_rx, Arc::clone(&maintenance));
+    // Spawn destination health checker
+    background::spawn_health_checker(Arc::clone(&writer), &conf);
+    // Load the domain lists, and reload them on change
+    let domain_lists = lists::DomainLists::load(&conf).expect("Unable to load the domain lists.");
+    let domain_lists = Arc::new(RwLock::new(Arc::new(domain_lists)));
+    background::spawn_lists_reloader(Arc::clone(&domain_lists), Arc::clone(&writer), &conf);
+
+    // Load the GeoIP database once, and share it across workers
+    let geoip = conf
+        .geoip_database
+        .as_deref()
+        .map(|path| Arc::new(redirect::open_geoip(path)));
 
     let port = conf.port;
     let addr = conf.listen_address.clone();
@@ -116,14 +165,20 @@ async fn main() -> Result<()> {
                 reader: database::open_db(&conf.db_location, true),
                 writer: Arc::clone(&writer),
                 config: conf.clone(),
+                geoip: geoip.clone(),
+                lists: Arc::clone(&domain_lists),
+                maintenance: Arc::clone(&maintenance),
             }))
+            .wrap(middleware::from_fn(utils::maintenance_guard))
             .wrap(if let Some(header) = &conf.cache_control_header {
                 middleware::DefaultHeaders::new().add(("Cache-Control", header.to_owned()))
             } else {
                 middleware::DefaultHeaders::new()
             })
             .service(services::link_handler)
+            .service(services::preview_handler)
             .service(services::edit_link)
+            .service(services::revive_link)
             .service(services::getall)
             .service(services::siteurl)
             .service(services::version)
@@ -133,7 +188,25 @@ async fn main() -> Result<()> {
             .service(services::login)
             .service(services::logout)
             .service(services::expand)
-            .service(services::whoami);
+            .service(services::available)
+            .service(services::suggest)
+            .service(services::whoami)
+            .service(services::health_report)
+            .service(services::maintenance)
+            .service(services::maintenance_mode)
+            .service(services::restore)
+            .service(services::download_backup)
+            .service(services::openapi_spec)
+            .service(services::v2::list)
+            .service(services::v2::create)
+            .service(services::v2::show)
+            .service(services::v2::create_with_slug)
+            .service(services::v2::update)
+            .service(services::v2::remove);
+
+        if conf.api_docs {
+            app = app.service(Scalar::with_url("/api/docs", services::ApiDoc::openapi()));
+        }
 
         if !conf.disable_frontend {
             if let Some(dir) = &conf.custom_landing_directory {
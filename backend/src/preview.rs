@@ -0,0 +1,144 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use url::Url;
+use utoipa::ToSchema;
+
+use crate::database::FoundLink;
+
+const TEMPLATE: &str = include_str!("preview.html");
+
+// OpenGraph metadata served to link preview crawlers
+#[derive(Serialize, Deserialize, Clone, Default, ToSchema)]
+pub(crate) struct OpenGraph {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) image: Option<String>,
+}
+
+impl OpenGraph {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.title.is_none() && self.description.is_none() && self.image.is_none()
+    }
+}
+
+// Escape text for use inside HTML content and attributes
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Format a unix timestamp for display, 0 means that it's not set
+fn format_time(time: i64, unset: &str) -> String {
+    DateTime::from_timestamp(time, 0)
+        .filter(|_| time > 0)
+        .map_or_else(
+            || unset.to_owned(),
+            |t| t.format("%Y-%m-%d %H:%M UTC").to_string(),
+        )
+}
+
+// Render the preview page of a link pointing to the given destination
+pub(crate) fn render(
+    link: &FoundLink,
+    shorturl: &str,
+    destination: &str,
+    continue_url: &str,
+) -> String {
+    let domain = Url::parse(destination)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_owned))
+        .unwrap_or_default();
+    let caveat = if link.rules.is_empty() && link.targets.is_empty() {
+        ""
+    } else {
+        "The destination of this link can vary between visits and visitors."
+    };
+    let notes = if link.notes.is_empty() {
+        "None"
+    } else {
+        &link.notes
+    };
+    let values = [
+        ("domain", domain),
+        ("shorturl", shorturl.to_owned()),
+        ("destination", destination.to_owned()),
+        ("continue", continue_url.to_owned()),
+        ("notes", notes.to_owned()),
+        ("created", format_time(link.created_at, "Unknown")),
+        ("expiry", format_time(link.expiry_time, "Never")),
+        ("caveat", caveat.to_owned()),
+    ];
+
+    // Fill in the placeholders in a single pass, so that the values can't introduce new ones
+    let mut page = String::with_capacity(TEMPLATE.len() + 512);
+    let mut rest = TEMPLATE;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        page.push_str(&rest[..start]);
+        let key = &rest[start + 2..start + len];
+        if let Some((_, value)) = values.iter().find(|(k, _)| *k == key) {
+            page.push_str(&escape_html(value));
+        }
+        rest = &rest[start + len + 2..];
+    }
+    page.push_str(rest);
+    page
+}
+
+// Render a minimal page with the OpenGraph and Twitter card tags of a link
+pub(crate) fn render_card(card: &OpenGraph, shorturl: &str, destination: &str) -> String {
+    let mut tags = vec![
+        ("property", "og:type", "website"),
+        ("property", "og:url", shorturl),
+    ];
+    if let Some(title) = &card.title {
+        tags.push(("property", "og:title", title));
+        tags.push(("name", "twitter:title", title));
+    }
+    if let Some(description) = &card.description {
+        tags.push(("property", "og:description", description));
+        tags.push(("name", "twitter:description", description));
+    }
+    if let Some(image) = &card.image {
+        tags.push(("property", "og:image", image));
+        tags.push(("name", "twitter:image", image));
+        tags.push(("name", "twitter:card", "summary_large_image"));
+    } else {
+        tags.push(("name", "twitter:card", "summary"));
+    }
+
+    let mut page = String::from("<!doctype html>\n<html>\n<head>\n<meta charset=\"utf-8\" />\n");
+    if let Some(title) = &card.title {
+        page.push_str(&format!("<title>{}</title>\n", escape_html(title)));
+    }
+    for (attr, key, value) in tags {
+        page.push_str(&format!(
+            "<meta {attr}=\"{key}\" content=\"{}\" />\n",
+            escape_html(value)
+        ));
+    }
+    // Anything that isn't actually a crawler still ends up at the destination
+    page.push_str(&format!(
+        "<meta http-equiv=\"refresh\" content=\"0; url={}\" />\n</head>\n</html>\n",
+        escape_html(destination)
+    ));
+    page
+}
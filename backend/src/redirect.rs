@@ -0,0 +1,216 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+use actix_web::{HttpRequest, http::header};
+use log::{error, info};
+use maxminddb::{Reader, geoip2};
+use rand::random_range;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::net::{IpAddr, SocketAddr};
+use utoipa::ToSchema;
+
+// Platforms that can be matched using the user-agent
+pub(crate) const PLATFORMS: [&str; 5] = ["ios", "android", "windows", "macos", "linux"];
+
+// A single conditional redirect rule, all of the provided conditions must match
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub(crate) struct RedirectRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) platform: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) country: Option<String>,
+    pub(crate) target: String,
+}
+
+// A destination for weighted rotation, along with the hits it received
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub(crate) struct WeightedTarget {
+    pub(crate) url: String,
+    pub(crate) weight: u32,
+    #[serde(default)]
+    pub(crate) hits: i64,
+}
+
+// Parse a JSON value as stored in the database
+pub(crate) fn parse_json<T: DeserializeOwned + Default>(value: Option<String>) -> T {
+    value
+        .and_then(|v| {
+            serde_json::from_str(&v)
+                .inspect_err(|e| error!("Unable to parse stored JSON value: {e}"))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+// Open the GeoIP database used for country based rules
+pub(crate) fn open_geoip(path: &str) -> Reader<Vec<u8>> {
+    let reader = Reader::open_readfile(path).expect("Unable to open the GeoIP database.");
+    info!(
+        "Loaded GeoIP database of type {}.",
+        reader.metadata().database_type
+    );
+    reader
+}
+
+// User-agents of the common link preview crawlers
+const CRAWLERS: [&str; 16] = [
+    "facebookexternalhit",
+    "Facebot",
+    "Twitterbot",
+    "Slackbot",
+    "Slack-ImgProxy",
+    "Discordbot",
+    "LinkedInBot",
+    "TelegramBot",
+    "WhatsApp",
+    "Mastodon",
+    "Pleroma",
+    "Akkoma",
+    "Misskey",
+    "redditbot",
+    "SkypeUriPreview",
+    "Iframely",
+];
+
+// Check if the request comes from a link preview crawler
+pub(crate) fn is_crawler(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::USER_AGENT)
+        .and_then(|ua| ua.to_str().ok())
+        .is_some_and(|ua| CRAWLERS.iter().any(|c| ua.contains(c)))
+}
+
+// Guess the platform from the user-agent
+fn get_platform(req: &HttpRequest) -> Option<&'static str> {
+    let ua = req.headers().get(header::USER_AGENT)?.to_str().ok()?;
+    if ["iPhone", "iPad", "iPod"].iter().any(|p| ua.contains(p)) {
+        Some("ios")
+    } else if ua.contains("Android") {
+        Some("android")
+    } else if ua.contains("Windows") {
+        Some("windows")
+    } else if ua.contains("Macintosh") {
+        Some("macos")
+    } else if ua.contains("Linux") {
+        Some("linux")
+    } else {
+        None
+    }
+}
+
+// Get the most preferred language from the Accept-Language header
+fn get_language(req: &HttpRequest) -> Option<String> {
+    let header = req.headers().get(header::ACCEPT_LANGUAGE)?.to_str().ok()?;
+    header
+        .split(',')
+        .filter_map(|part| {
+            let mut parts = part.trim().split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+            (!tag.is_empty() && tag != "*" && quality > 0.0).then_some((tag, quality))
+        })
+        .fold(
+            None,
+            |best: Option<(&str, f32)>, (tag, quality)| match best {
+                Some((_, q)) if q >= quality => best,
+                _ => Some((tag, quality)),
+            },
+        )
+        .map(|(tag, _)| tag.to_lowercase())
+}
+
+// Get the address of the client, which is only taken from X-Forwarded-For for requests from trusted proxies
+// The last entry is used, since earlier ones can be set by the client
+pub(crate) fn client_ip(req: &HttpRequest, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+    let forwarded = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .last()
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.rsplit(',').next())
+        .map(str::trim)
+        .and_then(|addr| {
+            addr.parse::<IpAddr>()
+                .or_else(|_| addr.parse::<SocketAddr>().map(|s| s.ip()))
+                .ok()
+        });
+    Some(forwarded.unwrap_or(peer))
+}
+
+// Look up the country of the client in the GeoIP database
+fn get_country(
+    req: &HttpRequest,
+    geoip: &Reader<Vec<u8>>,
+    trusted_proxies: &[IpAddr],
+) -> Option<String> {
+    let ip = client_ip(req, trusted_proxies)?;
+    let country = geoip.lookup(ip).ok()?.decode::<geoip2::Country>().ok()??;
+    country.country.iso_code.map(|c| c.to_uppercase())
+}
+
+// Find the target of the first matching rule, if any
+pub(crate) fn evaluate_rules<'a>(
+    rules: &'a [RedirectRule],
+    req: &HttpRequest,
+    geoip: Option<&Reader<Vec<u8>>>,
+    trusted_proxies: &[IpAddr],
+) -> Option<&'a str> {
+    if rules.is_empty() {
+        return None;
+    }
+    let platform = get_platform(req);
+    let language = get_language(req);
+    // Only look up the country if some rule needs it
+    let country = geoip
+        .filter(|_| rules.iter().any(|r| r.country.is_some()))
+        .and_then(|g| get_country(req, g, trusted_proxies));
+
+    rules
+        .iter()
+        .find(|rule| {
+            rule.platform
+                .as_deref()
+                .is_none_or(|p| platform.is_some_and(|q| p.eq_ignore_ascii_case(q)))
+                && rule.language.as_deref().is_none_or(|l| {
+                    let l = l.to_lowercase();
+                    language
+                        .as_deref()
+                        .is_some_and(|q| q == l || q.starts_with(&format!("{l}-")))
+                })
+                && rule.country.as_deref().is_none_or(|c| {
+                    country
+                        .as_deref()
+                        .is_some_and(|q| c.eq_ignore_ascii_case(q))
+                })
+        })
+        .map(|rule| rule.target.as_str())
+}
+
+// Pick a target at random based on the weights, honoring a previous pick if it's still valid
+pub(crate) fn pick_target(targets: &[WeightedTarget], previous: Option<usize>) -> Option<usize> {
+    if let Some(idx) = previous.filter(|&i| targets.get(i).is_some_and(|t| t.weight > 0)) {
+        return Some(idx);
+    }
+    let total: u64 = targets.iter().map(|t| u64::from(t.weight)).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut point = random_range(0..total);
+    targets.iter().position(|t| {
+        let weight = u64::from(t.weight);
+        if point < weight {
+            true
+        } else {
+            point -= weight;
+            false
+        }
+    })
+}
@@ -0,0 +1,156 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+// Cron-like schedules for the maintenance jobs, described in docs/INSTALLATION.md
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+
+// Give up looking for the next run after this many years, e.g. for the 30th of February
+const MAX_YEARS: i32 = 8;
+
+// The maintenance jobs which can be scheduled
+#[derive(Clone, Copy)]
+pub(crate) enum Job {
+    Cleanup,
+    Backup,
+    Vacuum,
+    Optimize,
+}
+
+impl Job {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Job::Cleanup => "cleanup",
+            Job::Backup => "backup",
+            Job::Vacuum => "vacuum",
+            Job::Optimize => "optimize",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Schedule {
+    pub(crate) expression: String,
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+pub(crate) fn parse(expression: &str) -> Result<Schedule, String> {
+    let expression = expression.trim();
+    let fields = match expression {
+        "@hourly" => "0 * * * *",
+        "@daily" | "@midnight" => "0 0 * * *",
+        "@weekly" => "0 0 * * 0",
+        "@monthly" => "0 0 1 * *",
+        "@yearly" | "@annually" => "0 0 1 1 *",
+        _ => expression,
+    };
+    let fields: Vec<_> = fields.split_whitespace().collect();
+    let [minute, hour, day, month, weekday] = fields[..] else {
+        return Err(format!("The schedule {expression} doesn't have 5 fields!"));
+    };
+    // Sunday can be either 0 or 7
+    let weekdays = parse_field(weekday, 0, 7, "day of week")?;
+    let schedule = Schedule {
+        expression: expression.to_owned(),
+        minutes: parse_field(minute, 0, 59, "minute")?,
+        hours: parse_field(hour, 0, 23, "hour")? as u32,
+        days: parse_field(day, 1, 31, "day of month")? as u32,
+        months: parse_field(month, 1, 12, "month")? as u16,
+        weekdays: ((weekdays | weekdays >> 7) & 0x7f) as u8,
+        any_day: day == "*",
+        any_weekday: weekday == "*",
+    };
+    if schedule
+        .next_after_naive(NaiveDateTime::default())
+        .is_none()
+    {
+        return Err(format!("The schedule {expression} never runs!"));
+    }
+    Ok(schedule)
+}
+
+// Parse a field into a bitmask of the matching values
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid {name} in the schedule: {field}!");
+    let number = |s: &str| {
+        s.parse::<u32>()
+            .ok()
+            .filter(|n| (min..=max).contains(n))
+            .ok_or_else(invalid)
+    };
+    let mut mask = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|&s| s > 0)),
+            None => (part, Some(1)),
+        };
+        let step = step.ok_or_else(invalid)?;
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            // A single value with a step runs from there until the end
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+impl Schedule {
+    // The first time after the given one when the job should run, in local time
+    pub(crate) fn next_after(&self, time: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut time = time.naive_local();
+        loop {
+            time = self.next_after_naive(time)?;
+            // Times skipped by a change to daylight saving time are left out
+            if let Some(next) = Local.from_local_datetime(&time).earliest() {
+                return Some(next);
+            }
+        }
+    }
+
+    pub(crate) fn next_after_naive(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let limit = time.year() + MAX_YEARS;
+        let mut time = time.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        while time.year() <= limit {
+            let date = time.date();
+            if self.months & (1 << date.month()) == 0 {
+                let (year, month) = if date.month() == 12 {
+                    (date.year() + 1, 1)
+                } else {
+                    (date.year(), date.month() + 1)
+                };
+                time = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.day_matches(date) {
+                time = date.succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hours & (1 << time.hour()) == 0 {
+                time = date.and_hms_opt(time.hour(), 0, 0)? + Duration::hours(1);
+            } else if self.minutes & (1 << time.minute()) == 0 {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
@@ -0,0 +1,347 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+// The query language used for filtering links in /api/all, described in docs/CLI.md
+use chrono::NaiveDate;
+use rusqlite::types::Value;
+
+const MAX_TERMS: usize = 32;
+const MAX_TOKENS: usize = 128;
+const MAX_DEPTH: usize = 8;
+
+// Fields that can be searched for text, with the matching columns of urls_fts
+const TEXT_FIELDS: [(&str, &str); 3] = [
+    ("long", "long_url"),
+    ("short", "short_url"),
+    ("notes", "notes"),
+];
+
+// Fields that can be compared, with the matching columns of urls
+const NUMBER_FIELDS: [(&str, &str); 3] = [
+    ("hits", "hits"),
+    ("expires", "expiry_time"),
+    ("created", "created_at"),
+];
+
+// A parsed query, as an SQL condition on urls (aliased as t) along with its parameters
+pub(crate) struct Search {
+    pub(crate) condition: String,
+    pub(crate) params: Vec<(String, Value)>,
+    // FTS5 query matching any of the text that isn't negated, used for ranking the results
+    pub(crate) ranking: Option<String>,
+}
+
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Text {
+        column: Option<&'static str>,
+        text: String,
+    },
+    Compare {
+        field: &'static str,
+        column: &'static str,
+        op: &'static str,
+        value: String,
+    },
+}
+
+pub(crate) fn parse(query: &str, nocase: bool) -> Result<Search, String> {
+    let tokens = tokenize(query)?;
+    if tokens.is_empty() {
+        return Err("The filter is empty!".to_owned());
+    } else if tokens.len() > MAX_TOKENS {
+        return Err("The filter is too long!".to_owned());
+    }
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        depth: 0,
+        params: Vec::new(),
+        nocase,
+        negated: false,
+        ranked: Vec::new(),
+    };
+    let condition = parser.parse_or()?;
+    if parser.pos < parser.tokens.len() {
+        return Err("Unmatched closing parenthesis in the filter!".to_owned());
+    }
+    Ok(Search {
+        condition,
+        params: parser.params,
+        ranking: (!parser.ranked.is_empty()).then(|| parser.ranked.join(" OR ")),
+    })
+}
+
+fn read_quoted(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    chars.next();
+    let mut text = String::new();
+    for c in chars.by_ref() {
+        if c == '"' {
+            return Ok(text);
+        }
+        text.push(c);
+    }
+    Err("Unmatched quote in the filter!".to_owned())
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, String> {
+    if query.chars().any(char::is_control) {
+        return Err("The filter contains invalid characters!".to_owned());
+    }
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => tokens.push(Token::Text {
+                column: None,
+                text: read_quoted(&mut chars)?,
+            }),
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek()
+                    && !c.is_whitespace()
+                    && !matches!(c, '(' | ')' | '"')
+                {
+                    word.push(c);
+                    chars.next();
+                }
+                // A field name directly followed by a quote limits the quoted text to that field
+                if let Some(field) = word.strip_suffix(':')
+                    && chars.peek() == Some(&'"')
+                {
+                    let column = text_column(field)
+                        .ok_or_else(|| format!("Unknown field in the filter: {field}!"))?;
+                    tokens.push(Token::Text {
+                        column: Some(column),
+                        text: read_quoted(&mut chars)?,
+                    });
+                } else {
+                    tokens.push(word_token(word)?);
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn text_column(field: &str) -> Option<&'static str> {
+    TEXT_FIELDS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(field))
+        .map(|&(_, column)| column)
+}
+
+fn word_token(word: String) -> Result<Token, String> {
+    match word.as_str() {
+        "AND" => return Ok(Token::And),
+        "OR" => return Ok(Token::Or),
+        "NOT" => return Ok(Token::Not),
+        _ => (),
+    }
+    if let Some((field, text)) = word.split_once(':')
+        && let Some(column) = text_column(field)
+    {
+        return Ok(Token::Text {
+            column: Some(column),
+            text: text.to_owned(),
+        });
+    }
+    for &(field, column) in &NUMBER_FIELDS {
+        let Some(rest) = word
+            .get(..field.len())
+            .filter(|w| w.eq_ignore_ascii_case(field))
+            .map(|_| &word[field.len()..])
+        else {
+            continue;
+        };
+        // Longer operators are checked first, since they start with the shorter ones
+        if let Some(op) = [">=", "<=", ">", "<", "="]
+            .into_iter()
+            .find(|op| rest.starts_with(op))
+        {
+            return Ok(Token::Compare {
+                field,
+                column,
+                op,
+                value: rest[op.len()..].to_owned(),
+            });
+        }
+    }
+    Ok(Token::Text {
+        column: None,
+        text: word,
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+    params: Vec<(String, Value)>,
+    nocase: bool,
+    negated: bool,
+    ranked: Vec<String>,
+}
+
+impl Parser {
+    // OR has the lowest precedence
+    fn parse_or(&mut self) -> Result<String, String> {
+        let mut parts = vec![self.parse_and()?];
+        while let Some(Token::Or) = self.tokens.get(self.pos) {
+            self.pos += 1;
+            parts.push(self.parse_and()?);
+        }
+        Ok(join(parts, " OR "))
+    }
+
+    // Terms next to each other are combined using AND
+    fn parse_and(&mut self) -> Result<String, String> {
+        let mut parts = vec![self.parse_not()?];
+        loop {
+            match self.tokens.get(self.pos) {
+                Some(Token::And) => self.pos += 1,
+                Some(Token::Or | Token::Close) | None => break,
+                Some(_) => (),
+            }
+            parts.push(self.parse_not()?);
+        }
+        Ok(join(parts, " AND "))
+    }
+
+    fn parse_not(&mut self) -> Result<String, String> {
+        if let Some(Token::Not) = self.tokens.get(self.pos) {
+            self.pos += 1;
+            self.negated = !self.negated;
+            let inner = self.parse_not()?;
+            self.negated = !self.negated;
+            return Ok(format!("NOT {inner}"));
+        }
+        self.parse_term()
+    }
+
+    fn parse_term(&mut self) -> Result<String, String> {
+        let Some(token) = self.tokens.get(self.pos) else {
+            return Err("The filter ends where a search term was expected!".to_owned());
+        };
+        self.pos += 1;
+        match token {
+            Token::Open => {
+                self.depth += 1;
+                if self.depth > MAX_DEPTH {
+                    return Err("The filter has too many nested parentheses!".to_owned());
+                }
+                let inner = self.parse_or()?;
+                let Some(Token::Close) = self.tokens.get(self.pos) else {
+                    return Err("Unmatched opening parenthesis in the filter!".to_owned());
+                };
+                self.pos += 1;
+                self.depth -= 1;
+                Ok(format!("({inner})"))
+            }
+            Token::Close => Err("Unmatched closing parenthesis in the filter!".to_owned()),
+            Token::And | Token::Or | Token::Not => {
+                Err("An operator in the filter is missing a search term!".to_owned())
+            }
+            Token::Text { column, text } => {
+                if text.is_empty() {
+                    return Err("A search term in the filter is empty!".to_owned());
+                } else if text.chars().count() < 3 {
+                    if column.is_some_and(|c| c != "short_url") {
+                        return Err(format!(
+                            "Search terms need to be at least 3 characters long: {text}!"
+                        ));
+                    }
+                    // Keys are lowercase when slugs are case-insensitive, so that the index can be used
+                    let start = if self.nocase {
+                        text.to_lowercase()
+                    } else {
+                        text.clone()
+                    };
+                    let end = format!("{start}{}", char::MAX);
+                    let start = self.param(Value::Text(start))?;
+                    let end = self.param(Value::Text(end))?;
+                    return Ok(format!(
+                        "(t.short_url_key >= {start} AND t.short_url_key < {end})"
+                    ));
+                }
+                // FTS5 strings are quoted, and the quotes within them are doubled
+                let text = format!("\"{}\"", text.replace('"', "\"\""));
+                let pattern = match column {
+                    Some(column) => format!("{column} : {text}"),
+                    None => text,
+                };
+                if !self.negated {
+                    self.ranked.push(pattern.clone());
+                }
+                let name = self.param(Value::Text(pattern))?;
+                Ok(format!(
+                    "t.id IN (SELECT rowid FROM urls_fts WHERE urls_fts MATCH {name})"
+                ))
+            }
+            Token::Compare {
+                field,
+                column,
+                op,
+                value,
+            } => {
+                let (field, column, op) = (*field, *column, *op);
+                if let Ok(number) = value.parse::<i64>() {
+                    let name = self.param(Value::Integer(number))?;
+                    return Ok(format!("IFNULL(t.{column} {op} {name}, FALSE)"));
+                }
+                let date = (field != "hits")
+                    .then(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok())
+                    .flatten()
+                    .and_then(|d| d.and_hms_opt(0, 0, 0))
+                    .map(|d| d.and_utc().timestamp())
+                    .ok_or_else(|| format!("Invalid value for {field} in the filter: {value}!"))?;
+                // Dates cover the whole day, in UTC
+                let (start, end) = (date, date + 86_400);
+                let condition = match op {
+                    ">" => format!("t.{column} >= {}", self.param(Value::Integer(end))?),
+                    ">=" => format!("t.{column} >= {}", self.param(Value::Integer(start))?),
+                    "<" => format!("t.{column} < {}", self.param(Value::Integer(start))?),
+                    "<=" => format!("t.{column} < {}", self.param(Value::Integer(end))?),
+                    _ => format!(
+                        "t.{column} >= {} AND t.{column} < {}",
+                        self.param(Value::Integer(start))?,
+                        self.param(Value::Integer(end))?
+                    ),
+                };
+                Ok(format!("IFNULL({condition}, FALSE)"))
+            }
+        }
+    }
+
+    fn param(&mut self, value: Value) -> Result<String, String> {
+        if self.params.len() >= MAX_TERMS {
+            return Err("The filter has too many search terms!".to_owned());
+        }
+        let name = format!(":search{}", self.params.len());
+        self.params.push((name.clone(), value));
+        Ok(name)
+    }
+}
+
+fn join(parts: Vec<String>, separator: &str) -> String {
+    if parts.len() == 1 {
+        parts.into_iter().collect()
+    } else {
+        format!("({})", parts.join(separator))
+    }
+}
@@ -8,15 +8,20 @@ use log::info;
 use crate::{
     AppState,
     auth::Auth,
-    services::types::{
-        ChhotoError::{ClientError, ServerError},
-        JSONResponse,
-    },
+    services::types::{ChhotoError::ServerError, DomainParams, JSONResponse},
     utils,
 };
 
 // Handle logout
 // There's no reason to be calling this route with an API key
+#[utoipa::path(
+    tag = "auth",
+    responses(
+        (status = 200, description = "Logged out", body = String, content_type = "text/plain"),
+        (status = 401, description = "Not logged in", body = String, content_type = "text/plain"),
+    ),
+    security(("session" = []))
+)]
 #[delete("/api/logout")]
 pub(crate) async fn logout(session: Session) -> HttpResponse {
     if session.remove("chhoto-url-auth").is_some() {
@@ -32,9 +37,20 @@ pub(crate) async fn logout(session: Session) -> HttpResponse {
 }
 
 // Delete a given shortlink
+#[utoipa::path(
+    tag = "links",
+    params(("shortlink" = String, Path), DomainParams),
+    responses(
+        (status = 200, description = "The link or alias was deleted", body = JSONResponse),
+        (status = 401, description = "Not authorized", body = JSONResponse),
+        (status = 404, description = "The shortlink does not exist", body = JSONResponse),
+    ),
+    security(("api_key" = []), ("session" = []))
+)]
 #[delete("/api/del/{shortlink}")]
 pub(crate) async fn delete_link(
     shortlink: web::Path<String>,
+    params: web::Query<DomainParams>,
     auth: Auth,
     data: web::Data<AppState>,
 ) -> HttpResponse {
@@ -42,8 +58,9 @@ pub(crate) async fn delete_link(
         Auth::ValidAPIKey => {
             match utils::delete_link_helper(
                 &shortlink,
+                &params.domain,
                 &*data.writer.lock().await,
-                data.config.allow_capital_letters,
+                &data.config,
             ) {
                 Ok(()) => {
                     let response = JSONResponse {
@@ -61,11 +78,11 @@ pub(crate) async fn delete_link(
                     };
                     HttpResponse::InternalServerError().json(response)
                 }
-                Err(ClientError { reason }) => {
+                Err(error) => {
                     let response = JSONResponse {
                         success: false,
                         error: true,
-                        reason,
+                        reason: error.reason(),
                     };
                     HttpResponse::NotFound().json(response)
                 }
@@ -76,8 +93,9 @@ pub(crate) async fn delete_link(
         Auth::ValidSession => {
             if utils::delete_link_helper(
                 &shortlink,
+                &params.domain,
                 &*data.writer.lock().await,
-                data.config.allow_capital_letters,
+                &data.config,
             )
             .is_ok()
             {
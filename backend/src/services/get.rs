@@ -3,24 +3,41 @@
 
 use actix_files::NamedFile;
 use actix_web::{
-    Either, HttpResponse, Responder, get,
-    http::StatusCode,
-    web::{self, Redirect},
+    Either, HttpRequest, HttpResponse, Responder,
+    cookie::{Cookie, SameSite, time::Duration},
+    get,
+    http::{
+        StatusCode,
+        header::{self, ContentDisposition, DispositionParam, DispositionType},
+    },
+    web,
 };
+use chrono::Local;
+use log::error;
 
 use crate::{
     AppState,
     auth::Auth,
-    database,
+    database::{self, DBRow, HealthReport},
+    preview, redirect,
     services::types::{
-        BackendConfig,
-        ChhotoError::{ClientError, ServerError},
-        GetReqParams,
+        BackendConfig, BackupParams, ChhotoError::ServerError, DomainParams, GetReqParams,
+        JSONResponse, MaintenanceJob, SlugAvailability, SlugSuggestions, SuggestParams,
     },
     utils,
 };
 
 // Return all active links
+#[utoipa::path(
+    tag = "links",
+    params(GetReqParams),
+    responses(
+        (status = 200, description = "The matching links", body = [DBRow]),
+        (status = 400, description = "Invalid parameters", body = String, content_type = "text/plain"),
+        (status = 401, description = "Not authorized", body = String, content_type = "text/plain"),
+    ),
+    security(("api_key" = []), ("session" = []))
+)]
 #[get("/api/all")]
 pub(crate) async fn getall(
     auth: Auth,
@@ -34,21 +51,229 @@ pub(crate) async fn getall(
         Auth::InvalidAPIKey { result } => HttpResponse::Unauthorized()
             .content_type("text/plain")
             .body(result.reason),
-        _ => match utils::getall_helper(&data.reader, params.into_inner()) {
+        _ => match utils::getall_helper(&data.reader, params.into_inner(), &data.config) {
             Ok(s) => HttpResponse::Ok().content_type("application/json").body(s),
             Err(ServerError) => HttpResponse::InternalServerError()
                 .content_type("text/plain")
                 .body("Something went wrong while loading the links.".to_owned()),
-            Err(ClientError { reason }) => HttpResponse::BadRequest()
+            Err(error) => HttpResponse::BadRequest()
                 .content_type("text/plain")
-                .body(reason),
+                .body(error.reason()),
         },
     }
 }
 
+// Summarize the results of the destination health checks
+#[utoipa::path(
+    tag = "links",
+    responses(
+        (status = 200, description = "Summary of the health checks", body = HealthReport),
+        (status = 401, description = "Not authorized", body = JSONResponse),
+    ),
+    security(("api_key" = []), ("session" = []))
+)]
+#[get("/api/health-report")]
+pub(crate) async fn health_report(auth: Auth, data: web::Data<AppState>) -> HttpResponse {
+    match auth {
+        Auth::ValidAPIKey | Auth::ValidSession => {
+            match database::health_report(&data.reader, data.config.health_failure_threshold) {
+                Ok(report) => HttpResponse::Ok().json(report),
+                Err(_) => HttpResponse::InternalServerError()
+                    .content_type("text/plain")
+                    .body("Something went wrong while creating the health report."),
+            }
+        }
+        Auth::None { result } | Auth::InvalidAPIKey { result } => {
+            HttpResponse::Unauthorized().json(result)
+        }
+    }
+}
+
+// List the maintenance jobs, along with when they last ran and will run next
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "The maintenance jobs", body = [MaintenanceJob]),
+        (status = 401, description = "Not authorized", body = JSONResponse),
+    ),
+    security(("api_key" = []), ("session" = []))
+)]
+#[get("/api/maintenance")]
+pub(crate) async fn maintenance(auth: Auth, data: web::Data<AppState>) -> HttpResponse {
+    match auth {
+        Auth::ValidAPIKey | Auth::ValidSession => {
+            let mut last_runs = database::last_runs(&data.reader);
+            let now = Local::now();
+            let jobs: Vec<_> = data
+                .config
+                .schedules()
+                .into_iter()
+                .map(|(job, schedule)| MaintenanceJob {
+                    job: job.name(),
+                    schedule: schedule.map(|s| s.expression.clone()),
+                    last_run: last_runs.remove(job.name()),
+                    next_run: schedule
+                        .and_then(|s| s.next_after(now))
+                        .map(|t| t.timestamp()),
+                })
+                .collect();
+            HttpResponse::Ok().json(jobs)
+        }
+        Auth::None { result } | Auth::InvalidAPIKey { result } => {
+            HttpResponse::Unauthorized().json(result)
+        }
+    }
+}
+
+// Download a snapshot of the database, optionally compressed using gzip
+#[utoipa::path(
+    tag = "admin",
+    params(BackupParams),
+    responses(
+        (status = 200, description = "The snapshot of the database", content_type = "application/octet-stream"),
+        (status = 401, description = "Not authorized", body = JSONResponse),
+        (status = 500, description = "The snapshot could not be created", body = JSONResponse),
+    ),
+    security(("api_key" = []), ("session" = []))
+)]
+#[get("/api/admin/backup")]
+pub(crate) async fn download_backup(
+    params: web::Query<BackupParams>,
+    auth: Auth,
+    data: web::Data<AppState>,
+) -> Either<NamedFile, HttpResponse> {
+    match auth {
+        Auth::ValidAPIKey | Auth::ValidSession => {
+            let compress = params.compress.unwrap_or_default();
+            // The snapshot is taken using the reader, so that writes aren't held up
+            let snapshot = database::snapshot_for_download(&data.reader);
+            let file = match snapshot {
+                Ok(path) => web::block(move || database::open_snapshot(&path, compress))
+                    .await
+                    .unwrap_or_else(|e| Err(e.to_string())),
+                Err(e) => Err(e),
+            };
+            let name = format!(
+                "chhoto-url-{}.sqlite{}",
+                Local::now().format("%Y%m%d-%H%M%S"),
+                if compress { ".gz" } else { "" }
+            );
+            match file.and_then(|f| NamedFile::from_file(f, &name).map_err(|e| e.to_string())) {
+                Ok(file) => Either::Left(file.set_content_disposition(ContentDisposition {
+                    disposition: DispositionType::Attachment,
+                    parameters: vec![DispositionParam::Filename(name)],
+                })),
+                Err(e) => {
+                    error!("Unable to create the backup for downloading. {e}");
+                    Either::Right(HttpResponse::InternalServerError().json(JSONResponse {
+                        success: false,
+                        error: true,
+                        reason: "Something went wrong when creating the backup.".to_owned(),
+                    }))
+                }
+            }
+        }
+        Auth::None { result } | Auth::InvalidAPIKey { result } => {
+            Either::Right(HttpResponse::Unauthorized().json(result))
+        }
+    }
+}
+
+// Check whether a shortlink can be used for a new link
+#[utoipa::path(
+    tag = "links",
+    params(("shortlink" = String, Path), DomainParams),
+    responses(
+        (status = 200, description = "Whether the shortlink can be used", body = SlugAvailability),
+        (status = 400, description = "Invalid domain", body = JSONResponse),
+        (status = 401, description = "Not authorized", body = JSONResponse),
+    ),
+    security(("api_key" = []), ("session" = []), ())
+)]
+#[get("/api/available/{shortlink}")]
+pub(crate) async fn available(
+    shortlink: web::Path<String>,
+    params: web::Query<DomainParams>,
+    auth: Auth,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let config = &data.config;
+    // Public mode users can add links, so they can check shortlinks too
+    match auth {
+        Auth::InvalidAPIKey { result } => return HttpResponse::Unauthorized().json(result),
+        Auth::None { result } if !config.public_mode => {
+            return HttpResponse::Unauthorized().json(result);
+        }
+        _ => (),
+    }
+    let Some(domain) = config.normalize_domain(&params.domain) else {
+        return HttpResponse::BadRequest().json(JSONResponse {
+            success: false,
+            error: true,
+            reason: "Invalid domain!".to_owned(),
+        });
+    };
+    match utils::check_availability(&shortlink, &domain, config, &data.reader) {
+        Ok(availability) => HttpResponse::Ok().json(availability),
+        Err(_) => HttpResponse::InternalServerError().json(JSONResponse {
+            success: false,
+            error: true,
+            reason: "Something went wrong while checking the shortlink.".to_owned(),
+        }),
+    }
+}
+
+// Suggest some free shortlinks
+#[utoipa::path(
+    tag = "links",
+    params(SuggestParams),
+    responses(
+        (status = 200, description = "Free shortlinks", body = SlugSuggestions),
+        (status = 400, description = "Invalid parameters", body = JSONResponse),
+        (status = 401, description = "Not authorized", body = JSONResponse),
+    ),
+    security(("api_key" = []), ("session" = []), ())
+)]
+#[get("/api/suggest")]
+pub(crate) async fn suggest(
+    params: web::Query<SuggestParams>,
+    auth: Auth,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let config = &data.config;
+    match auth {
+        Auth::InvalidAPIKey { result } => return HttpResponse::Unauthorized().json(result),
+        Auth::None { result } if !config.public_mode => {
+            return HttpResponse::Unauthorized().json(result);
+        }
+        _ => (),
+    }
+    match utils::suggest_slugs(params.into_inner(), config, &data.reader) {
+        Ok(suggestions) => HttpResponse::Ok().json(SlugSuggestions {
+            success: true,
+            error: false,
+            suggestions,
+        }),
+        Err(ServerError) => HttpResponse::InternalServerError().json(JSONResponse {
+            success: false,
+            error: true,
+            reason: "Something went wrong while suggesting shortlinks.".to_owned(),
+        }),
+        Err(error) => HttpResponse::BadRequest().json(JSONResponse {
+            success: false,
+            error: true,
+            reason: error.reason(),
+        }),
+    }
+}
+
 // Get the site URL
 // This is deprecated, and might be removed in the future.
 // Use /api/getconfig instead
+#[utoipa::path(
+    tag = "config",
+    responses((status = 200, description = "The site URL, or unset", body = String, content_type = "text/plain"))
+)]
 #[get("/api/siteurl")]
 pub(crate) async fn siteurl(data: web::Data<AppState>) -> HttpResponse {
     if let Some(url) = &data.config.site_url {
@@ -63,6 +288,10 @@ pub(crate) async fn siteurl(data: web::Data<AppState>) -> HttpResponse {
 // Get the version number
 // This is deprecated, and might be removed in the future.
 // Use /api/getconfig instead
+#[utoipa::path(
+    tag = "config",
+    responses((status = 200, description = "The version", body = String, content_type = "text/plain"))
+)]
 #[get("/api/version")]
 pub(crate) async fn version() -> HttpResponse {
     HttpResponse::Ok()
@@ -71,6 +300,13 @@ pub(crate) async fn version() -> HttpResponse {
 }
 
 // Get the user's current role
+#[utoipa::path(
+    tag = "auth",
+    responses(
+        (status = 200, description = "One of admin, public or nobody", body = String, content_type = "text/plain"),
+    ),
+    security(("api_key" = []), ("session" = []), ())
+)]
 #[get("/api/whoami")]
 pub(crate) async fn whoami(data: web::Data<AppState>, auth: Auth) -> HttpResponse {
     let config = &data.config;
@@ -90,18 +326,30 @@ pub(crate) async fn whoami(data: web::Data<AppState>, auth: Auth) -> HttpRespons
 }
 
 // Get some useful backend config
+#[utoipa::path(
+    tag = "config",
+    responses(
+        (status = 200, description = "The backend config", body = BackendConfig),
+        (status = 401, description = "Not authorized", body = JSONResponse),
+    ),
+    security(("api_key" = []), ("session" = []), ())
+)]
 #[get("/api/getconfig")]
 pub(crate) async fn getconfig(auth: Auth, data: web::Data<AppState>) -> HttpResponse {
     let config = &data.config;
     let ok_response = || {
         let backend_config = BackendConfig {
             version: utils::get_version(),
-            allow_capital_letters: config.allow_capital_letters,
+            allow_capital_letters: config.allow_mixed_case(),
             public_mode: config.public_mode,
             public_mode_expiry_delay: config.public_mode_expiry_delay.unwrap_or_default(),
             allowed_protocols: config.allowed_protocols.clone(),
             site_url: config.site_url.clone(),
-            slug_style: config.slug_style.to_string(),
+            extra_site_urls: config.extra_site_urls.clone(),
+            slug_style: match config.sequential_slugs {
+                Some(_) => String::from("Sequential"),
+                None => config.slug_style.to_string(),
+            },
             slug_length: config.slug_length,
             try_longer_slug: config.try_longer_slug,
             frontend_page_size: config.frontend_page_size,
@@ -120,28 +368,151 @@ pub(crate) async fn getconfig(auth: Auth, data: web::Data<AppState>) -> HttpResp
     }
 }
 
+// Show the preview page of a shortlink
+#[utoipa::path(
+    tag = "redirect",
+    responses(
+        (status = 200, description = "The preview page", body = String, content_type = "text/html"),
+        (status = 404, description = "The shortlink does not exist"),
+    )
+)]
+#[get("/preview/{shortlink}")]
+pub(crate) async fn preview_handler(
+    shortlink: web::Path<String>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    serve_link(&shortlink, &req, &data, true).await
+}
+
 // Handle a given shortlink
+#[utoipa::path(
+    tag = "redirect",
+    responses(
+        (status = 307, description = "Temporary redirection to the destination"),
+        (status = 308, description = "Permanent redirection to the destination"),
+        (status = 200, description = "The preview page, or the OpenGraph tags for crawlers", body = String, content_type = "text/html"),
+        (status = 404, description = "The shortlink does not exist"),
+    )
+)]
 #[get("/{shortlink}")]
 pub(crate) async fn link_handler(
     shortlink: web::Path<String>,
+    req: HttpRequest,
     data: web::Data<AppState>,
 ) -> impl Responder {
-    let shortlink_str = shortlink.as_str();
-    if let Ok(longlink) =
-        database::find_and_add_hit(shortlink_str, &data.reader, &data.hits_tx).await
-    {
-        if data.config.use_temp_redirect {
-            Either::Left(Redirect::to(longlink))
-        } else {
-            // Defaults to permanent redirection
-            Either::Left(Redirect::to(longlink).permanent())
-        }
-    } else {
-        Either::Right(
+    // A trailing + also asks for the preview page
+    match shortlink.strip_suffix('+') {
+        Some(slug) => serve_link(slug, &req, &data, true).await,
+        None => serve_link(&shortlink, &req, &data, false).await,
+    }
+}
+
+// Redirect to the destination of a shortlink, or show its preview page
+// Previews requested explicitly don't count as hits
+async fn serve_link(
+    shortlink: &str,
+    req: &HttpRequest,
+    data: &AppState,
+    preview_only: bool,
+) -> impl Responder + use<> {
+    // Requests coming in through unknown hosts are resolved against the default domain
+    let domain = data
+        .config
+        .normalize_domain(req.connection_info().host())
+        .unwrap_or_default();
+    let Ok(link) = database::find_link(
+        shortlink,
+        &domain,
+        data.config.case_insensitive_slugs,
+        &data.reader,
+    ) else {
+        return Either::Right(
             NamedFile::open_async("./frontend/static/404.html")
                 .await
                 .customize()
                 .with_status(StatusCode::NOT_FOUND),
-        )
+        );
+    };
+
+    let shorturl = {
+        let info = req.connection_info();
+        format!("{}://{}/{shortlink}", info.scheme(), info.host())
+    };
+    // Link preview crawlers get the OpenGraph tags, and their visits aren't counted
+    if !preview_only && !link.opengraph.is_empty() && redirect::is_crawler(req) {
+        let page = preview::render_card(&link.opengraph, &shorturl, &link.long_url);
+        return Either::Left(
+            HttpResponse::Ok()
+                .content_type("text/html; charset=utf-8")
+                .body(page),
+        );
+    }
+
+    // Conditional rules take precedence over weighted targets, which take precedence over the long URL
+    let cookie_name = format!("chhoto-target-{}", link.id);
+    let (longlink, target) = if let Some(url) = redirect::evaluate_rules(
+        &link.rules,
+        req,
+        data.geoip.as_deref(),
+        &data.config.trusted_proxies,
+    ) {
+        (url.to_owned(), None)
+    } else {
+        let previous = link
+            .sticky
+            .then(|| req.cookie(&cookie_name))
+            .flatten()
+            .and_then(|c| c.value().parse().ok());
+        match redirect::pick_target(&link.targets, previous) {
+            Some(idx) => (link.targets[idx].url.to_owned(), Some(idx)),
+            None => (link.long_url.to_owned(), None),
+        }
+    };
+
+    let interstitial = data.config.always_preview || link.preview;
+    let (mut response, page) = if preview_only || interstitial {
+        // Going through the shortlink again would show the same page in a loop
+        let continue_url = if interstitial {
+            longlink.clone()
+        } else {
+            format!("/{shortlink}")
+        };
+        let page = preview::render(&link, &shorturl, &longlink, &continue_url);
+        let mut response = HttpResponse::Ok();
+        response.content_type("text/html; charset=utf-8");
+        (response, Some(page))
+    } else {
+        // Browsers cache permanent redirects, so those are only used for plain links
+        let status = if data.config.use_temp_redirect
+            || !link.rules.is_empty()
+            || !link.targets.is_empty()
+        {
+            StatusCode::TEMPORARY_REDIRECT
+        } else {
+            // Defaults to permanent redirection
+            StatusCode::PERMANENT_REDIRECT
+        };
+        let mut response = HttpResponse::build(status);
+        response.insert_header((header::LOCATION, longlink));
+        (response, None)
+    };
+    if !preview_only {
+        database::add_hit(link.id, target, &data.hits_tx).await;
+        if link.sticky
+            && let Some(idx) = target
+        {
+            response.cookie(
+                Cookie::build(cookie_name, idx.to_string())
+                    .path(format!("/{shortlink}"))
+                    .max_age(Duration::days(30))
+                    .same_site(SameSite::Lax)
+                    .finish(),
+            );
+        }
     }
+    Either::Left(match page {
+        Some(page) => response.body(page),
+        None => response.finish(),
+    })
 }
@@ -3,12 +3,15 @@
 
 mod delete;
 mod get;
+mod openapi;
 mod post;
 mod put;
 pub(crate) mod types;
 pub(super) mod utils;
+pub(crate) mod v2;
 
 pub(crate) use self::delete::*;
 pub(crate) use self::get::*;
+pub(crate) use self::openapi::*;
 pub(crate) use self::post::*;
 pub(crate) use self::put::*;
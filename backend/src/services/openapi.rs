@@ -0,0 +1,77 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+use actix_web::{HttpResponse, get};
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{ApiKey, ApiKeyValue, SecurityScheme},
+};
+
+use crate::services::*;
+
+// The OpenAPI description, generated from the handlers and their types
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Chhoto URL"),
+    paths(
+        add_links,
+        edit_link,
+        revive_link,
+        delete_link,
+        expand,
+        getall,
+        available,
+        suggest,
+        health_report,
+        maintenance,
+        maintenance_mode,
+        restore,
+        download_backup,
+        getconfig,
+        siteurl,
+        version,
+        whoami,
+        login,
+        logout,
+        link_handler,
+        preview_handler,
+        v2::list,
+        v2::create,
+        v2::show,
+        v2::create_with_slug,
+        v2::update,
+        v2::remove,
+    ),
+    modifiers(&Security),
+    tags(
+        (name = "links", description = "Manage the links"),
+        (name = "config", description = "Backend configuration"),
+        (name = "auth", description = "Sessions and access"),
+        (name = "admin", description = "Maintenance and backups"),
+        (name = "redirect", description = "Resolve the links"),
+        (name = "v2", description = "Version 2 of the API, with consistent responses"),
+    )
+)]
+pub(crate) struct ApiDoc;
+
+// Authentication works using either the API key or the session cookie
+struct Security;
+impl Modify for Security {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_default();
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
+        );
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("id"))),
+        );
+    }
+}
+
+// Serve the OpenAPI description
+#[get("/api/openapi.json")]
+pub(crate) async fn openapi_spec() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
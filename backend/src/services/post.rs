@@ -1,145 +1,292 @@
 // SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
 // SPDX-License-Identifier: MIT
 
-use std::rc::Rc;
+use std::{rc::Rc, sync::atomic::Ordering};
 
 use actix_session::Session;
 use actix_web::{
-    HttpResponse, post,
+    HttpRequest, HttpResponse,
+    http::StatusCode,
+    post,
     web::{self},
 };
 use argon2::{Argon2, PasswordVerifier, password_hash::PasswordHash};
 use log::{debug, info, warn};
+use sha2::{Digest, Sha256};
 
 use crate::{
     AppState,
     auth::{self, Auth},
-    config::HashAlgorithm,
-    database,
+    config::{Config, HashAlgorithm},
+    database::{self, AddedLink},
+    lists,
     services::types::{
         AddLinkResponse,
-        ChhotoError::{ClientError, ServerError},
-        CreatedURL, JSONResponse, LinkInfo,
+        ChhotoError::{self, ServerError},
+        CreatedURL, DomainParams, JSONResponse, LinkInfo, NewURLRequest, OneOrMany, RestoreRequest,
     },
     utils,
 };
 
 const SERVER_ERROR_RES: &str = "Something went wrong when adding the link.";
 // Add new links
+#[utoipa::path(
+    tag = "links",
+    request_body(content = OneOrMany<NewURLRequest>, description = "A link, or an array of links"),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays the stored response when the same request is sent again"),
+    ),
+    responses(
+        (status = 201, description = "The link was added, or one entry per link for a batch", body = AddLinkResponse),
+        (status = 200, description = "An existing link was reused, or one entry per link for a batch", body = [AddLinkResponse]),
+        (status = 400, description = "The link could not be added", body = JSONResponse),
+        (status = 401, description = "Not authorized", body = JSONResponse),
+        (status = 422, description = "The Idempotency-Key was used for a different request", body = JSONResponse),
+    ),
+    security(("api_key" = []), ("session" = []), ())
+)]
 #[post("/api/new")]
-pub(crate) async fn add_links(req: String, auth: Auth, data: web::Data<AppState>) -> HttpResponse {
+pub(crate) async fn add_links(
+    req: String,
+    http: HttpRequest,
+    auth: Auth,
+    data: web::Data<AppState>,
+) -> HttpResponse {
     let config = &data.config;
-    let cookie_response = async |public_mode| {
-        let result =
-            utils::add_links_helper(&req, &mut *data.writer.lock().await, config, public_mode)
-                .and_then(|(v, _)| v.into_iter().next().unwrap_or(Err(ServerError)));
-        match result {
-            Ok((shorturl, _)) => HttpResponse::Created()
-                .content_type("text/plain")
-                .body(shorturl),
-            Err(ClientError { reason }) => HttpResponse::Conflict()
+    // Session and public mode responses are plain text, for backwards compatibility
+    let (api, public_mode) = match auth {
+        Auth::ValidAPIKey => (true, false),
+        Auth::InvalidAPIKey { result } => return HttpResponse::Unauthorized().json(result),
+        Auth::ValidSession => (false, false),
+        Auth::None { result: _ } if config.public_mode => (false, true),
+        Auth::None { result: _ } => {
+            return HttpResponse::Unauthorized()
                 .content_type("text/plain")
-                .body(reason),
-            Err(ServerError) => HttpResponse::InternalServerError()
+                .body("Not logged in!");
+        }
+    };
+    let error_response = |status, reason: &str| {
+        if api {
+            HttpResponse::build(status).json(JSONResponse {
+                success: false,
+                error: true,
+                reason: reason.to_owned(),
+            })
+        } else {
+            HttpResponse::build(status)
                 .content_type("text/plain")
-                .body(SERVER_ERROR_RES.to_owned()),
+                .body(reason.to_owned())
         }
     };
-    match auth {
-        Auth::ValidAPIKey => {
-            let to_response = |res| match res {
-                Ok((shortlink, expiry_time)) => {
-                    let site_url = config.site_url.to_owned();
-                    let shorturl = if let Some(url) = &site_url {
-                        format!("{url}/{shortlink}")
-                    } else {
-                        let protocol = if config.port == 443 { "https" } else { "http" };
-                        let port_text = if [80, 443].contains(&config.port) {
-                            String::new()
-                        } else {
-                            format!(":{}", config.port)
-                        };
-                        format!("{protocol}://localhost{port_text}/{shortlink}")
-                    };
-
-                    (
-                        actix_web::http::StatusCode::OK,
-                        AddLinkResponse::Success(CreatedURL {
-                            success: true,
-                            error: false,
-                            shorturl,
-                            expiry_time,
-                        }),
-                    )
-                }
-                Err(ClientError { reason }) => (
-                    actix_web::http::StatusCode::BAD_REQUEST,
-                    AddLinkResponse::Error(JSONResponse {
-                        success: false,
-                        error: true,
-                        reason,
-                    }),
-                ),
-                Err(ServerError) => (
-                    actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
-                    AddLinkResponse::Error(JSONResponse {
-                        success: false,
-                        error: true,
-                        reason: SERVER_ERROR_RES.to_owned(),
-                    }),
-                ),
-            };
 
-            match utils::add_links_helper(&req, &mut *data.writer.lock().await, config, false) {
-                Ok((reply, single_request)) => {
-                    if single_request {
-                        let (status, response) = to_response(
-                            reply
-                                .into_iter()
-                                .next()
-                                .expect("There should be one response here."),
-                        );
-                        HttpResponse::build(status).json(response)
-                    } else {
-                        let response: Rc<_> =
-                            reply.into_iter().map(to_response).map(|(_, r)| r).collect();
-                        HttpResponse::Ok().json(response)
-                    }
-                }
-                Err(error) => {
-                    let (status, response) = to_response(Err(error));
-                    HttpResponse::build(status).json(response)
-                }
+    // Keys are scoped to the kind of caller, so that public mode users can't claim the ones used by the admin
+    let key = match http.headers().get("Idempotency-Key") {
+        Some(_) if config.idempotency_window == 0 => None,
+        Some(value) => match value.to_str() {
+            Ok(key) if (1..=255).contains(&key.len()) => Some(format!("{api}:{public_mode}:{key}")),
+            _ => {
+                return error_response(StatusCode::BAD_REQUEST, "Invalid Idempotency-Key!");
             }
+        },
+        None => None,
+    };
+
+    // Hold the lock throughout, so that concurrent retries are handled one after another
+    let mut db = data.writer.lock().await;
+    let request_hash = format!("{:x}", Sha256::digest(format!("{api}:{public_mode}:{req}")));
+    if let Some(key) = &key
+        && let Some(stored) = database::find_idempotency_key(key, &db)
+    {
+        if stored.request_hash != request_hash {
+            return error_response(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "The Idempotency-Key was already used for a different request!",
+            );
         }
-        Auth::InvalidAPIKey { result } => HttpResponse::Unauthorized().json(result),
-        // If password authentication or public mode is used - keeps backwards compatibility
-        Auth::ValidSession => cookie_response(false).await,
-        Auth::None { result: _ } => {
-            if data.config.public_mode {
-                cookie_response(true).await
-            } else {
-                HttpResponse::Unauthorized()
-                    .content_type("text/plain")
-                    .body("Not logged in!")
-            }
+        debug!("Replaying the stored response for idempotency key {key}.");
+        return HttpResponse::build(StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK))
+            .content_type(stored.content_type)
+            .insert_header(("Idempotent-Replayed", "true"))
+            .body(stored.body);
+    }
+
+    let result = utils::add_links_helper(
+        &req,
+        &mut db,
+        config,
+        &lists::current(&data.lists),
+        public_mode,
+    );
+    let (status, content_type, body) = if api {
+        api_response(result, config)
+    } else {
+        text_response(result)
+    };
+
+    // Server errors may be retried, so they are not stored
+    if let Some(key) = &key
+        && !status.is_server_error()
+    {
+        let stored = database::StoredResponse {
+            request_hash,
+            status: status.as_u16(),
+            content_type: content_type.to_owned(),
+            body,
+        };
+        database::add_idempotency_key(key, &stored, config.idempotency_window, &db);
+        return HttpResponse::build(status)
+            .content_type(content_type)
+            .body(stored.body);
+    }
+    HttpResponse::build(status)
+        .content_type(content_type)
+        .body(body)
+}
+
+// Build the plain text response for session and public mode users
+fn text_response(
+    result: Result<(Vec<Result<AddedLink, ChhotoError>>, bool), ChhotoError>,
+) -> (StatusCode, &'static str, String) {
+    let result = result.and_then(|(v, _)| v.into_iter().next().unwrap_or(Err(ServerError)));
+    let (status, body) = match result {
+        // Reused links aren't newly created
+        Ok(added) if added.reused => (StatusCode::OK, added.shortlink),
+        Ok(added) => (StatusCode::CREATED, added.shortlink),
+        Err(ServerError) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            SERVER_ERROR_RES.to_owned(),
+        ),
+        Err(error) => (StatusCode::CONFLICT, error.reason()),
+    };
+    (status, "text/plain", body)
+}
+
+// Build the JSON response for API key users
+fn api_response(
+    result: Result<(Vec<Result<AddedLink, ChhotoError>>, bool), ChhotoError>,
+    config: &Config,
+) -> (StatusCode, &'static str, String) {
+    let to_response = |res: Result<AddedLink, ChhotoError>| match res {
+        Ok(added) => {
+            let shorturl = format!("{}/{}", config.site_url_for(&added.domain), added.shortlink);
+
+            (
+                StatusCode::OK,
+                AddLinkResponse::Success(CreatedURL {
+                    success: true,
+                    error: false,
+                    shorturl,
+                    expiry_time: added.expiry_time,
+                    reused: added.reused,
+                }),
+            )
+        }
+        Err(ServerError) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            AddLinkResponse::Error(JSONResponse {
+                success: false,
+                error: true,
+                reason: SERVER_ERROR_RES.to_owned(),
+            }),
+        ),
+        Err(error) => (
+            StatusCode::BAD_REQUEST,
+            AddLinkResponse::Error(JSONResponse {
+                success: false,
+                error: true,
+                reason: error.reason(),
+            }),
+        ),
+    };
+
+    let (status, body) = match result {
+        Ok((reply, true)) => {
+            let (status, response) = to_response(
+                reply
+                    .into_iter()
+                    .next()
+                    .expect("There should be one response here."),
+            );
+            (status, serde_json::to_string(&response))
+        }
+        Ok((reply, false)) => {
+            let response: Rc<_> = reply.into_iter().map(to_response).map(|(_, r)| r).collect();
+            (StatusCode::OK, serde_json::to_string(&response))
+        }
+        Err(error) => {
+            let (status, response) = to_response(Err(error));
+            (status, serde_json::to_string(&response))
+        }
+    };
+    match body {
+        Ok(body) => (status, "application/json", body),
+        Err(e) => {
+            warn!("Error serializing the response: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "text/plain",
+                SERVER_ERROR_RES.to_owned(),
+            )
         }
     }
 }
 
 // Get information about a single shortlink
+#[utoipa::path(
+    tag = "links",
+    params(DomainParams),
+    request_body(content = String, description = "The shortlink or one of its aliases", content_type = "text/plain"),
+    responses(
+        (status = 200, description = "Information about the link", body = LinkInfo),
+        (status = 400, description = "The shortlink does not exist", body = JSONResponse),
+        (status = 401, description = "Not authorized", body = JSONResponse),
+    ),
+    security(("api_key" = []))
+)]
 #[post("/api/expand")]
-pub(crate) async fn expand(req: String, auth: Auth, data: web::Data<AppState>) -> HttpResponse {
+pub(crate) async fn expand(
+    req: String,
+    params: web::Query<DomainParams>,
+    auth: Auth,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let Some(domain) = data.config.normalize_domain(&params.domain) else {
+        return HttpResponse::BadRequest().json(JSONResponse {
+            success: false,
+            error: true,
+            reason: "Invalid domain!".to_owned(),
+        });
+    };
     match auth {
-        Auth::ValidAPIKey => match database::find_url(&req, &data.reader) {
+        Auth::ValidAPIKey => match database::find_url(
+            &req,
+            &domain,
+            data.config.case_insensitive_slugs,
+            &data.reader,
+        ) {
             Ok(chunks) => {
                 let body = LinkInfo {
                     success: true,
                     error: false,
+                    shortlink: chunks.shortlink,
                     longurl: chunks.longlink,
                     hits: chunks.hits,
                     expiry_time: chunks.expiry_time,
                     notes: chunks.notes,
+                    rules: chunks.rules,
+                    targets: chunks.targets,
+                    sticky: chunks.sticky,
+                    created_at: chunks.created_at,
+                    updated_at: chunks.updated_at,
+                    last_accessed_at: chunks.last_accessed_at,
+                    idle_ttl: chunks.idle_ttl,
+                    preview: chunks.preview,
+                    opengraph: chunks.opengraph,
+                    health_status: chunks.health_status,
+                    health_checked_at: chunks.health_checked_at,
+                    health_failures: chunks.health_failures,
+                    disabled: chunks.disabled,
+                    aliases: chunks.aliases,
                 };
                 HttpResponse::Ok().json(body)
             }
@@ -151,11 +298,11 @@ pub(crate) async fn expand(req: String, auth: Auth, data: web::Data<AppState>) -
                 };
                 HttpResponse::BadRequest().json(body)
             }
-            Err(ClientError { reason }) => {
+            Err(error) => {
                 let body = JSONResponse {
                     success: false,
                     error: true,
-                    reason,
+                    reason: error.reason(),
                 };
                 HttpResponse::BadRequest().json(body)
             }
@@ -172,6 +319,14 @@ pub(crate) async fn expand(req: String, auth: Auth, data: web::Data<AppState>) -
 }
 
 // Handle login
+#[utoipa::path(
+    tag = "auth",
+    request_body(content = String, description = "The password", content_type = "text/plain"),
+    responses(
+        (status = 200, description = "Logged in", body = String, content_type = "text/plain"),
+        (status = 401, description = "Wrong password", body = JSONResponse),
+    )
+)]
 #[post("/api/login")]
 pub(crate) async fn login(
     auth: Auth,
@@ -250,3 +405,43 @@ pub(crate) async fn login(
             .body("Correct password!")
     }
 }
+
+// Replace the database with one of the backups, which needs maintenance mode to be on
+#[utoipa::path(
+    tag = "admin",
+    request_body = RestoreRequest,
+    responses(
+        (status = 200, description = "The backup was restored", body = JSONResponse),
+        (status = 400, description = "The backup could not be restored", body = JSONResponse),
+        (status = 401, description = "Not authorized", body = JSONResponse),
+        (status = 404, description = "The backup does not exist", body = JSONResponse),
+    ),
+    security(("api_key" = []), ("session" = []))
+)]
+#[post("/api/admin/restore")]
+pub(crate) async fn restore(req: String, auth: Auth, data: web::Data<AppState>) -> HttpResponse {
+    match auth {
+        Auth::ValidAPIKey | Auth::ValidSession => {
+            let maintenance = data.maintenance.load(Ordering::Relaxed);
+            let mut db = data.writer.lock().await;
+            match utils::restore_helper(&req, &mut db, &data.config, maintenance) {
+                Ok(links) => {
+                    info!("A backup with {links} links was restored.");
+                    HttpResponse::Ok().json(JSONResponse {
+                        success: true,
+                        error: false,
+                        reason: format!("Restored the backup with {links} links."),
+                    })
+                }
+                Err(error) => HttpResponse::build(error.status()).json(JSONResponse {
+                    success: false,
+                    error: true,
+                    reason: error.reason(),
+                }),
+            }
+        }
+        Auth::None { result } | Auth::InvalidAPIKey { result } => {
+            HttpResponse::Unauthorized().json(result)
+        }
+    }
+}
@@ -2,25 +2,44 @@
 // SPDX-License-Identifier: MIT
 
 use actix_web::{HttpResponse, put, web};
+use log::warn;
+use std::sync::atomic::Ordering;
 
 use crate::{
     AppState,
     auth::Auth,
+    lists,
     services::types::{
-        ChhotoError::{ClientError, ServerError},
-        JSONResponse,
+        ChhotoError::ServerError, EditURLRequest, JSONResponse, MaintenanceModeRequest,
+        ReviveRequest,
     },
     utils,
 };
 
 // Edit a shortlink
+#[utoipa::path(
+    tag = "links",
+    request_body = EditURLRequest,
+    responses(
+        (status = 201, description = "The link was edited", body = JSONResponse),
+        (status = 400, description = "The link could not be edited", body = JSONResponse),
+        (status = 401, description = "Not authorized", body = JSONResponse),
+    ),
+    security(("api_key" = []), ("session" = []))
+)]
 #[put("/api/edit")]
 pub(crate) async fn edit_link(req: String, auth: Auth, data: web::Data<AppState>) -> HttpResponse {
     let config = &data.config;
     match auth {
         Auth::ValidAPIKey | Auth::ValidSession => {
-            match utils::edit_link_helper(&req, &*data.writer.lock().await, &data.hits_tx, config)
-                .await
+            match utils::edit_link_helper(
+                &req,
+                &*data.writer.lock().await,
+                &data.hits_tx,
+                config,
+                &lists::current(&data.lists),
+            )
+            .await
             {
                 Ok(()) => {
                     let body = JSONResponse {
@@ -38,11 +57,11 @@ pub(crate) async fn edit_link(req: String, auth: Auth, data: web::Data<AppState>
                     };
                     HttpResponse::InternalServerError().json(body)
                 }
-                Err(ClientError { reason }) => {
+                Err(error) => {
                     let body = JSONResponse {
                         success: false,
                         error: true,
-                        reason,
+                        reason: error.reason(),
                     };
                     HttpResponse::BadRequest().json(body)
                 }
@@ -53,3 +72,92 @@ pub(crate) async fn edit_link(req: String, auth: Auth, data: web::Data<AppState>
         }
     }
 }
+
+// Revive a link that expired within the grace period
+#[utoipa::path(
+    tag = "links",
+    request_body = ReviveRequest,
+    responses(
+        (status = 201, description = "The link was revived", body = JSONResponse),
+        (status = 400, description = "The link could not be revived", body = JSONResponse),
+        (status = 401, description = "Not authorized", body = JSONResponse),
+    ),
+    security(("api_key" = []), ("session" = []))
+)]
+#[put("/api/revive")]
+pub(crate) async fn revive_link(
+    req: String,
+    auth: Auth,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    match auth {
+        Auth::ValidAPIKey | Auth::ValidSession => {
+            match utils::revive_link_helper(&req, &*data.writer.lock().await, &data.config) {
+                Ok(()) => HttpResponse::Created().json(JSONResponse {
+                    success: true,
+                    error: false,
+                    reason: String::from("The link was revived."),
+                }),
+                Err(ServerError) => HttpResponse::InternalServerError().json(JSONResponse {
+                    success: false,
+                    error: true,
+                    reason: "Something went wrong when reviving the link.".to_owned(),
+                }),
+                Err(error) => HttpResponse::BadRequest().json(JSONResponse {
+                    success: false,
+                    error: true,
+                    reason: error.reason(),
+                }),
+            }
+        }
+        Auth::None { result } | Auth::InvalidAPIKey { result } => {
+            HttpResponse::Unauthorized().json(result)
+        }
+    }
+}
+
+// Turn maintenance mode on or off, which rejects any changes other than the admin ones
+#[utoipa::path(
+    tag = "admin",
+    request_body = MaintenanceModeRequest,
+    responses(
+        (status = 200, description = "Maintenance mode was changed", body = JSONResponse),
+        (status = 400, description = "Malformed request", body = JSONResponse),
+        (status = 401, description = "Not authorized", body = JSONResponse),
+    ),
+    security(("api_key" = []), ("session" = []))
+)]
+#[put("/api/admin/maintenance-mode")]
+pub(crate) async fn maintenance_mode(
+    req: String,
+    auth: Auth,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    match auth {
+        Auth::ValidAPIKey | Auth::ValidSession => {
+            let Ok(req) = serde_json::from_str::<MaintenanceModeRequest>(&req) else {
+                return HttpResponse::BadRequest().json(JSONResponse {
+                    success: false,
+                    error: true,
+                    reason: "Malformed request!".to_owned(),
+                });
+            };
+            data.maintenance.store(req.enabled, Ordering::Relaxed);
+            let reason = if req.enabled {
+                warn!("Maintenance mode was turned on.");
+                "Maintenance mode is on."
+            } else {
+                warn!("Maintenance mode was turned off.");
+                "Maintenance mode is off."
+            };
+            HttpResponse::Ok().json(JSONResponse {
+                success: true,
+                error: false,
+                reason: reason.to_owned(),
+            })
+        }
+        Auth::None { result } | Auth::InvalidAPIKey { result } => {
+            HttpResponse::Unauthorized().json(result)
+        }
+    }
+}
@@ -1,17 +1,68 @@
 // SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
 // SPDX-License-Identifier: MIT
 
+use actix_web::http::StatusCode;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    preview::OpenGraph,
+    redirect::{RedirectRule, WeightedTarget},
+};
 
 // Error types
 #[derive(Clone)]
 pub(crate) enum ChhotoError {
     ServerError,
     ClientError { reason: String },
+    InvalidField { field: &'static str, reason: String },
+    Forbidden { reason: String },
+    NotFound { reason: String },
+    Conflict { reason: String },
+    Unauthorized { reason: String },
+}
+
+impl ChhotoError {
+    // Machine readable code used by the v2 API
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            Self::ServerError => "server_error",
+            Self::ClientError { .. } => "bad_request",
+            Self::InvalidField { .. } => "invalid_field",
+            Self::Forbidden { .. } => "forbidden",
+            Self::NotFound { .. } => "not_found",
+            Self::Conflict { .. } => "conflict",
+            Self::Unauthorized { .. } => "unauthorized",
+        }
+    }
+
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            Self::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::ClientError { .. } | Self::InvalidField { .. } => StatusCode::BAD_REQUEST,
+            Self::Forbidden { .. } => StatusCode::FORBIDDEN,
+            Self::NotFound { .. } => StatusCode::NOT_FOUND,
+            Self::Conflict { .. } => StatusCode::CONFLICT,
+            Self::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    // Human readable reason, which is all that the v1 API reports
+    pub(crate) fn reason(self) -> String {
+        match self {
+            Self::ServerError => "Something went wrong on the server.".to_owned(),
+            Self::ClientError { reason }
+            | Self::InvalidField { reason, .. }
+            | Self::Forbidden { reason }
+            | Self::NotFound { reason }
+            | Self::Conflict { reason }
+            | Self::Unauthorized { reason } => reason,
+        }
+    }
 }
 
 // Enum for optional batching
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(untagged)]
 pub(super) enum OneOrMany<T> {
     One(T),
@@ -26,8 +77,68 @@ impl<T> OneOrMany<T> {
     }
 }
 
+// Struct for reading link pairs sent during API call for new link
+#[derive(Deserialize, Clone, ToSchema)]
+pub(crate) struct NewURLRequest {
+    #[serde(default)]
+    pub(crate) shortlink: String,
+    pub(crate) longlink: String,
+    pub(crate) expiry_delay: Option<i64>,
+    pub(crate) notes: Option<String>,
+    #[serde(default)]
+    pub(crate) domain: String,
+    pub(crate) rules: Option<Vec<RedirectRule>>,
+    pub(crate) targets: Option<Vec<WeightedTarget>>,
+    pub(crate) sticky: Option<bool>,
+    pub(crate) preview: Option<bool>,
+    pub(crate) opengraph: Option<OpenGraph>,
+    pub(crate) dedupe: Option<bool>,
+    pub(crate) aliases: Option<Vec<String>>,
+    pub(crate) idle_ttl: Option<i64>,
+}
+
+// Struct for reading link pairs sent during API call for editing link
+#[derive(Deserialize, ToSchema)]
+pub(super) struct EditURLRequest {
+    pub(super) shortlink: String,
+    #[serde(default)]
+    pub(super) domain: String,
+    pub(super) longlink: String,
+    pub(super) reset_hits: bool,
+    pub(super) expiry_time: Option<i64>,
+    pub(super) notes: Option<String>,
+    pub(super) rules: Option<Vec<RedirectRule>>,
+    pub(super) targets: Option<Vec<WeightedTarget>>,
+    pub(super) sticky: Option<bool>,
+    pub(super) preview: Option<bool>,
+    pub(super) opengraph: Option<OpenGraph>,
+    pub(super) aliases: Option<Vec<String>>,
+    pub(super) idle_ttl: Option<i64>,
+}
+
+// Struct for reading the link to revive, and its new expiry delay
+#[derive(Deserialize, ToSchema)]
+pub(super) struct ReviveRequest {
+    pub(super) shortlink: String,
+    #[serde(default)]
+    pub(super) domain: String,
+    pub(super) expiry_delay: Option<i64>,
+}
+
+// Struct for turning maintenance mode on or off
+#[derive(Deserialize, ToSchema)]
+pub(super) struct MaintenanceModeRequest {
+    pub(super) enabled: bool,
+}
+
+// Struct for reading the name of the backup to restore, within the backups directory
+#[derive(Deserialize, ToSchema)]
+pub(super) struct RestoreRequest {
+    pub(super) backup: String,
+}
+
 // Define JSON struct for returning success/error data
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub(crate) struct JSONResponse {
     pub(crate) success: bool,
     pub(crate) error: bool,
@@ -35,10 +146,11 @@ pub(crate) struct JSONResponse {
 }
 
 // Define JSON struct for returning backend config
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub(super) struct BackendConfig {
     pub(super) version: String,
     pub(super) site_url: Option<String>,
+    pub(super) extra_site_urls: Vec<String>,
     pub(super) allow_capital_letters: bool,
     pub(super) public_mode: bool,
     pub(super) public_mode_expiry_delay: i64,
@@ -50,16 +162,17 @@ pub(super) struct BackendConfig {
 }
 
 // Needed to return the short URL to make it easier for programs leveraging the API
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub(super) struct CreatedURL {
     pub(super) success: bool,
     pub(super) error: bool,
     pub(super) shorturl: String,
     pub(super) expiry_time: i64,
+    pub(super) reused: bool,
 }
 
 // Response type for add_links
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(untagged)]
 pub(super) enum AddLinkResponse {
     Success(CreatedURL),
@@ -67,21 +180,243 @@ pub(super) enum AddLinkResponse {
 }
 
 // Struct for returning information about a shortlink in expand
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub(super) struct LinkInfo {
     pub(super) success: bool,
     pub(super) error: bool,
+    pub(super) shortlink: String,
     pub(super) longurl: String,
     pub(super) hits: i64,
     pub(super) expiry_time: i64,
     pub(super) notes: String,
+    pub(super) rules: Vec<RedirectRule>,
+    pub(super) targets: Vec<WeightedTarget>,
+    pub(super) sticky: bool,
+    pub(super) created_at: i64,
+    pub(super) updated_at: i64,
+    pub(super) last_accessed_at: i64,
+    pub(super) idle_ttl: i64,
+    pub(super) preview: bool,
+    pub(super) opengraph: OpenGraph,
+    pub(super) health_status: Option<i64>,
+    pub(super) health_checked_at: i64,
+    pub(super) health_failures: i64,
+    pub(super) disabled: bool,
+    pub(super) aliases: Vec<String>,
+}
+
+// Struct for reporting whether a shortlink can be used
+#[derive(Serialize, ToSchema)]
+pub(super) struct SlugAvailability {
+    pub(super) success: bool,
+    pub(super) error: bool,
+    pub(super) shortlink: String,
+    pub(super) valid: bool,
+    pub(super) available: bool,
+    pub(super) expired: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) reason: Option<String>,
+}
+
+// Struct for returning the state of a maintenance job
+#[derive(Serialize, ToSchema)]
+pub(super) struct MaintenanceJob {
+    pub(super) job: &'static str,
+    pub(super) schedule: Option<String>,
+    pub(super) last_run: Option<i64>,
+    pub(super) next_run: Option<i64>,
+}
+
+// Struct for returning free shortlinks
+#[derive(Serialize, ToSchema)]
+pub(super) struct SlugSuggestions {
+    pub(super) success: bool,
+    pub(super) error: bool,
+    pub(super) suggestions: Vec<String>,
 }
 
 // Struct for query params in /api/all
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub(crate) struct GetReqParams {
     pub(crate) page_after: Option<String>,
+    pub(crate) page_after_domain: Option<String>,
     pub(crate) page_no: Option<i64>,
     pub(crate) page_size: Option<i64>,
     pub(crate) filter: Option<String>,
+    pub(crate) broken: Option<bool>,
+    pub(crate) sort: Option<SortBy>,
+    pub(crate) order: Option<SortOrder>,
+    pub(crate) created_after: Option<i64>,
+    pub(crate) created_before: Option<i64>,
+    pub(crate) updated_after: Option<i64>,
+    pub(crate) updated_before: Option<i64>,
+    pub(crate) accessed_after: Option<i64>,
+    pub(crate) accessed_before: Option<i64>,
+    pub(crate) include_expired: Option<bool>,
+}
+
+// Columns that links can be sorted by in /api/all
+#[derive(Deserialize, ToSchema, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SortBy {
+    CreatedAt,
+    UpdatedAt,
+    LastAccessedAt,
+    Hits,
+}
+
+#[derive(Deserialize, ToSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SortOrder {
+    Asc,
+    Desc,
+    Relevance,
+}
+
+// Struct for query params selecting the domain of a shortlink
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct DomainParams {
+    #[serde(default)]
+    pub(crate) domain: String,
+}
+
+// Struct for query params in /api/suggest
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct SuggestParams {
+    #[serde(default)]
+    pub(crate) base: String,
+    #[serde(default)]
+    pub(crate) domain: String,
+    pub(crate) count: Option<usize>,
+}
+
+// Struct for query params in /api/admin/backup
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct BackupParams {
+    pub(crate) compress: Option<bool>,
+}
+
+// Envelope used for every response of the v2 API
+#[derive(Serialize, ToSchema)]
+pub(super) struct Envelope<T> {
+    pub(super) success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) error: Option<ErrorBody>,
+}
+
+// Struct for describing an error in the v2 API
+#[derive(Serialize, ToSchema)]
+pub(super) struct ErrorBody {
+    pub(super) code: &'static str,
+    pub(super) message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) field: Option<&'static str>,
+}
+
+impl From<ChhotoError> for ErrorBody {
+    fn from(error: ChhotoError) -> Self {
+        // Field names follow the naming used by the v2 API
+        let field = match &error {
+            ChhotoError::InvalidField { field, .. } => Some(match *field {
+                "shortlink" => "slug",
+                "longlink" => "long_url",
+                field => field,
+            }),
+            _ => None,
+        };
+        ErrorBody {
+            code: error.code(),
+            message: error.reason(),
+            field,
+        }
+    }
+}
+
+// A link, as returned by the v2 API
+#[derive(Serialize, ToSchema)]
+pub(super) struct Link {
+    pub(super) slug: String,
+    pub(super) domain: String,
+    pub(super) short_url: String,
+    pub(super) long_url: String,
+    pub(super) hits: i64,
+    pub(super) expiry_time: i64,
+    pub(super) notes: String,
+    pub(super) rules: Vec<RedirectRule>,
+    pub(super) targets: Vec<WeightedTarget>,
+    pub(super) sticky: bool,
+    pub(super) created_at: i64,
+    pub(super) updated_at: i64,
+    pub(super) last_accessed_at: i64,
+    pub(super) idle_ttl: i64,
+    pub(super) preview: bool,
+    pub(super) opengraph: OpenGraph,
+    pub(super) health_status: Option<i64>,
+    pub(super) health_checked_at: i64,
+    pub(super) health_failures: i64,
+    pub(super) disabled: bool,
+    pub(super) aliases: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) snippet: Option<String>,
+}
+
+// A link, as returned by the v2 API to public mode users
+#[derive(Serialize, ToSchema)]
+pub(super) struct PublicLink {
+    pub(super) slug: String,
+    pub(super) domain: String,
+    pub(super) short_url: String,
+    pub(super) long_url: String,
+    pub(super) expiry_time: i64,
+}
+
+// A newly added link, with the details depending on the caller
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub(super) enum CreatedLink {
+    Full(Box<Link>),
+    Public(PublicLink),
+}
+
+// Struct for adding a link using the v2 API
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub(super) struct NewLink {
+    #[serde(default)]
+    pub(super) slug: String,
+    pub(super) long_url: String,
+    pub(super) expiry_delay: Option<i64>,
+    pub(super) notes: Option<String>,
+    pub(super) rules: Option<Vec<RedirectRule>>,
+    pub(super) targets: Option<Vec<WeightedTarget>>,
+    pub(super) sticky: Option<bool>,
+    pub(super) preview: Option<bool>,
+    pub(super) opengraph: Option<OpenGraph>,
+    pub(super) dedupe: Option<bool>,
+    pub(super) aliases: Option<Vec<String>>,
+    pub(super) idle_ttl: Option<i64>,
+}
+
+// Struct for changing a link using the v2 API, missing fields are left as they are
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub(super) struct LinkPatch {
+    pub(super) long_url: Option<String>,
+    #[serde(default)]
+    pub(super) reset_hits: bool,
+    pub(super) expiry_time: Option<i64>,
+    pub(super) notes: Option<String>,
+    pub(super) rules: Option<Vec<RedirectRule>>,
+    pub(super) targets: Option<Vec<WeightedTarget>>,
+    pub(super) sticky: Option<bool>,
+    pub(super) preview: Option<bool>,
+    pub(super) opengraph: Option<OpenGraph>,
+    pub(super) aliases: Option<Vec<String>>,
+    pub(super) idle_ttl: Option<i64>,
 }
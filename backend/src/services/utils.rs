@@ -2,45 +2,43 @@
 // SPDX-License-Identifier: MIT
 
 use actix_files::NamedFile;
-use actix_web::{Responder, http::StatusCode};
+use actix_web::{
+    Error, HttpResponse, Responder,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::{Method, StatusCode},
+    middleware::Next,
+    web,
+};
 use log::{debug, error};
-use nanoid::nanoid;
-use rand::{random_range, seq::IndexedRandom};
 use rusqlite::Connection;
-use serde::Deserialize;
-use std::env;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    path::Path,
+    rc::Rc,
+    sync::atomic::Ordering,
+};
 use tokio::sync::mpsc;
 use url::Url;
 
 use crate::{
+    AppState,
+    background::HitUpdate,
     config::{Config, SlugStyle},
-    database::{self, add_links},
+    database::{self, AddedLink, DBRow, LinkOrder, TimeFilters, add_links},
+    lists::DomainLists,
+    preview::OpenGraph,
+    redirect::{PLATFORMS, RedirectRule, WeightedTarget},
+    search,
     services::types::{
-        ChhotoError::{self, ClientError, ServerError},
-        GetReqParams, OneOrMany,
+        ChhotoError::{self, ClientError, Forbidden, InvalidField, NotFound, ServerError},
+        EditURLRequest, GetReqParams, JSONResponse, NewURLRequest, OneOrMany, RestoreRequest,
+        ReviveRequest, SlugAvailability, SortOrder, SuggestParams,
     },
+    slugs,
 };
 
-// Struct for reading link pairs sent during API call for new link
-#[derive(Deserialize, Clone)]
-pub(crate) struct NewURLRequest {
-    #[serde(default)]
-    pub(crate) shortlink: String,
-    pub(crate) longlink: String,
-    pub(crate) expiry_delay: Option<i64>,
-    pub(crate) notes: Option<String>,
-}
-
-// Struct for reading link pairs sent during API call for editing link
-#[derive(Deserialize)]
-struct EditURLRequest {
-    shortlink: String,
-    longlink: String,
-    reset_hits: bool,
-    expiry_time: Option<i64>,
-    notes: Option<String>,
-}
-
 // Only allow safe URI schemes
 #[inline]
 fn is_longlink_valid(link: &str, allowed_protocols: &[String]) -> bool {
@@ -67,33 +65,87 @@ fn is_note_valid(note: &Option<String>) -> bool {
         .is_none_or(|n| n.chars().all(|c| c.is_ascii_graphic() || c == ' '))
 }
 
-// Only have a-z, 0-9, - and _ as valid characters in a shortlink
-#[inline]
-fn normalize_filter(link: &str) -> Option<String> {
-    if link.len() < 3 {
-        return None;
-    }
+// Rules need a valid target and at least one well-formed condition
+fn are_rules_valid(rules: &Option<Vec<RedirectRule>>, allowed_protocols: &[String]) -> bool {
+    rules.as_ref().is_none_or(|rules| {
+        rules.len() <= 32
+            && rules.iter().all(|r| {
+                (r.platform.is_some() || r.language.is_some() || r.country.is_some())
+                    && is_longlink_valid(&r.target, allowed_protocols)
+                    && r.platform
+                        .as_ref()
+                        .is_none_or(|p| PLATFORMS.iter().any(|q| p.eq_ignore_ascii_case(q)))
+                    && r.language.as_ref().is_none_or(|l| {
+                        !l.is_empty() && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                    })
+                    && r.country
+                        .as_ref()
+                        .is_none_or(|c| c.len() == 2 && c.chars().all(|c| c.is_ascii_alphabetic()))
+            })
+    })
+}
 
-    let mut out = String::with_capacity(link.len());
-    let mut last_was_sep = true;
-
-    for c in link.chars() {
-        // Allow printable ascii chars
-        if c.is_ascii_alphanumeric() {
-            out.push(c);
-            last_was_sep = false;
-        } else if c.is_ascii_punctuation() || c == ' ' {
-            if !last_was_sep {
-                out.push(' ');
-                last_was_sep = true;
-            }
-        } else {
-            return None;
-        }
-    }
+// Aliases follow the same rules as custom shortlinks, and can't repeat
+fn are_aliases_valid(aliases: &Option<Vec<String>>, config: &Config) -> bool {
+    aliases.as_ref().is_none_or(|aliases| {
+        aliases.len() <= 32
+            && aliases.iter().all(|a| {
+                !a.is_empty()
+                    && is_shortlink_valid(a, config.allow_mixed_case())
+                    && !config.reserved_slugs.is_reserved(a)
+            })
+            && aliases
+                .iter()
+                .map(|a| database::slug_key(a, config.case_insensitive_slugs))
+                .collect::<HashSet<_>>()
+                .len()
+                == aliases.len()
+    })
+}
+
+// Limit the length of the OpenGraph text, and only allow web URLs for the image
+fn is_opengraph_valid(opengraph: &Option<OpenGraph>) -> bool {
+    let is_text_valid = |text: &Option<String>, max_len: usize| {
+        text.as_ref()
+            .is_none_or(|t| t.chars().count() <= max_len && !t.chars().any(char::is_control))
+    };
+    opengraph.as_ref().is_none_or(|o| {
+        is_text_valid(&o.title, 200)
+            && is_text_valid(&o.description, 1000)
+            && o.image.as_ref().is_none_or(|i| {
+                Url::parse(i).is_ok_and(|u| ["http", "https"].contains(&u.scheme()))
+            })
+    })
+}
 
-    let s = out.trim_ascii_end();
-    (s.len() > 2).then(|| s.to_owned())
+// Every destination of a link needs to pass the domain lists
+fn are_destinations_allowed(
+    longlink: &str,
+    rules: &Option<Vec<RedirectRule>>,
+    targets: &Option<Vec<WeightedTarget>>,
+    lists: &DomainLists,
+    public: bool,
+) -> bool {
+    lists.is_allowed(longlink, public)
+        && rules
+            .iter()
+            .flatten()
+            .all(|r| lists.is_allowed(&r.target, public))
+        && targets
+            .iter()
+            .flatten()
+            .all(|t| lists.is_allowed(&t.url, public))
+}
+
+// Targets need a valid url, and the weights must be sensible
+fn are_targets_valid(targets: &Option<Vec<WeightedTarget>>, allowed_protocols: &[String]) -> bool {
+    targets.as_ref().is_none_or(|targets| {
+        targets.len() <= 32
+            && (targets.is_empty() || targets.iter().any(|t| t.weight > 0))
+            && targets
+                .iter()
+                .all(|t| t.weight <= 10_000 && is_longlink_valid(&t.url, allowed_protocols))
+    })
 }
 
 // Simply get the version string
@@ -110,48 +162,112 @@ pub(crate) fn get_version() -> String {
 }
 
 // Request the DB for all URLs
-pub(super) fn getall_helper(db: &Connection, params: GetReqParams) -> Result<String, ChhotoError> {
+pub(super) fn getall_helper(
+    db: &Connection,
+    params: GetReqParams,
+    config: &Config,
+) -> Result<String, ChhotoError> {
+    let links = list_links(db, params, config)?;
+    serde_json::to_string(&links).map_err(|err| {
+        error!("Failure during creation of json from db columns.\n{err}");
+        ChhotoError::ServerError
+    })
+}
+
+// Check the query params, and get the matching links
+pub(super) fn list_links(
+    db: &Connection,
+    params: GetReqParams,
+    config: &Config,
+) -> Result<Rc<[DBRow]>, ChhotoError> {
     let page_after = match params.page_after {
         Some(s) if s.is_empty() => {
-            return Err(ChhotoError::ClientError {
+            return Err(ChhotoError::InvalidField {
+                field: "page_after",
                 reason: "Invalid page_after was supplied!".to_owned(),
             });
         }
         other => other,
     };
+    let page_after_domain = params.page_after_domain.unwrap_or_default();
     let page_no = match params.page_no {
         Some(n) if n <= 0 => {
-            return Err(ChhotoError::ClientError {
+            return Err(ChhotoError::InvalidField {
+                field: "page_no",
                 reason: "Invalid page_no was supplied!".to_owned(),
             });
         }
         other => other,
     };
     let page_size = params.page_size.filter(|&n| n > 0);
-    let filter = params
+    let search = params
         .filter
         .map(|s| {
-            normalize_filter(&s).ok_or(ChhotoError::ClientError {
-                reason: "Invalid filter was supplied!".to_owned(),
+            search::parse(&s, config.case_insensitive_slugs).map_err(|reason| {
+                ChhotoError::InvalidField {
+                    field: "filter",
+                    reason,
+                }
             })
         })
         .transpose()?;
-    let links = database::getall(db, page_after.as_deref(), page_no, page_size, filter);
-    serde_json::to_string(&links).map_err(|err| {
-        error!("Failure during creation of json from db columns.\n{err}");
-        ChhotoError::ServerError
-    })
+    let order = match (params.sort, params.order) {
+        (None, None) => None,
+        (None, Some(SortOrder::Relevance)) if search.is_some() => Some(LinkOrder::Relevance),
+        (None, Some(SortOrder::Relevance)) => {
+            return Err(ChhotoError::InvalidField {
+                field: "order",
+                reason: "Ordering by relevance needs a filter!".to_owned(),
+            });
+        }
+        (Some(_), Some(SortOrder::Relevance)) => {
+            return Err(ChhotoError::InvalidField {
+                field: "order",
+                reason: "Ordering by relevance can't be combined with sort!".to_owned(),
+            });
+        }
+        (None, Some(_)) => {
+            return Err(ChhotoError::InvalidField {
+                field: "order",
+                reason: "An order can only be given along with sort!".to_owned(),
+            });
+        }
+        (Some(column), order) => Some(LinkOrder::Column(column, order != Some(SortOrder::Asc))),
+    };
+    let times = TimeFilters {
+        created: (params.created_after, params.created_before),
+        updated: (params.updated_after, params.updated_before),
+        accessed: (params.accessed_after, params.accessed_before),
+        expired_within: params
+            .include_expired
+            .unwrap_or_default()
+            .then_some(config.expiry_grace_period),
+    };
+    Ok(database::getall(
+        db,
+        page_after
+            .as_deref()
+            .map(|s| (s, page_after_domain.as_str())),
+        page_no,
+        page_size,
+        search,
+        params.broken,
+        config.health_failure_threshold,
+        order,
+        &times,
+    ))
 }
 
 // Make checks and then request the DB to add a new URL entry
-type AddLinksReturnType = Result<(Vec<Result<(String, i64), ChhotoError>>, bool), ChhotoError>;
+type AddLinksReturnType = Result<(Vec<Result<AddedLink, ChhotoError>>, bool), ChhotoError>;
 pub(super) fn add_links_helper(
     req: &str,
     db: &mut Connection,
     config: &Config,
+    lists: &DomainLists,
     using_public_mode: bool,
 ) -> AddLinksReturnType {
-    // Ok : Vec<shortlink, expiry_time>, single_request
+    // Ok : Vec<AddedLink>, single_request
     let Ok((single_request, chunks)) =
         serde_json::from_str::<OneOrMany<NewURLRequest>>(req).map(|s| {
             let single = matches!(s, OneOrMany::One(_));
@@ -167,12 +283,30 @@ pub(super) fn add_links_helper(
             reason: "An empty array of links was provided!".to_owned(),
         });
     }
+    let output = add_link_requests(chunks, db, config, lists, using_public_mode);
+    if !single_request {
+        debug!("Processed a batch of {} requests.", output.len());
+    }
+    Ok((output, single_request))
+}
 
-    let allow_capital_letters = config.allow_capital_letters;
+// Validate and add the requested links, returning the result for each of them in order
+pub(super) fn add_link_requests(
+    chunks: Vec<NewURLRequest>,
+    db: &mut Connection,
+    config: &Config,
+    lists: &DomainLists,
+    using_public_mode: bool,
+) -> Vec<Result<AddedLink, ChhotoError>> {
+    let allow_capital_letters = config.allow_mixed_case();
+    let nocase = config.case_insensitive_slugs;
+    let grace_period = config.expiry_grace_period;
     let public_mode_expiry_delay = config.public_mode_expiry_delay;
 
     let mut output: Vec<_> = (0..chunks.len()).map(|_| Err(ServerError)).collect();
     let (mut with_shortlinks, mut without_shortlinks) = (Vec::new(), Vec::new());
+    // Duplicates within the same batch reuse the link created for the first one
+    let (mut batch_dupes, mut reused) = (HashMap::new(), Vec::new());
     let clean_req = |mut req: NewURLRequest| {
         // Allow max delay of 5 years
         let exp = req
@@ -183,82 +317,187 @@ pub(super) fn add_links_helper(
             (true, Some(delay)) => Some(exp.map_or(delay, |d| d.min(delay))),
             _ => exp,
         };
+        req.idle_ttl = req
+            .idle_ttl
+            .map(|d| d.clamp(0, 157_784_760))
+            .filter(|&d| d > 0);
         req.notes = req.notes.filter(|s| !s.is_empty());
+        req.aliases = req.aliases.filter(|a| !a.is_empty());
+        // Per target hits are always counted by the server
+        req.targets.iter_mut().flatten().for_each(|t| t.hits = 0);
         req
     };
     for (i, req) in chunks.into_iter().enumerate() {
-        let req = clean_req(req);
+        let mut req = clean_req(req);
+        let Some(domain) = config.normalize_domain(&req.domain) else {
+            output[i] = Err(InvalidField {
+                field: "domain",
+                reason: "Invalid domain!".to_owned(),
+            });
+            continue;
+        };
+        req.domain = domain;
         if !is_shortlink_valid(&req.shortlink, allow_capital_letters) {
-            output[i] = Err(ClientError {
+            output[i] = Err(InvalidField {
+                field: "shortlink",
                 reason: "Invalid shortlink!".to_owned(),
             });
+        } else if config.reserved_slugs.is_reserved(&req.shortlink) {
+            output[i] = Err(Forbidden {
+                reason: "Shortlink is reserved!".to_owned(),
+            });
         } else if !is_longlink_valid(&req.longlink, &config.allowed_protocols) {
-            output[i] = Err(ClientError {
+            output[i] = Err(InvalidField {
+                field: "longlink",
                 reason: "Invalid longlink!".to_owned(),
             });
         } else if !is_note_valid(&req.notes) {
-            output[i] = Err(ClientError {
+            output[i] = Err(InvalidField {
+                field: "notes",
                 reason: "Invalid notes!".to_owned(),
             });
+        } else if !are_rules_valid(&req.rules, &config.allowed_protocols) {
+            output[i] = Err(InvalidField {
+                field: "rules",
+                reason: "Invalid redirect rules!".to_owned(),
+            });
+        } else if !are_targets_valid(&req.targets, &config.allowed_protocols) {
+            output[i] = Err(InvalidField {
+                field: "targets",
+                reason: "Invalid targets!".to_owned(),
+            });
+        } else if !is_opengraph_valid(&req.opengraph) {
+            output[i] = Err(InvalidField {
+                field: "opengraph",
+                reason: "Invalid OpenGraph metadata!".to_owned(),
+            });
+        } else if !are_destinations_allowed(
+            &req.longlink,
+            &req.rules,
+            &req.targets,
+            lists,
+            using_public_mode,
+        ) {
+            output[i] = Err(Forbidden {
+                reason: "Destination is not allowed!".to_owned(),
+            });
+        } else if !are_aliases_valid(&req.aliases, config) {
+            output[i] = Err(InvalidField {
+                field: "aliases",
+                reason: "Invalid aliases!".to_owned(),
+            });
         } else if req.shortlink.is_empty() {
-            without_shortlinks.push((i, req));
+            // Only plain links are deduplicated, since an existing link would silently drop
+            // the rules, targets, aliases, expiry, previews or notes of the request
+            // Public mode users don't get existing links either, since those may belong to the admin
+            let dedupe = !using_public_mode
+                && req.dedupe.unwrap_or(config.dedupe_links)
+                && req.rules.as_ref().is_none_or(Vec::is_empty)
+                && req.targets.as_ref().is_none_or(Vec::is_empty)
+                && req.aliases.is_none()
+                && req.expiry_delay.is_none()
+                && req.idle_ttl.is_none()
+                && req.notes.is_none()
+                && req.preview != Some(true)
+                && req.opengraph.as_ref().is_none_or(OpenGraph::is_empty);
+            if !dedupe {
+                without_shortlinks.push((i, req));
+                continue;
+            }
+            let normalized = Url::parse(&req.longlink)
+                .map(String::from)
+                .unwrap_or_default();
+            let key = (req.domain.clone(), normalized);
+            if let Some(&first) = batch_dupes.get(&key) {
+                reused.push((i, first));
+            } else if let Some(existing) =
+                database::find_duplicate(&req.longlink, &key.1, &req.domain, db)
+            {
+                debug!("Reusing {} for {}.", existing.shortlink, req.longlink);
+                output[i] = Ok(existing);
+            } else {
+                batch_dupes.insert(key, i);
+                without_shortlinks.push((i, req));
+            }
         } else {
             with_shortlinks.push((i, req));
         }
     }
 
-    for (i, res) in add_links(with_shortlinks, db, false).0 {
+    for (i, res) in add_links(with_shortlinks, db, false, nocase, grace_period, None).0 {
         output[i] = res
     }
 
-    let with_link = |mut req: NewURLRequest, retry: bool| {
-        req.shortlink = gen_link(
-            &config.slug_style,
-            config.slug_length,
-            config.allow_capital_letters,
-            retry,
-        );
-        req
-    };
-    let (successful, rejected) = add_links(
-        without_shortlinks
-            .into_iter()
-            .map(|(i, r)| (i, with_link(r, false)))
-            .collect(),
-        db,
-        true,
-    );
-    for (i, res) in successful {
-        if res.is_ok() {
-            output[i] = res
-        }
-    }
-    if let Some(rej) = rejected {
+    if let Some(sqids) = &config.sequential_slugs {
+        // The slugs are assigned from the ids reserved while inserting
+        let encode =
+            |id| slugs::encode_id(sqids, id).filter(|s| !config.reserved_slugs.is_reserved(s));
         for (i, res) in add_links(
-            rej.into_iter()
-                .map(|(i, r)| (i, with_link(r, true)))
-                .collect(),
+            without_shortlinks,
             db,
             false,
+            nocase,
+            grace_period,
+            Some(&encode),
         )
         .0
         {
             output[i] = res
         }
+    } else {
+        // Requests which couldn't get a slug are left as server errors
+        let with_link = |(i, mut req): (usize, NewURLRequest), retry: bool| {
+            req.shortlink = gen_link(config, retry)?;
+            Some((i, req))
+        };
+        let (successful, rejected) = add_links(
+            without_shortlinks
+                .into_iter()
+                .filter_map(|r| with_link(r, false))
+                .collect(),
+            db,
+            true,
+            nocase,
+            grace_period,
+            None,
+        );
+        for (i, res) in successful {
+            if res.is_ok() {
+                output[i] = res
+            }
+        }
+        if let Some(rej) = rejected {
+            for (i, res) in add_links(
+                rej.into_iter().filter_map(|r| with_link(r, true)).collect(),
+                db,
+                false,
+                nocase,
+                grace_period,
+                None,
+            )
+            .0
+            {
+                output[i] = res
+            }
+        }
     }
 
-    if !single_request {
-        debug!("Processed a batch of {} requests.", output.len());
+    for (i, first) in reused {
+        output[i] = output[first].clone().map(|added| AddedLink {
+            reused: true,
+            ..added
+        });
     }
-    Ok((output, single_request))
+    output
 }
 
 // Make checks and then request the DB to edit an URL entry
 pub(super) async fn edit_link_helper(
     req: &str,
     db: &Connection,
-    hits_tx: &mpsc::Sender<(String, bool)>,
+    hits_tx: &mpsc::Sender<HitUpdate>,
     config: &Config,
+    lists: &DomainLists,
 ) -> Result<(), ChhotoError> {
     let chunks: EditURLRequest;
     if let Ok(json) = serde_json::from_str(req) {
@@ -268,35 +507,112 @@ pub(super) async fn edit_link_helper(
             reason: "Malformed request!".to_owned(),
         });
     }
-    if !is_shortlink_valid(&chunks.shortlink, config.allow_capital_letters) {
-        return Err(ClientError {
+    edit_link_request(chunks, db, hits_tx, config, lists).await
+}
+
+// Validate the edit, and apply it
+pub(super) async fn edit_link_request(
+    chunks: EditURLRequest,
+    db: &Connection,
+    hits_tx: &mpsc::Sender<HitUpdate>,
+    config: &Config,
+    lists: &DomainLists,
+) -> Result<(), ChhotoError> {
+    let Some(domain) = config.normalize_domain(&chunks.domain) else {
+        return Err(InvalidField {
+            field: "domain",
+            reason: "Invalid domain!".to_owned(),
+        });
+    };
+    if !is_shortlink_valid(&chunks.shortlink, config.allow_mixed_case()) {
+        return Err(InvalidField {
+            field: "shortlink",
             reason: "Invalid shortlink!".to_owned(),
         });
     } else if !is_longlink_valid(&chunks.longlink, &config.allowed_protocols) {
-        return Err(ClientError {
+        return Err(InvalidField {
+            field: "longlink",
             reason: "Invalid longlink!".to_owned(),
         });
     } else if !is_note_valid(&chunks.notes) {
-        return Err(ClientError {
+        return Err(InvalidField {
+            field: "notes",
             reason: "Invalid notes!".to_owned(),
         });
+    } else if !are_rules_valid(&chunks.rules, &config.allowed_protocols) {
+        return Err(InvalidField {
+            field: "rules",
+            reason: "Invalid redirect rules!".to_owned(),
+        });
+    } else if !are_targets_valid(&chunks.targets, &config.allowed_protocols) {
+        return Err(InvalidField {
+            field: "targets",
+            reason: "Invalid targets!".to_owned(),
+        });
+    } else if !is_opengraph_valid(&chunks.opengraph) {
+        return Err(InvalidField {
+            field: "opengraph",
+            reason: "Invalid OpenGraph metadata!".to_owned(),
+        });
+    } else if !are_destinations_allowed(
+        &chunks.longlink,
+        &chunks.rules,
+        &chunks.targets,
+        lists,
+        false,
+    ) {
+        return Err(Forbidden {
+            reason: "Destination is not allowed!".to_owned(),
+        });
+    } else if !are_aliases_valid(&chunks.aliases, config) {
+        return Err(InvalidField {
+            field: "aliases",
+            reason: "Invalid aliases!".to_owned(),
+        });
+    }
+    // The link and its aliases are changed together, so that a failure leaves both untouched
+    let tx = db.unchecked_transaction().map_err(|e| {
+        error!("Unable to start a transaction for editing a link: {e}");
+        ServerError
+    })?;
+    if let Some(aliases) = &chunks.aliases {
+        database::set_aliases(
+            &chunks.shortlink,
+            &domain,
+            aliases,
+            config.case_insensitive_slugs,
+            &tx,
+        )?;
     }
+    let mut targets = chunks.targets;
+    targets.iter_mut().flatten().for_each(|t| t.hits = 0);
     let result = database::edit_link(
         &chunks.shortlink,
+        &domain,
         &chunks.longlink,
         chunks.reset_hits,
         chunks.expiry_time.filter(|&t| t > 0),
         chunks.notes.filter(|s| !s.is_empty()).as_deref(),
+        chunks.rules.as_deref(),
+        targets.as_deref(),
+        chunks.sticky,
+        chunks.preview,
+        chunks.opengraph.as_ref(),
+        chunks.idle_ttl.map(|d| d.clamp(0, 157_784_760)),
+        config.case_insensitive_slugs,
         hits_tx,
-        db,
+        &tx,
     )
     .await;
     match result {
         // Zero rows returned means no updates
-        Ok(0) => Err(ClientError {
+        Ok(0) => Err(NotFound {
             reason: "The shortlink was not found, and could not be edited.".to_owned(),
         }),
-        Ok(_) => Ok(()),
+        Ok(_) => tx.commit().map_err(|e| {
+            error!("Unable to commit the edit of {}: {e}", chunks.shortlink);
+            ServerError
+        }),
         Err(()) => Err(ServerError),
     }
 }
@@ -304,96 +620,210 @@ pub(super) async fn edit_link_helper(
 // Check if link, and request DB to delete it if exists
 pub(super) fn delete_link_helper(
     shortlink: &str,
+    domain: &str,
     db: &Connection,
-    allow_capital_letters: bool,
+    config: &Config,
 ) -> Result<(), ChhotoError> {
-    if is_shortlink_valid(shortlink, allow_capital_letters) {
-        database::delete_link(shortlink, db)
+    let Some(domain) = config.normalize_domain(domain) else {
+        return Err(InvalidField {
+            field: "domain",
+            reason: "The domain is invalid.".to_owned(),
+        });
+    };
+    if is_shortlink_valid(shortlink, config.allow_mixed_case()) {
+        database::delete_link(shortlink, &domain, config.case_insensitive_slugs, db)
     } else {
-        Err(ClientError {
+        Err(InvalidField {
+            field: "shortlink",
             reason: "The shortlink is invalid.".to_owned(),
         })
     }
 }
 
-// Generate a random link using either adjective-name pair (default) of a slug or a-z, 0-9
-fn gen_link(
-    style: &SlugStyle,
-    len: usize,
-    allow_capital_letters: bool,
-    try_longer_slug: bool,
-) -> String {
-    #[rustfmt::skip]
-    static ADJECTIVES: [&str; 108] = ["admiring", "adoring", "affectionate", "agitated", "amazing", "angry", "awesome", "beautiful", 
-		"blissful", "bold", "boring", "brave", "busy", "charming", "clever", "compassionate", "competent", "condescending", "confident", "cool", 
-		"cranky", "crazy", "dazzling", "determined", "distracted", "dreamy", "eager", "ecstatic", "elastic", "elated", "elegant", "eloquent", "epic", 
-		"exciting", "fervent", "festive", "flamboyant", "focused", "friendly", "frosty", "funny", "gallant", "gifted", "goofy", "gracious", 
-		"great", "happy", "hardcore", "heuristic", "hopeful", "hungry", "infallible", "inspiring", "intelligent", "interesting", "jolly", 
-		"jovial", "keen", "kind", "laughing", "loving", "lucid", "magical", "modest", "musing", "mystifying", "naughty", "nervous", "nice", 
-		"nifty", "nostalgic", "objective", "optimistic", "peaceful", "pedantic", "pensive", "practical", "priceless", "quirky", "quizzical", 
-		"recursing", "relaxed", "reverent", "romantic", "sad", "serene", "sharp", "silly", "sleepy", "stoic", "strange", "stupefied", "suspicious", 
-		"sweet", "tender", "thirsty", "trusting", "unruffled", "upbeat", "vibrant", "vigilant", "vigorous", "wizardly", "wonderful", "xenodochial", 
-		"youthful", "zealous", "zen"];
-    #[rustfmt::skip]
-    static NAMES: [&str; 241] = ["agnesi", "albattani", "allen", "almeida", "antonelli", "archimedes", "ardinghelli", "aryabhata", "austin", 
-		"babbage", "banach", "banzai", "bardeen", "bartik", "bassi", "beaver", "bell", "benz", "bhabha", "bhaskara", "black", "blackburn", "blackwell", 
-		"bohr", "booth", "borg", "bose", "bouman", "boyd", "brahmagupta", "brattain", "brown", "buck", "burnell", "cannon", "carson", "cartwright", 
-		"carver", "cauchy", "cerf", "chandrasekhar", "chaplygin", "chatelet", "chatterjee", "chaum", "chebyshev", "clarke", "cohen", "colden", "cori", 
-		"cray", "curie", "curran", "darwin", "davinci", "dewdney", "dhawan", "diffie", "dijkstra", "dirac", "driscoll", "dubinsky", "easley", "edison", 
-		"einstein", "elbakyan", "elgamal", "elion", "ellis", "engelbart", "euclid", "euler", "faraday", "feistel", "fermat", "fermi", "feynman", "franklin", 
-		"gagarin", "galileo", "galois", "ganguly", "gates", "gauss", "germain", "goldberg", "goldstine", "goldwasser", "golick", "goodall", "gould", "greider", 
-		"grothendieck", "haibt", "hamilton", "hardy", "haslett", "hawking", "heisenberg", "hellman", "hermann", "herschel", "hertz", "heyrovsky", "hodgkin", 
-		"hofstadter", "hoover", "hopper", "hugle", "hypatia", "ishizaka", "jackson", "jang", "jemison", "jennings", "jepsen", "johnson", "joliot", "jones", 
-		"kalam", "kapitsa", "kare", "keldysh", "keller", "kepler", "khayyam", "khorana", "kilby", "kirch", "knuth", "kowalevski", "lalande", "lamarr", 
-		"lamport", "leakey", "leavitt", "lederberg", "lehmann", "lewin", "lichterman", "liskov", "lovelace", "lumiere", "mahavira", "margulis", "matsumoto", 
-		"maxwell", "mayer", "mccarthy", "mcclintock", "mclaren", "mclean", "mcnulty", "meitner", "mendel", "mendeleev", "meninsky", "merkle", "mestorf", 
-		"mirzakhani", "montalcini", "moore", "morse", "moser", "murdock", "napier", "nash", "neumann", "newton", "nightingale", "nobel", "noether", "northcutt", 
-		"noyce", "panini", "pare", "pascal", "pasteur", "payne", "perlman", "pike", "poincare", "poitras", "proskuriakova", "ptolemy", "raman", "ramanujan", 
-		"rhodes", "ride", "riemann", "ritchie", "robinson", "roentgen", "rosalind", "rubin", "saha", "sammet", "sanderson", "satoshi", "shamir", "shannon", 
-		"shaw", "shirley", "shockley", "shtern", "sinoussi", "snyder", "solomon", "spence", "stonebraker", "sutherland", "swanson", "swartz", "swirles", 
-		"taussig", "tesla", "tharp", "thompson", "torvalds", "tu", "turing", "varahamihira", "vaughan", "vaughn", "villani", "visvesvaraya", "volhard", 
-		"wescoff", "weierstrass", "wilbur", "wiles", "williams", "williamson", "wilson", "wing", "wozniak", "wright", "wu", "yalow", "yonath", "zhukovsky"];
-
-    static CHARS_SMALL: [char; 36] = [
-        'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r',
-        's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9',
-    ];
-    // uppercase and lowercase characters; exclude ambiguous characters
-    static CHARS_CAPITAL: [char; 58] = [
-        'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'J', 'K', 'L', 'M', 'N', 'P', 'Q', 'R', 'S', 'T',
-        'U', 'V', 'W', 'X', 'Y', 'Z', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'm',
-        'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '1', '2', '3', '4', '5',
-        '6', '7', '8', '9',
-    ];
-
-    match style {
-        SlugStyle::Uid => {
-            let slug_len = if try_longer_slug { len + 4 } else { len };
-            debug!(
-                "Generating a link with style: UID, length: {slug_len}, allow_capital_letters: {allow_capital_letters}"
-            );
-            if allow_capital_letters {
-                nanoid!(slug_len, &CHARS_CAPITAL)
-            } else {
-                nanoid!(slug_len, &CHARS_SMALL)
-            }
+// Revive a link that expired within the grace period
+pub(super) fn revive_link_helper(
+    req: &str,
+    db: &Connection,
+    config: &Config,
+) -> Result<(), ChhotoError> {
+    let Ok(req) = serde_json::from_str::<ReviveRequest>(req) else {
+        return Err(ClientError {
+            reason: "Malformed request!".to_owned(),
+        });
+    };
+    let Some(domain) = config.normalize_domain(&req.domain) else {
+        return Err(InvalidField {
+            field: "domain",
+            reason: "Invalid domain!".to_owned(),
+        });
+    };
+    // Allow max delay of 5 years, and no delay means the link never expires
+    let expiry_time = req
+        .expiry_delay
+        .map(|d| d.clamp(0, 157_784_760))
+        .filter(|&d| d > 0)
+        .map(|d| chrono::Utc::now().timestamp() + d);
+    database::revive_link(
+        &req.shortlink,
+        &domain,
+        expiry_time,
+        config.case_insensitive_slugs,
+        config.expiry_grace_period,
+        db,
+    )
+}
+
+// Restore a backup from the backups directory, which is only allowed in maintenance mode
+pub(super) fn restore_helper(
+    req: &str,
+    db: &mut Connection,
+    config: &Config,
+    maintenance: bool,
+) -> Result<i64, ChhotoError> {
+    let Ok(req) = serde_json::from_str::<RestoreRequest>(req) else {
+        return Err(ClientError {
+            reason: "Malformed request!".to_owned(),
+        });
+    };
+    if !maintenance {
+        return Err(ClientError {
+            reason: "The server needs to be in maintenance mode to restore a backup.".to_owned(),
+        });
+    }
+    // Only files directly within the backups directory can be restored
+    if req.backup.is_empty() || req.backup.contains(['/', '\\']) || req.backup.starts_with('.') {
+        return Err(InvalidField {
+            field: "backup",
+            reason: "Invalid backup name!".to_owned(),
+        });
+    }
+    let db_path = db.path().map(Path::new).ok_or(ServerError)?;
+    let path = database::backup_dir(db_path).join(&req.backup);
+    if !path.is_file() {
+        return Err(NotFound {
+            reason: "The backup does not exist!".to_owned(),
+        });
+    }
+    database::restore(db, &path, &config.backup_settings, false).map_err(|e| {
+        error!("Unable to restore {}: {e}", req.backup);
+        ClientError { reason: e }
+    })
+}
+
+// Generate a random link that isn't reserved
+fn gen_link(config: &Config, try_longer_slug: bool) -> Option<String> {
+    for _ in 0..16 {
+        let slug = slugs::gen_slug(
+            &config.slug_style,
+            config.slug_length,
+            config.allow_capital_letters,
+            try_longer_slug,
+        );
+        if !config.reserved_slugs.is_reserved(&slug) {
+            return Some(slug);
         }
-        SlugStyle::Pair => {
-            debug!("Generating a link with style: Pair.");
-            let adj = ADJECTIVES
-                .choose(&mut rand::rng())
-                .expect("Error choosing random adjective.")
-                .to_owned();
-            let name = NAMES
-                .choose(&mut rand::rng())
-                .expect("Error choosing random name.");
-            if try_longer_slug {
-                format!("{adj}-{name}-{:04}", random_range(0..=9999))
-            } else {
-                format!("{adj}-{name}")
-            }
+        debug!("Skipping reserved slug {slug}.");
+    }
+    error!("Unable to generate a slug that isn't reserved.");
+    None
+}
+
+// Check whether a shortlink is valid and free to use
+pub(super) fn check_availability(
+    shortlink: &str,
+    domain: &str,
+    config: &Config,
+    db: &Connection,
+) -> Result<SlugAvailability, ChhotoError> {
+    let invalid =
+        if shortlink.is_empty() || !is_shortlink_valid(shortlink, config.allow_mixed_case()) {
+            Some("Invalid shortlink!")
+        } else if config.reserved_slugs.is_reserved(shortlink) {
+            Some("Shortlink is reserved!")
+        } else {
+            None
+        };
+    let usage = match invalid {
+        Some(_) => None,
+        None => database::slug_usage(
+            shortlink,
+            domain,
+            config.case_insensitive_slugs,
+            config.expiry_grace_period,
+            db,
+        )?,
+    };
+    // Expired links are replaced when their shortlink is added again once their grace period is over,
+    // but aliases of expired links aren't
+    let available = invalid.is_none()
+        && usage
+            .as_ref()
+            .is_none_or(|u| u.expired && !u.in_grace_period && !u.alias);
+    let reason = match invalid {
+        Some(reason) => Some(reason.to_owned()),
+        None => (!available).then(|| "Short URL is already in use!".to_owned()),
+    };
+    Ok(SlugAvailability {
+        success: true,
+        error: false,
+        shortlink: shortlink.to_owned(),
+        valid: invalid.is_none(),
+        available,
+        expired: usage.is_some_and(|u| u.expired),
+        reason,
+    })
+}
+
+// Suggest free shortlinks, optionally starting with a given base
+pub(super) fn suggest_slugs(
+    params: SuggestParams,
+    config: &Config,
+    db: &Connection,
+) -> Result<Vec<String>, ChhotoError> {
+    let Some(domain) = config.normalize_domain(&params.domain) else {
+        return Err(InvalidField {
+            field: "domain",
+            reason: "Invalid domain!".to_owned(),
+        });
+    };
+    let base = params.base;
+    if !is_shortlink_valid(&base, config.allow_mixed_case()) {
+        return Err(InvalidField {
+            field: "base",
+            reason: "Invalid base!".to_owned(),
+        });
+    }
+    let count = params.count.unwrap_or(5).clamp(1, 20);
+    let is_free = |slug: &str| check_availability(slug, &domain, config, db).map(|a| a.available);
+
+    let mut suggestions = Vec::with_capacity(count);
+    if !base.is_empty() && is_free(&base)? {
+        suggestions.push(base.clone());
+    }
+    for _ in 0..count * 8 {
+        if suggestions.len() >= count {
+            break;
+        }
+        let slug = if base.is_empty() {
+            gen_suggestion(config, false)
+        } else {
+            format!("{base}-{}", gen_suggestion(config, true))
+        };
+        if !suggestions.contains(&slug) && is_free(&slug)? {
+            suggestions.push(slug);
         }
     }
+    Ok(suggestions)
+}
+
+// Sequential slugs are only assigned while adding a link, so random ones are suggested instead
+// Suffixes for a base are kept short
+fn gen_suggestion(config: &Config, suffix: bool) -> String {
+    let (style, len) = match &config.slug_style {
+        SlugStyle::Uid if suffix => (&SlugStyle::Uid, 4),
+        style => (style, config.slug_length),
+    };
+    slugs::gen_slug(style, len, config.allow_capital_letters, false)
 }
 
 // 404 error page
@@ -403,3 +833,28 @@ pub(crate) async fn error404() -> impl Responder {
         .customize()
         .with_status(StatusCode::NOT_FOUND)
 }
+
+// Reject changes while in maintenance mode, except for the admin endpoints and logging in or out
+pub(crate) async fn maintenance_guard(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse, Error> {
+    let blocked = req
+        .app_data::<web::Data<AppState>>()
+        .is_some_and(|data| data.maintenance.load(Ordering::Relaxed))
+        && !matches!(*req.method(), Method::GET | Method::HEAD)
+        && !req.path().starts_with("/api/admin/")
+        && !matches!(req.path(), "/api/login" | "/api/logout" | "/api/expand");
+    if blocked {
+        let body = JSONResponse {
+            success: false,
+            error: true,
+            reason: "The server is in maintenance mode.".to_owned(),
+        };
+        let resp = HttpResponse::ServiceUnavailable().json(body);
+        return Ok(req.into_response(resp));
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_boxed_body)
+}
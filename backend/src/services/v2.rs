@@ -0,0 +1,356 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+// Version 2 of the API, which returns the same JSON envelope to every caller
+use actix_web::{HttpResponse, delete, get, http::StatusCode, patch, post, web};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    auth::Auth,
+    config::Config,
+    database::{self, DBRow},
+    lists,
+    services::types::{
+        ChhotoError::{self, ClientError, InvalidField, ServerError, Unauthorized},
+        CreatedLink, DomainParams, EditURLRequest, Envelope, GetReqParams, Link, LinkPatch,
+        NewLink, NewURLRequest, PublicLink,
+    },
+    utils,
+};
+
+// Wrap the result in the envelope
+fn respond<T: Serialize>(result: Result<(StatusCode, Option<T>), ChhotoError>) -> HttpResponse {
+    match result {
+        Ok((status, data)) => HttpResponse::build(status).json(Envelope {
+            success: true,
+            data,
+            error: None,
+        }),
+        Err(error) => HttpResponse::build(error.status()).json(Envelope::<()> {
+            success: false,
+            data: None,
+            error: Some(error.into()),
+        }),
+    }
+}
+
+// Check the auth, and return whether the caller is acting in public mode
+fn authorize(auth: Auth, allow_public: bool) -> Result<bool, ChhotoError> {
+    match auth {
+        Auth::ValidAPIKey | Auth::ValidSession => Ok(false),
+        Auth::None { result: _ } if allow_public => Ok(true),
+        Auth::None { result } | Auth::InvalidAPIKey { result } => Err(Unauthorized {
+            reason: result.reason,
+        }),
+    }
+}
+
+fn parse<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, ChhotoError> {
+    serde_json::from_str(body).map_err(|e| ClientError {
+        reason: format!("Malformed request: {e}"),
+    })
+}
+
+fn normalize_domain(params: &DomainParams, config: &Config) -> Result<String, ChhotoError> {
+    config
+        .normalize_domain(&params.domain)
+        .ok_or_else(|| InvalidField {
+            field: "domain",
+            reason: "Invalid domain!".to_owned(),
+        })
+}
+
+fn to_link(row: &DBRow, config: &Config) -> Link {
+    Link {
+        slug: row.shortlink.clone(),
+        domain: row.domain.clone(),
+        short_url: format!("{}/{}", config.site_url_for(&row.domain), row.shortlink),
+        long_url: row.longlink.clone(),
+        hits: row.hits,
+        expiry_time: row.expiry_time,
+        notes: row.notes.clone(),
+        rules: row.rules.clone(),
+        targets: row.targets.clone(),
+        sticky: row.sticky,
+        created_at: row.created_at,
+        updated_at: row.updated_at,
+        last_accessed_at: row.last_accessed_at,
+        idle_ttl: row.idle_ttl,
+        preview: row.preview,
+        opengraph: row.opengraph.clone(),
+        health_status: row.health_status,
+        health_checked_at: row.health_checked_at,
+        health_failures: row.health_failures,
+        disabled: row.disabled,
+        aliases: row.aliases.clone(),
+        snippet: row.snippet.clone(),
+    }
+}
+
+// List the links
+#[utoipa::path(
+    tag = "v2",
+    params(GetReqParams),
+    responses(
+        (status = 200, description = "The matching links", body = Envelope<Vec<Link>>),
+        (status = 400, description = "Invalid parameters", body = Envelope<Link>),
+        (status = 401, description = "Not authorized", body = Envelope<Link>),
+    ),
+    security(("api_key" = []), ("session" = []))
+)]
+#[get("/api/v2/links")]
+pub(crate) async fn list(
+    params: web::Query<GetReqParams>,
+    auth: Auth,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let config = &data.config;
+    respond(
+        authorize(auth, false)
+            .and_then(|_| utils::list_links(&data.reader, params.into_inner(), config))
+            .map(|links| {
+                let links: Vec<_> = links.iter().map(|row| to_link(row, config)).collect();
+                (StatusCode::OK, Some(links))
+            }),
+    )
+}
+
+// Add a link, with the slug generated unless given in the body
+#[utoipa::path(
+    tag = "v2",
+    params(DomainParams),
+    request_body = NewLink,
+    responses(
+        (status = 201, description = "The link was added", body = Envelope<CreatedLink>),
+        (status = 200, description = "An existing link was reused", body = Envelope<CreatedLink>),
+        (status = 400, description = "Invalid request", body = Envelope<Link>),
+        (status = 401, description = "Not authorized", body = Envelope<Link>),
+        (status = 403, description = "The slug is reserved, or the destination isn't allowed", body = Envelope<Link>),
+        (status = 409, description = "The slug is already in use", body = Envelope<Link>),
+    ),
+    security(("api_key" = []), ("session" = []), ())
+)]
+#[post("/api/v2/links")]
+pub(crate) async fn create(
+    body: String,
+    params: web::Query<DomainParams>,
+    auth: Auth,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    respond(add_link(None, &body, &params, auth, &data).await)
+}
+
+// Add a link with the given slug
+#[utoipa::path(
+    tag = "v2",
+    params(("slug" = String, Path), DomainParams),
+    request_body = NewLink,
+    responses(
+        (status = 201, description = "The link was added", body = Envelope<CreatedLink>),
+        (status = 400, description = "Invalid request", body = Envelope<Link>),
+        (status = 401, description = "Not authorized", body = Envelope<Link>),
+        (status = 403, description = "The slug is reserved, or the destination isn't allowed", body = Envelope<Link>),
+        (status = 409, description = "The slug is already in use", body = Envelope<Link>),
+    ),
+    security(("api_key" = []), ("session" = []), ())
+)]
+#[post("/api/v2/links/{slug}")]
+pub(crate) async fn create_with_slug(
+    slug: web::Path<String>,
+    body: String,
+    params: web::Query<DomainParams>,
+    auth: Auth,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    respond(add_link(Some(&slug), &body, &params, auth, &data).await)
+}
+
+async fn add_link(
+    slug: Option<&str>,
+    body: &str,
+    params: &DomainParams,
+    auth: Auth,
+    data: &AppState,
+) -> Result<(StatusCode, Option<CreatedLink>), ChhotoError> {
+    let config = &data.config;
+    let public_mode = authorize(auth, config.public_mode)?;
+    let link: NewLink = parse(body)?;
+    let shortlink = match slug {
+        Some(slug) if !link.slug.is_empty() && link.slug != slug => {
+            return Err(InvalidField {
+                field: "slug",
+                reason: "The slug doesn't match the one in the path!".to_owned(),
+            });
+        }
+        Some(slug) => slug.to_owned(),
+        None => link.slug,
+    };
+    let request = NewURLRequest {
+        shortlink,
+        longlink: link.long_url,
+        expiry_delay: link.expiry_delay,
+        notes: link.notes,
+        domain: params.domain.clone(),
+        rules: link.rules,
+        targets: link.targets,
+        sticky: link.sticky,
+        preview: link.preview,
+        opengraph: link.opengraph,
+        dedupe: link.dedupe,
+        aliases: link.aliases,
+        idle_ttl: link.idle_ttl,
+    };
+    let mut db = data.writer.lock().await;
+    let lists = lists::current(&data.lists);
+    let added =
+        utils::add_link_requests(Vec::from([request]), &mut db, config, &lists, public_mode)
+            .into_iter()
+            .next()
+            .unwrap_or(Err(ServerError))?;
+    let row = database::find_url(
+        &added.shortlink,
+        &added.domain,
+        config.case_insensitive_slugs,
+        &db,
+    )?;
+    // Reused links aren't newly created
+    let status = if added.reused {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    // Public mode users only get back what they sent, and not the private details of the link
+    let link = if public_mode {
+        CreatedLink::Public(PublicLink {
+            short_url: format!("{}/{}", config.site_url_for(&row.domain), row.shortlink),
+            slug: row.shortlink,
+            domain: row.domain,
+            long_url: row.longlink,
+            expiry_time: row.expiry_time,
+        })
+    } else {
+        CreatedLink::Full(Box::new(to_link(&row, config)))
+    };
+    Ok((status, Some(link)))
+}
+
+// Get a single link, using its slug or one of its aliases
+#[utoipa::path(
+    tag = "v2",
+    params(("slug" = String, Path), DomainParams),
+    responses(
+        (status = 200, description = "The link", body = Envelope<Link>),
+        (status = 401, description = "Not authorized", body = Envelope<Link>),
+        (status = 404, description = "The link does not exist", body = Envelope<Link>),
+    ),
+    security(("api_key" = []), ("session" = []))
+)]
+#[get("/api/v2/links/{slug}")]
+pub(crate) async fn show(
+    slug: web::Path<String>,
+    params: web::Query<DomainParams>,
+    auth: Auth,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let config = &data.config;
+    let result = || -> Result<_, ChhotoError> {
+        authorize(auth, false)?;
+        let domain = normalize_domain(&params, config)?;
+        let row = database::find_url(&slug, &domain, config.case_insensitive_slugs, &data.reader)?;
+        Ok((StatusCode::OK, Some(to_link(&row, config))))
+    };
+    respond(result())
+}
+
+// Change some of the fields of a link
+#[utoipa::path(
+    tag = "v2",
+    params(("slug" = String, Path), DomainParams),
+    request_body = LinkPatch,
+    responses(
+        (status = 200, description = "The edited link", body = Envelope<Link>),
+        (status = 400, description = "Invalid request", body = Envelope<Link>),
+        (status = 401, description = "Not authorized", body = Envelope<Link>),
+        (status = 403, description = "The destination isn't allowed", body = Envelope<Link>),
+        (status = 404, description = "The link does not exist", body = Envelope<Link>),
+        (status = 409, description = "An alias is already in use", body = Envelope<Link>),
+    ),
+    security(("api_key" = []), ("session" = []))
+)]
+#[patch("/api/v2/links/{slug}")]
+pub(crate) async fn update(
+    slug: web::Path<String>,
+    body: String,
+    params: web::Query<DomainParams>,
+    auth: Auth,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    respond(edit_link(&slug, &body, &params, auth, &data).await)
+}
+
+async fn edit_link(
+    slug: &str,
+    body: &str,
+    params: &DomainParams,
+    auth: Auth,
+    data: &AppState,
+) -> Result<(StatusCode, Option<Link>), ChhotoError> {
+    let config = &data.config;
+    authorize(auth, false)?;
+    let patch: LinkPatch = parse(body)?;
+    let domain = normalize_domain(params, config)?;
+    let nocase = config.case_insensitive_slugs;
+    let db = data.writer.lock().await;
+    // Aliases resolve to the link they belong to
+    let row = database::find_url(slug, &domain, nocase, &db)?;
+    let request = EditURLRequest {
+        shortlink: row.shortlink.clone(),
+        domain: domain.clone(),
+        longlink: patch.long_url.unwrap_or(row.longlink),
+        reset_hits: patch.reset_hits,
+        expiry_time: patch.expiry_time,
+        notes: patch.notes,
+        rules: patch.rules,
+        targets: patch.targets,
+        sticky: patch.sticky,
+        preview: patch.preview,
+        opengraph: patch.opengraph,
+        aliases: patch.aliases,
+        idle_ttl: patch.idle_ttl,
+    };
+    let lists = lists::current(&data.lists);
+    utils::edit_link_request(request, &db, &data.hits_tx, config, &lists).await?;
+    let row = database::find_url(&row.shortlink, &domain, nocase, &db)?;
+    Ok((StatusCode::OK, Some(to_link(&row, config))))
+}
+
+// Delete a link, or just one of its aliases
+#[utoipa::path(
+    tag = "v2",
+    params(("slug" = String, Path), DomainParams),
+    responses(
+        (status = 200, description = "The link or alias was deleted", body = Envelope<Link>),
+        (status = 401, description = "Not authorized", body = Envelope<Link>),
+        (status = 404, description = "The link does not exist", body = Envelope<Link>),
+    ),
+    security(("api_key" = []), ("session" = []))
+)]
+#[delete("/api/v2/links/{slug}")]
+pub(crate) async fn remove(
+    slug: web::Path<String>,
+    params: web::Query<DomainParams>,
+    auth: Auth,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let result = match authorize(auth, false) {
+        Ok(_) => utils::delete_link_helper(
+            &slug,
+            &params.domain,
+            &*data.writer.lock().await,
+            &data.config,
+        ),
+        Err(error) => Err(error),
+    };
+    respond(result.map(|()| (StatusCode::OK, None::<Link>)))
+}
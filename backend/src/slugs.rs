@@ -0,0 +1,296 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+use log::{debug, warn};
+use nanoid::nanoid;
+use rand::{random_range, seq::IndexedRandom};
+use sqids::Sqids;
+use std::{collections::HashMap, fs, sync::Arc};
+
+use crate::config::SlugStyle;
+
+#[rustfmt::skip]
+static ADJECTIVES: [&str; 108] = ["admiring", "adoring", "affectionate", "agitated", "amazing", "angry", "awesome", "beautiful", 
+		"blissful", "bold", "boring", "brave", "busy", "charming", "clever", "compassionate", "competent", "condescending", "confident", "cool", 
+		"cranky", "crazy", "dazzling", "determined", "distracted", "dreamy", "eager", "ecstatic", "elastic", "elated", "elegant", "eloquent", "epic", 
+		"exciting", "fervent", "festive", "flamboyant", "focused", "friendly", "frosty", "funny", "gallant", "gifted", "goofy", "gracious", 
+		"great", "happy", "hardcore", "heuristic", "hopeful", "hungry", "infallible", "inspiring", "intelligent", "interesting", "jolly", 
+		"jovial", "keen", "kind", "laughing", "loving", "lucid", "magical", "modest", "musing", "mystifying", "naughty", "nervous", "nice", 
+		"nifty", "nostalgic", "objective", "optimistic", "peaceful", "pedantic", "pensive", "practical", "priceless", "quirky", "quizzical", 
+		"recursing", "relaxed", "reverent", "romantic", "sad", "serene", "sharp", "silly", "sleepy", "stoic", "strange", "stupefied", "suspicious", 
+		"sweet", "tender", "thirsty", "trusting", "unruffled", "upbeat", "vibrant", "vigilant", "vigorous", "wizardly", "wonderful", "xenodochial", 
+		"youthful", "zealous", "zen"];
+#[rustfmt::skip]
+static NAMES: [&str; 241] = ["agnesi", "albattani", "allen", "almeida", "antonelli", "archimedes", "ardinghelli", "aryabhata", "austin", 
+		"babbage", "banach", "banzai", "bardeen", "bartik", "bassi", "beaver", "bell", "benz", "bhabha", "bhaskara", "black", "blackburn", "blackwell", 
+		"bohr", "booth", "borg", "bose", "bouman", "boyd", "brahmagupta", "brattain", "brown", "buck", "burnell", "cannon", "carson", "cartwright", 
+		"carver", "cauchy", "cerf", "chandrasekhar", "chaplygin", "chatelet", "chatterjee", "chaum", "chebyshev", "clarke", "cohen", "colden", "cori", 
+		"cray", "curie", "curran", "darwin", "davinci", "dewdney", "dhawan", "diffie", "dijkstra", "dirac", "driscoll", "dubinsky", "easley", "edison", 
+		"einstein", "elbakyan", "elgamal", "elion", "ellis", "engelbart", "euclid", "euler", "faraday", "feistel", "fermat", "fermi", "feynman", "franklin", 
+		"gagarin", "galileo", "galois", "ganguly", "gates", "gauss", "germain", "goldberg", "goldstine", "goldwasser", "golick", "goodall", "gould", "greider", 
+		"grothendieck", "haibt", "hamilton", "hardy", "haslett", "hawking", "heisenberg", "hellman", "hermann", "herschel", "hertz", "heyrovsky", "hodgkin", 
+		"hofstadter", "hoover", "hopper", "hugle", "hypatia", "ishizaka", "jackson", "jang", "jemison", "jennings", "jepsen", "johnson", "joliot", "jones", 
+		"kalam", "kapitsa", "kare", "keldysh", "keller", "kepler", "khayyam", "khorana", "kilby", "kirch", "knuth", "kowalevski", "lalande", "lamarr", 
+		"lamport", "leakey", "leavitt", "lederberg", "lehmann", "lewin", "lichterman", "liskov", "lovelace", "lumiere", "mahavira", "margulis", "matsumoto", 
+		"maxwell", "mayer", "mccarthy", "mcclintock", "mclaren", "mclean", "mcnulty", "meitner", "mendel", "mendeleev", "meninsky", "merkle", "mestorf", 
+		"mirzakhani", "montalcini", "moore", "morse", "moser", "murdock", "napier", "nash", "neumann", "newton", "nightingale", "nobel", "noether", "northcutt", 
+		"noyce", "panini", "pare", "pascal", "pasteur", "payne", "perlman", "pike", "poincare", "poitras", "proskuriakova", "ptolemy", "raman", "ramanujan", 
+		"rhodes", "ride", "riemann", "ritchie", "robinson", "roentgen", "rosalind", "rubin", "saha", "sammet", "sanderson", "satoshi", "shamir", "shannon", 
+		"shaw", "shirley", "shockley", "shtern", "sinoussi", "snyder", "solomon", "spence", "stonebraker", "sutherland", "swanson", "swartz", "swirles", 
+		"taussig", "tesla", "tharp", "thompson", "torvalds", "tu", "turing", "varahamihira", "vaughan", "vaughn", "villani", "visvesvaraya", "volhard", 
+		"wescoff", "weierstrass", "wilbur", "wiles", "williams", "williamson", "wilson", "wing", "wozniak", "wright", "wu", "yalow", "yonath", "zhukovsky"];
+
+static CHARS_SMALL: [char; 36] = [
+    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's',
+    't', 'u', 'v', 'w', 'x', 'y', 'z', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9',
+];
+// uppercase and lowercase characters; exclude ambiguous characters
+static CHARS_CAPITAL: [char; 58] = [
+    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'J', 'K', 'L', 'M', 'N', 'P', 'Q', 'R', 'S', 'T', 'U',
+    'V', 'W', 'X', 'Y', 'Z', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'm', 'n', 'o',
+    'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '1', '2', '3', '4', '5', '6', '7', '8',
+    '9',
+];
+
+static CONSONANTS: [char; 21] = [
+    'b', 'c', 'd', 'f', 'g', 'h', 'j', 'k', 'l', 'm', 'n', 'p', 'q', 'r', 's', 't', 'v', 'w', 'x',
+    'y', 'z',
+];
+static VOWELS: [char; 5] = ['a', 'e', 'i', 'o', 'u'];
+
+// Named word lists which can be used in templates
+pub(crate) type WordLists = HashMap<String, Arc<[String]>>;
+
+// A single piece of a slug template
+#[derive(Clone)]
+enum Part {
+    Literal(String),
+    Words(Arc<[String]>),
+    Chars(&'static [char], usize),
+    Pronounceable(usize),
+}
+
+impl Part {
+    // Number of different values this part can take
+    fn choices(&self) -> u128 {
+        match self {
+            Part::Literal(_) => 1,
+            Part::Words(words) => words.len() as u128,
+            Part::Chars(chars, n) => (chars.len() as u128).saturating_pow(*n as u32),
+            Part::Pronounceable(n) => (CONSONANTS.len() as u128)
+                .saturating_pow(n.div_ceil(2) as u32)
+                .saturating_mul((VOWELS.len() as u128).saturating_pow((n / 2) as u32)),
+        }
+    }
+
+    fn push_to(&self, slug: &mut String) {
+        let mut rng = rand::rng();
+        match self {
+            Part::Literal(text) => slug.push_str(text),
+            Part::Words(words) => slug.push_str(words.choose(&mut rng).expect("Empty word list.")),
+            Part::Chars(chars, n) => {
+                slug.extend((0..*n).map(|_| *chars.choose(&mut rng).expect("Empty character set.")))
+            }
+            Part::Pronounceable(n) => slug.extend((0..*n).map(|i| {
+                let set: &[char] = if i % 2 == 0 { &CONSONANTS } else { &VOWELS };
+                *set.choose(&mut rng).expect("Empty character set.")
+            })),
+        }
+    }
+}
+
+// A pattern like {adj}-{noun}-{digits:3}, where the placeholders are filled in randomly
+#[derive(Clone)]
+pub(crate) struct SlugTemplate {
+    pub(crate) pattern: String,
+    parts: Vec<Part>,
+}
+
+impl SlugTemplate {
+    pub(crate) fn parse(pattern: &str, lists: &WordLists) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = pattern;
+        while !rest.is_empty() {
+            let Some(start) = rest.find('{') else {
+                parts.push(literal(rest)?);
+                break;
+            };
+            if start > 0 {
+                parts.push(literal(&rest[..start])?);
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("Unclosed placeholder in {pattern}."))?
+                + start;
+            parts.push(placeholder(&rest[start + 1..end], lists)?);
+            rest = &rest[end + 1..];
+        }
+        if !parts.iter().any(|p| p.choices() > 1) {
+            return Err(format!("The template {pattern} has no random parts."));
+        }
+        Ok(SlugTemplate {
+            pattern: pattern.to_owned(),
+            parts,
+        })
+    }
+
+    // Number of different slugs the template can generate
+    pub(crate) fn collision_space(&self) -> u128 {
+        self.parts
+            .iter()
+            .fold(1, |acc: u128, p| acc.saturating_mul(p.choices()))
+    }
+
+    fn generate(&self) -> String {
+        let mut slug = String::new();
+        self.parts.iter().for_each(|p| p.push_to(&mut slug));
+        slug
+    }
+}
+
+// Text outside placeholders is used as is, so it must be valid in a slug
+fn literal(text: &str) -> Result<Part, String> {
+    if text
+        .chars()
+        .all(|c| c.is_ascii_digit() || c.is_ascii_lowercase() || c == '-' || c == '_')
+    {
+        Ok(Part::Literal(text.to_owned()))
+    } else {
+        Err(format!("Invalid text {text} in slug template."))
+    }
+}
+
+fn placeholder(spec: &str, lists: &WordLists) -> Result<Part, String> {
+    let Some((kind, len)) = spec.split_once(':') else {
+        return lists
+            .get(spec)
+            .map(|words| Part::Words(Arc::clone(words)))
+            .ok_or_else(|| format!("Unknown word list {spec} in slug template."));
+    };
+    let len = len
+        .parse::<usize>()
+        .ok()
+        .filter(|n| (1..=32).contains(n))
+        .ok_or_else(|| format!("Invalid length in placeholder {{{spec}}}."))?;
+    match kind {
+        "digits" => Ok(Part::Chars(&CHARS_SMALL[26..], len)),
+        "letters" => Ok(Part::Chars(&CHARS_SMALL[..26], len)),
+        "alnum" => Ok(Part::Chars(&CHARS_SMALL, len)),
+        "consonant-vowel" => Ok(Part::Pronounceable(len)),
+        _ => Err(format!("Unknown placeholder {{{spec}}} in slug template.")),
+    }
+}
+
+// The built-in adj and noun lists, along with the ones from files given as name=path
+pub(crate) fn load_wordlists(files: &[(String, String)]) -> Result<WordLists, String> {
+    let builtin = |words: &[&str]| words.iter().map(|w| w.to_string()).collect();
+    let mut lists = WordLists::from([
+        (String::from("adj"), builtin(&ADJECTIVES)),
+        (String::from("noun"), builtin(&NAMES)),
+    ]);
+    for (name, path) in files {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+        let mut words: Vec<String> = contents
+            .lines()
+            .map(|l| l.trim().to_lowercase())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .collect();
+        let total = words.len();
+        // Words are used as is, so only keep the ones valid in a slug
+        words.retain(|w| w.chars().all(|c| c.is_ascii_alphanumeric()));
+        words.sort_unstable();
+        words.dedup();
+        if words.len() < total {
+            warn!(
+                "Ignored {} invalid or duplicate words in {path}.",
+                total - words.len()
+            );
+        }
+        if words.is_empty() {
+            return Err(format!("The word list {path} is empty."));
+        }
+        lists.insert(name.to_owned(), words.into());
+    }
+    Ok(lists)
+}
+
+// Build the encoder for sequential slugs, the alphabet order keeps them from being guessed
+pub(crate) fn sequential_encoder(
+    alphabet: &str,
+    min_length: usize,
+    allow_capital_letters: bool,
+) -> Result<Sqids, String> {
+    let alphabet: Vec<char> = alphabet.trim().chars().collect();
+    if !alphabet.iter().all(|c| {
+        c.is_ascii_digit()
+            || c.is_ascii_lowercase()
+            || (allow_capital_letters && c.is_ascii_uppercase())
+    }) {
+        return Err(String::from(
+            "The slug alphabet has characters not allowed in slugs.",
+        ));
+    }
+    Sqids::builder()
+        .alphabet(alphabet)
+        .min_length(min_length.min(u8::MAX as usize) as u8)
+        .build()
+        .map_err(|e| format!("Invalid slug alphabet: {e}."))
+}
+
+// Encode a reserved row id as a slug
+pub(crate) fn encode_id(sqids: &Sqids, id: i64) -> Option<String> {
+    sqids
+        .encode(&[u64::try_from(id).ok()?])
+        .inspect_err(|e| warn!("Unable to encode {id} as a slug: {e}"))
+        .ok()
+}
+
+// Generate a random slug using either adjective-name pair (default), a-z, 0-9, or a template
+pub(crate) fn gen_slug(
+    style: &SlugStyle,
+    len: usize,
+    allow_capital_letters: bool,
+    try_longer_slug: bool,
+) -> String {
+    match style {
+        SlugStyle::Uid => {
+            let slug_len = if try_longer_slug { len + 4 } else { len };
+            debug!(
+                "Generating a link with style: UID, length: {slug_len}, allow_capital_letters: {allow_capital_letters}"
+            );
+            if allow_capital_letters {
+                nanoid!(slug_len, &CHARS_CAPITAL)
+            } else {
+                nanoid!(slug_len, &CHARS_SMALL)
+            }
+        }
+        SlugStyle::Pair => {
+            debug!("Generating a link with style: Pair.");
+            let adj = ADJECTIVES
+                .choose(&mut rand::rng())
+                .expect("Error choosing random adjective.")
+                .to_owned();
+            let name = NAMES
+                .choose(&mut rand::rng())
+                .expect("Error choosing random name.");
+            if try_longer_slug {
+                format!("{adj}-{name}-{:04}", random_range(0..=9999))
+            } else {
+                format!("{adj}-{name}")
+            }
+        }
+        SlugStyle::Template(templates) => {
+            let template = templates
+                .choose(&mut rand::rng())
+                .expect("There should be at least one template.");
+            debug!("Generating a link with template: {}.", template.pattern);
+            let slug = template.generate();
+            if try_longer_slug {
+                format!("{slug}-{:04}", random_range(0..=9999))
+            } else {
+                slug
+            }
+        }
+    }
+}
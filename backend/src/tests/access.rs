@@ -1,10 +1,11 @@
 // SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
 // SPDX-License-Identifier: MIT
 
-use actix_web::test;
+use actix_web::{body::to_bytes, test};
 use tokio::time::{Duration, sleep};
 
 use super::utils::*;
+use crate::redirect;
 
 #[test]
 async fn link_resolution() {
@@ -23,6 +24,166 @@ async fn link_resolution() {
     );
 }
 
+#[test]
+async fn case_insensitive_resolution() {
+    let test = "case-insensitive-resolution";
+    let mut conf = default_config(test);
+    conf.case_insensitive_slugs = true;
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+    let (status, _) = add_link(&app, &api_key, "MyLink", 0, "").await;
+    assert!(status.is_success());
+    let (status, reply) = add_link(&app, &api_key, "mylink", 0, "").await;
+    assert!(status.is_client_error());
+    assert_eq!(reply.reason, "Short URL is already in use!");
+
+    // The original case is kept for display
+    for path in ["/MyLink", "/mylink", "/MYLINK"] {
+        let req = test::TestRequest::get().uri(path).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_redirection(), "{path}");
+    }
+    assert_eq!(getall(&app, &api_key, "").await[0].shortlink, "MyLink");
+
+    let req = test::TestRequest::delete()
+        .uri("/api/del/MYLINK")
+        .insert_header(("X-API-Key", api_key))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+}
+
+#[test]
+async fn link_aliases() {
+    let test = "link-aliases";
+    let conf = default_config(test);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+    let _ = add_link(&app, &api_key, "taken", 0, "").await;
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .set_payload(
+            r#"{"shortlink":"main","longlink":"https://example.com","aliases":["alt","other"]}"#,
+        )
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    // Aliases share the destination and the hit counter
+    for path in ["/main", "/alt", "/other"] {
+        let req = test::TestRequest::get().uri(path).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("location").unwrap(),
+            "https://example.com"
+        );
+    }
+    sleep(Duration::from_millis(800)).await;
+    let (status, reply) = expand(&app, &api_key, "alt").await;
+    assert!(status.is_success());
+    assert_eq!(reply.shortlink, "main");
+    assert_eq!(reply.hits, 3);
+    assert_eq!(reply.aliases, ["alt", "other"]);
+
+    // Aliases and shortlinks can't collide
+    let (status, reply) = add_link(&app, &api_key, "alt", 0, "").await;
+    assert!(status.is_client_error());
+    assert_eq!(reply.reason, "Short URL is already in use!");
+    let req = test::TestRequest::put()
+        .uri("/api/edit")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .set_payload(r#"{"shortlink":"main","longlink":"https://example.com","reset_hits":false,"aliases":["taken"]}"#)
+        .to_request();
+    assert!(
+        test::call_service(&app, req)
+            .await
+            .status()
+            .is_client_error()
+    );
+
+    // Editing replaces the whole set
+    let req = test::TestRequest::put()
+        .uri("/api/edit")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .set_payload(r#"{"shortlink":"main","longlink":"https://example.com","reset_hits":false,"aliases":["third","other"]}"#)
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    let reply = getall(&app, &api_key, "").await;
+    let main = reply.iter().find(|l| l.shortlink == "main").unwrap();
+    assert_eq!(main.aliases, ["third", "other"]);
+
+    // Deleting an alias keeps the link, deleting the link drops its aliases
+    let req = test::TestRequest::delete()
+        .uri("/api/del/third")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    assert_eq!(expand(&app, &api_key, "main").await.1.aliases, ["other"]);
+    let req = test::TestRequest::delete()
+        .uri("/api/del/main")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    let req = test::TestRequest::get().uri("/other").to_request();
+    assert!(
+        test::call_service(&app, req)
+            .await
+            .status()
+            .is_client_error()
+    );
+}
+
+#[test]
+async fn failed_alias_changes() {
+    let test = "failed-alias-changes";
+    let conf = default_config(test);
+    let (tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+    let _ = add_link(&app, &api_key, "taken", 0, "").await;
+
+    // A link whose aliases can't be added isn't created either
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .set_payload(r#"{"shortlink":"main","longlink":"https://example.com","aliases":["taken"]}"#)
+        .to_request();
+    assert!(
+        test::call_service(&app, req)
+            .await
+            .status()
+            .is_client_error()
+    );
+    assert!(expand(&app, &api_key, "main").await.0.is_client_error());
+
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .set_payload(r#"{"shortlink":"main","longlink":"https://example.com","aliases":["alt"]}"#)
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    // If the link itself can't be edited, its aliases are left untouched
+    let db = rusqlite::Connection::open(tempdir.path().join(format!("{test}.sqlite"))).unwrap();
+    db.execute_batch(
+        "CREATE TRIGGER fail_edit BEFORE UPDATE OF long_url ON urls
+           BEGIN SELECT RAISE(ABORT, 'edit failed'); END",
+    )
+    .unwrap();
+    let req = test::TestRequest::put()
+        .uri("/api/edit")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .set_payload(r#"{"shortlink":"main","longlink":"https://example.org","reset_hits":false,"aliases":["other"]}"#)
+        .to_request();
+    assert!(
+        test::call_service(&app, req)
+            .await
+            .status()
+            .is_server_error()
+    );
+    let (_, reply) = expand(&app, &api_key, "main").await;
+    assert_eq!(reply.longlink, "https://example.com");
+    assert_eq!(reply.aliases, ["alt"]);
+}
+
 #[test]
 async fn link_deletion() {
     let test = "link-deletion";
@@ -78,6 +239,80 @@ async fn data_fetching_all() {
     assert_eq!(reply[0].shortlink, "test1");
 }
 
+#[test]
+async fn link_timestamps() {
+    let test = "link-timestamps";
+    let conf = default_config(test);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+    let _ = add_link(&app, &api_key, "test1", 10, "").await;
+    let _ = add_link(&app, &api_key, "test2", 10, "").await;
+    let _ = add_link(&app, &api_key, "test3", 10, "").await;
+    for _ in 0..2 {
+        let req = test::TestRequest::get().uri("/test1").to_request();
+        let _ = test::call_service(&app, req).await;
+    }
+    let req = test::TestRequest::get().uri("/test3").to_request();
+    let _ = test::call_service(&app, req).await;
+
+    // Wait for the hits to be recorded, and for the clock to move on before editing
+    sleep(Duration::from_millis(1100)).await;
+    assert!(
+        edit_link(&app, &api_key, "test2", false, None, Some("edited"))
+            .await
+            .is_success()
+    );
+
+    let reply = getall(&app, &api_key, "").await;
+    let now = chrono::Utc::now().timestamp();
+    assert!(
+        reply
+            .iter()
+            .all(|l| l.created_at > 0 && l.created_at <= now)
+    );
+    assert!(reply[0].last_accessed_at >= reply[0].created_at);
+    assert_eq!(reply[1].last_accessed_at, 0);
+    assert_eq!(reply[0].updated_at, reply[0].created_at);
+    assert!(reply[1].updated_at > reply[1].created_at);
+    let edited = reply[1].updated_at;
+
+    let reply = getall(&app, &api_key, "sort=hits").await;
+    let order: Vec<_> = reply.iter().map(|l| l.shortlink.as_str()).collect();
+    assert_eq!(order, ["test1", "test3", "test2"]);
+    let reply = getall(
+        &app,
+        &api_key,
+        "sort=last_accessed_at&order=asc&page_size=1",
+    )
+    .await;
+    assert_eq!(reply[0].shortlink, "test2");
+    let reply = getall(&app, &api_key, "sort=updated_at&page_size=1").await;
+    assert_eq!(reply[0].shortlink, "test2");
+    let reply = getall(&app, &api_key, "sort=hits&page_after=test1&page_size=1").await;
+    assert_eq!(reply[0].shortlink, "test3");
+
+    // Links that were never accessed count as accessed before any time
+    let reply = getall(&app, &api_key, &format!("accessed_before={}", now - 3600)).await;
+    assert_eq!(reply.len(), 1);
+    assert_eq!(reply[0].shortlink, "test2");
+    let reply = getall(&app, &api_key, "accessed_after=1").await;
+    assert_eq!(reply.len(), 2);
+    let reply = getall(&app, &api_key, &format!("updated_after={edited}")).await;
+    assert_eq!(reply.len(), 1);
+    assert!(
+        getall(&app, &api_key, &format!("created_after={}", now + 1))
+            .await
+            .is_empty()
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/api/all?order=asc")
+        .insert_header(("X-API-Key", api_key))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+}
+
 #[test]
 async fn expand_link() {
     let test = "expand-link";
@@ -140,6 +375,131 @@ async fn notes_and_filtering() {
     assert_eq!(reply[1].shortlink, "test2");
     assert_eq!(reply[0].notes, "note1");
 }
+
+#[test]
+async fn search_queries() {
+    let test = "search-queries";
+    let conf = default_config(test);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+    let _ = add_link(&app, &api_key, "alpha", 100, "Q3 launch").await;
+    let _ = add_link(&app, &api_key, "beta", 100, "Q4 launch").await;
+    let _ = add_link(&app, &api_key, "gamma", 0, "docs").await;
+    for _ in 0..2 {
+        let req = test::TestRequest::get().uri("/alpha").to_request();
+        let _ = test::call_service(&app, req).await;
+    }
+    sleep(Duration::from_millis(800)).await;
+
+    let search = |query: &str| {
+        let query: String = url::form_urlencoded::byte_serialize(query.as_bytes()).collect();
+        format!("filter={query}")
+    };
+    for (query, expected) in [
+        ("long:example-alpha", vec!["alpha"]),
+        (r#"notes:"Q3 launch""#, vec!["alpha"]),
+        ("launch NOT beta", vec!["alpha"]),
+        ("hits>1", vec!["alpha"]),
+        ("short:gamma OR hits>=2", vec!["alpha", "gamma"]),
+        ("(alpha OR beta) AND launch", vec!["alpha", "beta"]),
+        ("expires>2000-01-01", vec!["alpha", "beta"]),
+        ("NOT expires>2000-01-01", vec!["gamma"]),
+        ("expires<2000-01-01", vec![]),
+        ("gamma.com notes:doc", vec!["gamma"]),
+    ] {
+        let reply = getall(&app, &api_key, &search(query)).await;
+        let found: Vec<_> = reply.iter().map(|l| l.shortlink.as_str()).collect();
+        assert_eq!(found, expected, "{query}");
+    }
+    let reply = getall(&app, &api_key, &format!("{}&page_size=1", search("launch"))).await;
+    assert_eq!(reply[0].shortlink, "beta");
+    let reply = getall(
+        &app,
+        &api_key,
+        &format!("{}&page_after=beta&page_size=1", search("launch")),
+    )
+    .await;
+    assert_eq!(reply[0].shortlink, "alpha");
+
+    for (query, reason) in [
+        ("(launch", "Unmatched opening parenthesis in the filter!"),
+        ("hits>many", "Invalid value for hits in the filter: many!"),
+        (
+            "launch OR",
+            "The filter ends where a search term was expected!",
+        ),
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/all?{}", search(query)))
+            .insert_header(("X-API-Key", api_key.clone()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(body.as_str(), reason);
+    }
+}
+
+#[test]
+async fn relevance_ordering() {
+    let test = "relevance-ordering";
+    let conf = default_config(test);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+    let _ = add_link(&app, &api_key, "launch", 0, "launch plans for the launch").await;
+    let _ = add_link(&app, &api_key, "docs", 0, "before the launch").await;
+    let _ = add_link(&app, &api_key, "ab12", 0, "").await;
+    let _ = add_link(&app, &api_key, "ab1", 0, "").await;
+
+    let reply = getall(&app, &api_key, "filter=launch&order=relevance").await;
+    let found: Vec<_> = reply.iter().map(|l| l.shortlink.as_str()).collect();
+    assert_eq!(found, ["launch", "docs"]);
+    assert!(
+        reply[1]
+            .snippet
+            .as_ref()
+            .unwrap()
+            .contains("<mark>launch</mark>")
+    );
+    let reply = getall(&app, &api_key, "filter=launch&order=relevance&page_size=1").await;
+    assert_eq!(reply[0].shortlink, "launch");
+    let reply = getall(
+        &app,
+        &api_key,
+        "filter=launch&order=relevance&page_after=launch&page_size=1",
+    )
+    .await;
+    assert_eq!(reply[0].shortlink, "docs");
+    // Without ordering by relevance, there are no snippets
+    assert!(
+        getall(&app, &api_key, "filter=launch").await[0]
+            .snippet
+            .is_none()
+    );
+
+    // Shorter queries match the start of the shortlinks
+    let reply = getall(&app, &api_key, "filter=ab").await;
+    let found: Vec<_> = reply.iter().map(|l| l.shortlink.as_str()).collect();
+    assert_eq!(found, ["ab12", "ab1"]);
+    let reply = getall(&app, &api_key, "filter=ab&order=relevance").await;
+    let found: Vec<_> = reply.iter().map(|l| l.shortlink.as_str()).collect();
+    assert_eq!(found, ["ab1", "ab12"]);
+    assert!(getall(&app, &api_key, "filter=b1").await.is_empty());
+
+    for params in [
+        "order=relevance",
+        "filter=launch&sort=hits&order=relevance",
+        "filter=notes:ab",
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/all?{params}"))
+            .insert_header(("X-API-Key", api_key.clone()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400, "{params}");
+    }
+}
+
 #[test]
 async fn edit_expiry() {
     let test = "link-editing";
@@ -170,3 +530,359 @@ async fn edit_expiry() {
     let status = edit_link(&app, &api_key, "test1", true, None, None).await;
     assert!(status.is_client_error());
 }
+
+#[test]
+async fn multiple_domains() {
+    let test = "multiple-domains";
+    let mut conf = default_config(test);
+    conf.extra_site_urls = Vec::from([String::from("https://other.com")]);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+
+    let (status, reply) = add_link(&app, &api_key, "test1", 10, "").await;
+    assert!(status.is_success());
+    assert_eq!(reply.shortlink, "https://mydomain.com/test1");
+
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .set_payload(
+            r#"{"shortlink":"test1","longlink":"https://other-test1.com","domain":"other.com"}"#,
+        )
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    let reply: URLData = serde_json::from_str(body.as_str()).unwrap();
+    assert_eq!(reply.shortlink, "https://other.com/test1");
+
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .set_payload(
+            r#"{"shortlink":"test2","longlink":"https://example.com","domain":"unknown.com"}"#,
+        )
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_client_error());
+
+    for (host, longlink) in [
+        ("other.com", "https://other-test1.com"),
+        ("mydomain.com", "https://example-test1.com"),
+        ("localhost:4567", "https://example-test1.com"),
+    ] {
+        let req = test::TestRequest::get()
+            .uri("/test1")
+            .insert_header(("Host", host))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_redirection());
+        assert_eq!(resp.headers().get("location").unwrap(), longlink);
+    }
+
+    let (status, reply) = expand(&app, &api_key, "test1").await;
+    assert!(status.is_success());
+    assert_eq!(reply.longlink, "https://example-test1.com");
+
+    let req = test::TestRequest::delete()
+        .uri("/api/del/test1?domain=other.com")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let reply = getall(&app, &api_key, "").await;
+    assert_eq!(reply.len(), 1);
+    assert_eq!(reply[0].longlink, "https://example-test1.com");
+}
+
+#[test]
+async fn conditional_redirects() {
+    let test = "conditional-redirects";
+    let conf = default_config(test);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .set_payload(
+            r#"{"shortlink":"app","longlink":"https://example.com","rules":[
+            {"platform":"ios","target":"https://apps.apple.com/app"},
+            {"platform":"android","target":"https://play.google.com/app"},
+            {"language":"de","target":"https://example.de"}]}"#,
+        )
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    for (user_agent, language, longlink) in [
+        (
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X)",
+            "de",
+            "https://apps.apple.com/app",
+        ),
+        (
+            "Mozilla/5.0 (Linux; Android 14; Pixel 8)",
+            "en",
+            "https://play.google.com/app",
+        ),
+        (
+            "Mozilla/5.0 (X11; Linux x86_64)",
+            "en-US,de-AT;q=0.5",
+            "https://example.com",
+        ),
+        (
+            "Mozilla/5.0 (X11; Linux x86_64)",
+            "de-AT,en;q=0.5",
+            "https://example.de",
+        ),
+    ] {
+        let req = test::TestRequest::get()
+            .uri("/app")
+            .insert_header(("User-Agent", user_agent))
+            .insert_header(("Accept-Language", language))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_redirection());
+        assert_eq!(resp.headers().get("location").unwrap(), longlink);
+    }
+
+    let req = test::TestRequest::put()
+        .uri("/api/edit")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .set_payload(
+            r#"{"shortlink":"app","longlink":"https://example.com","reset_hits":false,"rules":[]}"#,
+        )
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let req = test::TestRequest::get()
+        .uri("/app")
+        .insert_header(("User-Agent", "Mozilla/5.0 (iPhone)"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(
+        resp.headers().get("location").unwrap(),
+        "https://example.com"
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key))
+        .set_payload(
+            r#"{"shortlink":"bad","longlink":"https://example.com","rules":[
+            {"platform":"symbian","target":"https://example.com/old"}]}"#,
+        )
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_client_error());
+}
+
+#[test]
+async fn client_addresses() {
+    let proxy = "10.0.0.1:4000".parse().unwrap();
+    let client = |peer| {
+        test::TestRequest::get()
+            .peer_addr(peer)
+            .insert_header(("X-Forwarded-For", "1.2.3.4, 5.6.7.8"))
+            .to_http_request()
+    };
+
+    // Forwarded addresses are ignored unless the request comes from a trusted proxy
+    let ip = redirect::client_ip(&client(proxy), &[]);
+    assert_eq!(ip, Some(proxy.ip()));
+    let ip = redirect::client_ip(&client(proxy), &[proxy.ip()]);
+    assert_eq!(ip, "5.6.7.8".parse().ok());
+    let ip = redirect::client_ip(&client("10.0.0.2:4000".parse().unwrap()), &[proxy.ip()]);
+    assert_eq!(ip, "10.0.0.2".parse().ok());
+}
+
+#[test]
+async fn weighted_targets() {
+    let test = "weighted-targets";
+    let conf = default_config(test);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .set_payload(
+            r#"[{"shortlink":"split","longlink":"https://example.com","targets":[
+            {"url":"https://example.com/a","weight":70},
+            {"url":"https://example.com/b","weight":0}]},
+            {"shortlink":"sticky","longlink":"https://example.com","sticky":true,"targets":[
+            {"url":"https://example.com/a","weight":1},
+            {"url":"https://example.com/b","weight":1}]}]"#,
+        )
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    for _ in 0..3 {
+        let req = test::TestRequest::get().uri("/split").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 307);
+        assert_eq!(
+            resp.headers().get("location").unwrap(),
+            "https://example.com/a"
+        );
+    }
+
+    let req = test::TestRequest::get().uri("/sticky").to_request();
+    let resp = test::call_service(&app, req).await;
+    let cookie = resp.response().cookies().next().unwrap().into_owned();
+    let location = resp.headers().get("location").unwrap().clone();
+    for _ in 0..5 {
+        let req = test::TestRequest::get()
+            .uri("/sticky")
+            .cookie(cookie.clone())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("location").unwrap(), location);
+    }
+
+    sleep(Duration::from_millis(800)).await;
+    let req = test::TestRequest::get()
+        .uri("/api/all")
+        .insert_header(("X-API-Key", api_key))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    let reply: serde_json::Value = serde_json::from_str(body.as_str()).unwrap();
+    assert_eq!(reply[0]["hits"], 3);
+    assert_eq!(reply[0]["targets"][0]["hits"], 3);
+    assert_eq!(reply[0]["targets"][1]["hits"], 0);
+    assert_eq!(reply[1]["hits"], 6);
+    let idx: usize = cookie.value().parse().unwrap();
+    assert_eq!(reply[1]["targets"][idx]["hits"], 6);
+    assert_eq!(reply[1]["targets"][idx]["url"], location.to_str().unwrap());
+}
+
+#[test]
+async fn link_preview() {
+    let test = "link-preview";
+    let conf = default_config(test);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .set_payload(
+            r#"[{"shortlink":"peek","longlink":"https://example.com/page?a=1&b=2","notes":"<b>docs</b>"},
+            {"shortlink":"always","longlink":"https://example.org","preview":true}]"#,
+        )
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    for uri in ["/peek+", "/preview/peek"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        let page = body.as_str();
+        assert!(page.contains("https://example.com/page?a=1&amp;b=2"));
+        assert!(page.contains("&lt;b&gt;docs&lt;/b&gt;"));
+        assert!(page.contains(r#"href="/peek""#));
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/preview/missing")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    // Links with the preview flag always show the page, but still count the hit
+    let req = test::TestRequest::get().uri("/always").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    assert!(body.as_str().contains(r#"href="https://example.org""#));
+
+    sleep(Duration::from_millis(800)).await;
+    let req = test::TestRequest::get()
+        .uri("/api/all")
+        .insert_header(("X-API-Key", api_key))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    let reply: serde_json::Value = serde_json::from_str(body.as_str()).unwrap();
+    assert_eq!(reply[0]["hits"], 0);
+    assert_eq!(reply[1]["hits"], 1);
+    assert_eq!(reply[1]["preview"], true);
+    assert!(reply[0]["created_at"].as_i64().unwrap() > 0);
+}
+
+#[test]
+async fn opengraph_cards() {
+    let test = "opengraph-cards";
+    let conf = default_config(test);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .set_payload(
+            r#"{"shortlink":"card","longlink":"https://example.com","opengraph":{
+            "title":"Launch \"day\"","description":"All the news","image":"https://example.com/card.png"}}"#,
+        )
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .set_payload(
+            r#"{"shortlink":"badcard","longlink":"https://example.com","opengraph":{"image":"ftp://example.com/a.png"}}"#,
+        )
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    let req = test::TestRequest::get()
+        .uri("/card")
+        .insert_header((
+            "User-Agent",
+            "Slackbot-LinkExpanding 1.0 (+https://api.slack.com/robots)",
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    let page = body.as_str();
+    assert!(page.contains(r#"<meta property="og:title" content="Launch &quot;day&quot;" />"#));
+    assert!(page.contains(r#"<meta property="og:description" content="All the news" />"#));
+    assert!(page.contains(r#"<meta name="twitter:card" content="summary_large_image" />"#));
+
+    // Regular browsers still get redirected
+    let req = test::TestRequest::get()
+        .uri("/card")
+        .insert_header((
+            "User-Agent",
+            "Mozilla/5.0 (X11; Linux x86_64; rv:140.0) Firefox/140.0",
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 308);
+
+    // Clearing the metadata turns off the card
+    let req = test::TestRequest::put()
+        .uri("/api/edit")
+        .insert_header(("X-API-Key", api_key))
+        .set_payload(
+            r#"{"shortlink":"card","longlink":"https://example.com","reset_hits":false,"opengraph":{}}"#,
+        )
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let req = test::TestRequest::get()
+        .uri("/card")
+        .insert_header(("User-Agent", "Twitterbot/1.0"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 308);
+}
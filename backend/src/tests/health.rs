@@ -0,0 +1,120 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+use actix_web::{App, HttpResponse, HttpServer, body::to_bytes, rt, test, web};
+use tokio::sync::Mutex;
+
+use super::utils::*;
+use crate::{database, health};
+
+// Start a local server with destinations in different states of health
+fn start_destinations() -> String {
+    let server = HttpServer::new(|| {
+        App::new()
+            .route("/ok", web::route().to(HttpResponse::Ok))
+            .route("/gone", web::route().to(HttpResponse::NotFound))
+            .route("/nohead", web::head().to(HttpResponse::MethodNotAllowed))
+            .route("/nohead", web::get().to(HttpResponse::Ok))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = server.addrs()[0];
+    rt::spawn(server.run());
+    format!("http://{addr}")
+}
+
+#[test]
+async fn health_checks() {
+    let test = "health-checks";
+    let conf = default_config(test);
+    let (tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+    let base = start_destinations();
+
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .set_payload(format!(
+            r#"[{{"shortlink":"ok","longlink":"{base}/ok"}},
+            {{"shortlink":"gone","longlink":"{base}/gone"}},
+            {{"shortlink":"nohead","longlink":"{base}/nohead"}},
+            {{"shortlink":"dead","longlink":"http://127.0.0.1:1/"}}]"#
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let db_file = tempdir.path().join(format!("{test}.sqlite"));
+    let writer = Mutex::new(database::open_db(db_file.to_str().unwrap(), false));
+    health::run_checks(&writer, &health::build_client(true), true, 2, 100).await;
+
+    let reply = getall(&app, &api_key, "broken=true").await;
+    let mut broken: Vec<_> = reply.iter().map(|l| l.shortlink.as_str()).collect();
+    broken.sort();
+    assert_eq!(broken, ["dead", "gone"]);
+    let reply = getall(&app, &api_key, "broken=false").await;
+    assert_eq!(reply.len(), 2);
+
+    let req = test::TestRequest::get()
+        .uri("/api/health-report")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    let report: serde_json::Value = serde_json::from_str(body.as_str()).unwrap();
+    assert_eq!(report["total"], 4);
+    assert_eq!(report["checked"], 4);
+    assert_eq!(report["broken"], 2);
+    let gone = report["links"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|l| l["shortlink"] == "gone")
+        .unwrap();
+    assert_eq!(gone["health_status"], 404);
+    assert_eq!(gone["health_failures"], 1);
+
+    // Fixing the destination clears the failures
+    let req = test::TestRequest::put()
+        .uri("/api/edit")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .set_payload(format!(
+            r#"{{"shortlink":"gone","longlink":"{base}/ok","reset_hits":false}}"#
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let reply = getall(&app, &api_key, "broken=true").await;
+    assert_eq!(reply.len(), 1);
+    assert_eq!(reply[0].shortlink, "dead");
+
+    let req = test::TestRequest::get()
+        .uri("/api/health-report")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+}
+
+#[test]
+async fn private_destinations() {
+    let base = start_destinations();
+    let port = base.rsplit(':').next().unwrap();
+
+    // Private and local addresses are refused unless allowed, whether given directly or by name
+    let client = health::build_client(false);
+    assert_eq!(
+        health::check_url(&client, false, &format!("{base}/ok")).await,
+        None
+    );
+    let named = format!("http://localhost:{port}/ok");
+    assert_eq!(health::check_url(&client, false, &named).await, None);
+    assert_eq!(
+        health::check_url(&client, false, "http://[::1]/").await,
+        None
+    );
+
+    let client = health::build_client(true);
+    assert_eq!(health::check_url(&client, true, &named).await, Some(200));
+}
@@ -1,7 +1,7 @@
 // SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
 // SPDX-License-Identifier: MIT
 
-use actix_web::{body::to_bytes, test};
+use actix_web::{body::to_bytes, http::StatusCode, test};
 use regex::Regex;
 use tokio::time::{Duration, sleep};
 
@@ -193,6 +193,75 @@ async fn adding_link_with_generated_shortlink_with_uid_slug_capital_letters() {
     assert!(re.is_match(reply.shortlink.as_str()));
 }
 
+#[test]
+async fn adding_link_with_generated_shortlink_with_template_slug() {
+    let test = "autogen-with-template-slug";
+    let listdir = tempfile::TempDir::new().unwrap();
+    let wordlist = listdir.path().join("team.txt");
+    std::fs::write(&wordlist, "# Teams\nalpha\nBeta\nbeta\ngam ma\n").unwrap();
+    let lists =
+        slugs::load_wordlists(&[(String::from("team"), wordlist.to_str().unwrap().to_owned())])
+            .unwrap();
+
+    let template = slugs::SlugTemplate::parse("{team}-{adj}-{digits:3}", &lists).unwrap();
+    assert_eq!(template.collision_space(), 2 * 108 * 1000);
+    let pronounceable = slugs::SlugTemplate::parse("go{consonant-vowel:5}", &lists).unwrap();
+    assert_eq!(pronounceable.collision_space(), 21 * 5 * 21 * 5 * 21);
+    for invalid in ["{team", "{colour}", "{digits:0}", "Fixed", "plain"] {
+        assert!(
+            slugs::SlugTemplate::parse(invalid, &lists).is_err(),
+            "{invalid}"
+        );
+    }
+
+    let mut conf = default_config(test);
+    conf.slug_style = config::SlugStyle::Template(vec![template, pronounceable]);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.unwrap();
+    let re = Regex::new(
+        r"^https://mydomain.com/((alpha|beta)-[a-z]+-[0-9]{3}|go[^aeiou][aeiou][^aeiou][aeiou][^aeiou])$",
+    )
+    .unwrap();
+    for _ in 0..10 {
+        let (status, reply) = add_link(&app, &api_key, "", 0, "").await;
+        assert!(status.is_success());
+        assert!(re.is_match(reply.shortlink.as_str()), "{}", reply.shortlink);
+    }
+}
+
+#[test]
+async fn adding_link_with_generated_shortlink_with_sequential_slug() {
+    let test = "autogen-with-sequential-slug";
+    let mut conf = default_config(test);
+    let sqids = slugs::sequential_encoder("k3g7qae5ur9dx2znp6s8bj4yhmvwfc", 5, false).unwrap();
+    assert!(slugs::sequential_encoder("ab-", 5, false).is_err());
+    assert!(slugs::sequential_encoder("", 5, false).is_err());
+    conf.sequential_slugs = Some(sqids.clone());
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.unwrap();
+    let slug = |reply: URLData| reply.shortlink.rsplit('/').next().unwrap().to_owned();
+
+    let first = slug(add_link(&app, &api_key, "", 0, "").await.1);
+    assert_eq!(first, slugs::encode_id(&sqids, 1).unwrap());
+    assert!(first.len() >= 5);
+
+    // A custom slug taking the next one is skipped over
+    let taken = slugs::encode_id(&sqids, 3).unwrap();
+    let (status, _) = add_link(&app, &api_key, &taken, 0, "").await;
+    assert!(status.is_success());
+    let second = slug(add_link(&app, &api_key, "", 0, "").await.1);
+    assert_eq!(second, slugs::encode_id(&sqids, 4).unwrap());
+
+    // Slugs of deleted links aren't handed out again
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/del/{second}"))
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    let third = slug(add_link(&app, &api_key, "", 0, "").await.1);
+    assert_eq!(third, slugs::encode_id(&sqids, 5).unwrap());
+}
+
 #[test]
 async fn adding_link_with_retry_on_collision() {
     let test = "retry_on_collision";
@@ -292,3 +361,479 @@ async fn link_editing() {
     assert_eq!(reply.longlink, "https://edited-test1.com");
     assert_eq!(reply.hits, 0);
 }
+
+#[test]
+async fn domain_lists() {
+    let test = "domain-lists";
+    let listdir = tempfile::TempDir::new().unwrap();
+    let blocklist = listdir.path().join("blocklist.txt");
+    let public_allowlist = listdir.path().join("public-allowlist.txt");
+    std::fs::write(
+        &blocklist,
+        "# Known phishing\nevil.com\nregex:^https?://[^/]+/login\n",
+    )
+    .unwrap();
+    std::fs::write(&public_allowlist, "example.org\n").unwrap();
+
+    let mut conf = default_config(test);
+    conf.public_mode = true;
+    conf.blocklist = Some(blocklist.to_str().unwrap().to_owned());
+    conf.public_allowlist = Some(public_allowlist.to_str().unwrap().to_owned());
+    let (tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+
+    for (longlink, allowed) in [
+        ("https://evil.com", false),
+        ("https://www.EVIL.com/page", false),
+        ("https://notevil.com", true),
+        ("https://example.com/login", false),
+        ("https://example.com/logout", true),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/api/new")
+            .insert_header(("X-API-Key", api_key.clone()))
+            .set_payload(format!(r#"{{"longlink":"{longlink}"}}"#))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status().is_success(), allowed, "{longlink}");
+        if !allowed {
+            let body = to_bytes(resp.into_body()).await.unwrap();
+            let reply: URLData = serde_json::from_str(body.as_str()).unwrap();
+            assert_eq!(reply.reason, "Destination is not allowed!");
+        }
+    }
+
+    // Blocked rule targets are rejected as well
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .set_payload(
+            r#"{"longlink":"https://example.com","rules":[{"platform":"ios","target":"https://evil.com"}]}"#,
+        )
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    // Public submissions also need to pass the public lists
+    for (longlink, allowed) in [
+        ("https://example.com", false),
+        ("https://example.org", true),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/api/new")
+            .set_payload(format!(r#"{{"longlink":"{longlink}"}}"#))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status().is_success(), allowed, "{longlink}");
+    }
+
+    // Existing links matching an updated blocklist get disabled
+    let (status, _) = add_link(&app, &api_key, "soon-blocked", 0, "").await;
+    assert!(status.is_success());
+    std::fs::write(&blocklist, "example-soon-blocked.com\n").unwrap();
+    let lists = lists::DomainLists::load(&conf).unwrap();
+    let db_file = tempdir.path().join(format!("{test}.sqlite"));
+    let mut db = database::open_db(db_file.to_str().unwrap(), false);
+    let disabled = database::disable_blocked(&mut db, |url| lists.is_blocked(url));
+    assert_eq!(disabled, 1);
+
+    let req = test::TestRequest::get().uri("/soon-blocked").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+    let req = test::TestRequest::get()
+        .uri("/api/all")
+        .insert_header(("X-API-Key", api_key))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    let reply: serde_json::Value = serde_json::from_str(body.as_str()).unwrap();
+    let link = reply
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|l| l["shortlink"] == "soon-blocked")
+        .unwrap();
+    assert_eq!(link["disabled"], true);
+}
+
+#[test]
+async fn deduplicated_insertion() {
+    let test = "deduplicated-insertion";
+    let conf = default_config(test);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.unwrap();
+
+    let add = async |payload: &str| {
+        let req = test::TestRequest::post()
+            .uri("/api/new")
+            .insert_header(("X-API-Key", api_key.as_str()))
+            .set_payload(payload.to_owned())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        serde_json::from_str::<serde_json::Value>(body.as_str()).unwrap()
+    };
+
+    let first = add(r#"{"longlink":"https://example.com/page","dedupe":true}"#).await;
+    assert_eq!(first["reused"], false);
+    // The comparison ignores differences that don't change the URL
+    let second = add(r#"{"longlink":"https://EXAMPLE.com:443/page","dedupe":true}"#).await;
+    assert_eq!(second["reused"], true);
+    assert_eq!(second["shorturl"], first["shorturl"]);
+
+    // Without the option, or with a custom shortlink, a new link is created
+    let third = add(r#"{"longlink":"https://example.com/page"}"#).await;
+    assert_eq!(third["reused"], false);
+    assert_ne!(third["shorturl"], first["shorturl"]);
+    let custom =
+        add(r#"{"shortlink":"custom","longlink":"https://example.com/page","dedupe":true}"#).await;
+    assert_eq!(custom["reused"], false);
+
+    // Duplicates within a batch share the new link
+    let batch = add(r#"[{"longlink":"https://example.org","dedupe":true},
+        {"longlink":"https://example.org","dedupe":true}]"#)
+    .await;
+    assert_eq!(batch[0]["reused"], false);
+    assert_eq!(batch[1]["reused"], true);
+    assert_eq!(batch[0]["shorturl"], batch[1]["shorturl"]);
+
+    // Links with an expiry, previews or an idle timeout are never reused
+    for (i, extra) in [
+        r#""expiry_delay":3600"#,
+        r#""preview":true"#,
+        r#""opengraph":{"title":"Title"}"#,
+        r#""idle_ttl":3600"#,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let longlink = format!("https://example.net/{i}");
+        let existing = add(&format!(r#"{{"longlink":"{longlink}",{extra}}}"#)).await;
+        let plain = add(&format!(r#"{{"longlink":"{longlink}","dedupe":true}}"#)).await;
+        assert_eq!(plain["reused"], false, "{extra}");
+        assert_ne!(plain["shorturl"], existing["shorturl"], "{extra}");
+
+        // Neither are requests carrying these fields, or notes
+        let request = add(&format!(
+            r#"{{"longlink":"{longlink}","dedupe":true,{extra}}}"#
+        ))
+        .await;
+        assert_eq!(request["reused"], false, "{extra}");
+        assert_ne!(request["shorturl"], plain["shorturl"], "{extra}");
+    }
+    let notes = add(r#"{"longlink":"https://example.com/page","dedupe":true,"notes":"Hi"}"#).await;
+    assert_eq!(notes["reused"], false);
+}
+
+#[test]
+async fn idempotent_insertion() {
+    let test = "idempotent-insertion";
+    let mut conf = default_config(test);
+    conf.public_mode = true;
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.unwrap();
+
+    let add = async |key: &str, payload: &str| {
+        let req = test::TestRequest::post()
+            .uri("/api/new")
+            .insert_header(("X-API-Key", api_key.as_str()))
+            .insert_header(("Idempotency-Key", key))
+            .set_payload(payload.to_owned())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let status = resp.status();
+        let replayed = resp.headers().contains_key("Idempotent-Replayed");
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        (status, replayed, body.as_str().to_owned())
+    };
+
+    // A retry gets the original response, without creating another link
+    let (status, replayed, first) = add("key-1", r#"{"longlink":"https://example.com"}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!replayed);
+    let (status, replayed, retry) = add("key-1", r#"{"longlink":"https://example.com"}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(replayed);
+    assert_eq!(first, retry);
+    assert_eq!(getall(&app, &api_key, "").await.len(), 1);
+
+    // Reusing the key for something else is rejected
+    let (status, _, _) = add("key-1", r#"{"longlink":"https://example.org"}"#).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Batches and errors are replayed as well
+    let batch = r#"[{"shortlink":"one","longlink":"https://example.com"},
+        {"shortlink":"one","longlink":"https://example.com"}]"#;
+    let (_, _, first) = add("key-2", batch).await;
+    let (_, replayed, retry) = add("key-2", batch).await;
+    assert!(replayed);
+    assert_eq!(first, retry);
+    assert!(retry.contains("Short URL is already in use!"));
+    assert_eq!(getall(&app, &api_key, "").await.len(), 2);
+
+    // Keys used by public mode users don't affect the admin
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("Idempotency-Key", "key-3"))
+        .set_payload(r#"{"longlink":"https://example.net"}"#)
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    let (status, replayed, _) = add("key-3", r#"{"longlink":"https://example.org"}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!replayed);
+    assert_eq!(getall(&app, &api_key, "").await.len(), 4);
+}
+
+#[test]
+async fn reserved_slugs() {
+    let test = "reserved-slugs";
+    let listdir = tempfile::TempDir::new().unwrap();
+    let denylist = listdir.path().join("denylist.txt");
+    std::fs::write(&denylist, "# Words\nbadword\n").unwrap();
+    let landing = listdir.path().join("landing");
+    std::fs::create_dir_all(landing.join("about")).unwrap();
+
+    let mut conf = default_config(test);
+    conf.reserved_slugs = config::ReservedSlugs::new(
+        Some(landing.to_str().unwrap()),
+        Some(denylist.to_str().unwrap()),
+    );
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+
+    for (shortlink, reserved) in [
+        ("api", true),
+        ("admin", true),
+        ("about", true),
+        ("api-docs", false),
+        ("badword", true),
+        ("my-badword", true),
+        ("my_badwords", false),
+    ] {
+        let (status, reply) = add_link(&app, &api_key, shortlink, 0, "").await;
+        assert_eq!(status.is_success(), !reserved, "{shortlink}");
+        if reserved {
+            assert_eq!(reply.reason, "Shortlink is reserved!");
+        }
+    }
+
+    // Generated slugs are screened as well
+    let mut conf = default_config(test);
+    conf.slug_style = config::SlugStyle::Uid;
+    conf.slug_length = 1;
+    std::fs::write(
+        &denylist,
+        "abcdefghijklmnopqrstuvwxyz0123456789"
+            .chars()
+            .map(|c| format!("{c}\n"))
+            .collect::<String>(),
+    )
+    .unwrap();
+    conf.reserved_slugs = config::ReservedSlugs::new(None, Some(denylist.to_str().unwrap()));
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .set_payload(r#"{"longlink":"https://example.com"}"#)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_server_error());
+    assert!(getall(&app, &api_key, "").await.is_empty());
+}
+
+#[test]
+async fn slug_availability_and_suggestions() {
+    let test = "slug-availability";
+    let conf = default_config(test);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+    let _ = add_link(&app, &api_key, "taken", 0, "").await;
+    let _ = add_link(&app, &api_key, "stale", 1, "").await;
+    sleep(Duration::from_millis(1100)).await;
+
+    let check = async |shortlink: &str| {
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/available/{shortlink}"))
+            .insert_header(("X-API-Key", api_key.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        let reply: serde_json::Value = serde_json::from_str(body.as_str()).unwrap();
+        (
+            reply["valid"].as_bool().unwrap(),
+            reply["available"].as_bool().unwrap(),
+            reply["expired"].as_bool().unwrap(),
+        )
+    };
+    assert_eq!(check("free").await, (true, true, false));
+    assert_eq!(check("taken").await, (true, false, false));
+    assert_eq!(check("stale").await, (true, true, true));
+    assert_eq!(check("Upper").await, (false, false, false));
+    assert_eq!(check("api").await, (false, false, false));
+
+    let req = test::TestRequest::get()
+        .uri("/api/suggest?base=taken&count=3")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    let reply: serde_json::Value = serde_json::from_str(body.as_str()).unwrap();
+    let suggestions = reply["suggestions"].as_array().unwrap();
+    assert_eq!(suggestions.len(), 3);
+    for slug in suggestions {
+        let slug = slug.as_str().unwrap();
+        assert!(slug.starts_with("taken-"), "{slug}");
+        assert_eq!(check(slug).await, (true, true, false));
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/api/suggest?base=Bad!")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .to_request();
+    assert!(
+        test::call_service(&app, req)
+            .await
+            .status()
+            .is_client_error()
+    );
+}
+
+#[test]
+async fn idle_links() {
+    let test = "idle-links";
+    let conf = default_config(test);
+    let (tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+    for shortlink in ["idle", "busy", "kept"] {
+        let req = test::TestRequest::post()
+            .uri("/api/new")
+            .insert_header(("X-API-Key", api_key.clone()))
+            .set_payload(format!(
+                r#"{{"shortlink":"{shortlink}","longlink":"https://example.com","idle_ttl":3600}}"#
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+    let (_, reply) = expand(&app, &api_key, "idle").await;
+    assert_eq!(reply.idle_ttl, 3600);
+    let db_file = tempdir.path().join(format!("{test}.sqlite"));
+    let db = database::open_db(db_file.to_str().unwrap(), false);
+    db.execute("UPDATE urls SET updated_at = updated_at - 7200", ())
+        .unwrap();
+
+    // A recent visit keeps the link alive, while removing the TTL keeps it forever
+    let req = test::TestRequest::get().uri("/busy").to_request();
+    let _ = test::call_service(&app, req).await;
+    sleep(Duration::from_millis(800)).await;
+    let req = test::TestRequest::put()
+        .uri("/api/edit")
+        .insert_header(("X-API-Key", api_key.clone()))
+        .set_payload(r#"{"shortlink":"kept","longlink":"https://example.com","reset_hits":false,"idle_ttl":0}"#)
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    db.execute("UPDATE urls SET updated_at = updated_at - 7200", ())
+        .unwrap();
+
+    database::cleanup(&db, false, false, 0);
+    let reply = getall(&app, &api_key, "").await;
+    let remaining: Vec<_> = reply.iter().map(|l| l.shortlink.as_str()).collect();
+    assert_eq!(remaining, ["busy", "kept"]);
+
+    // Idle links can be disabled instead, and editing one brings it back
+    db.execute(
+        "UPDATE urls SET last_accessed_at = last_accessed_at - 7200",
+        (),
+    )
+    .unwrap();
+    database::cleanup(&db, false, true, 0);
+    let req = test::TestRequest::get().uri("/busy").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+    assert!(
+        edit_link(&app, &api_key, "busy", false, None, None)
+            .await
+            .is_success()
+    );
+    let req = test::TestRequest::get().uri("/busy").to_request();
+    assert!(
+        test::call_service(&app, req)
+            .await
+            .status()
+            .is_redirection()
+    );
+}
+
+#[test]
+async fn expiry_grace_period() {
+    let test = "expiry-grace-period";
+    let mut conf = default_config(test);
+    conf.expiry_grace_period = 3600;
+    let (tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+    for shortlink in ["recent", "old"] {
+        let (status, _) = add_link(&app, &api_key, shortlink, 100, "").await;
+        assert!(status.is_success());
+    }
+    let db_file = tempdir.path().join(format!("{test}.sqlite"));
+    let db = database::open_db(db_file.to_str().unwrap(), false);
+    db.execute(
+        "UPDATE urls SET expiry_time = unixepoch() - 60 WHERE short_url = 'recent'",
+        (),
+    )
+    .unwrap();
+    db.execute(
+        "UPDATE urls SET expiry_time = unixepoch() - 7200 WHERE short_url = 'old'",
+        (),
+    )
+    .unwrap();
+
+    // Links in their grace period are hidden, but keep their slug
+    let req = test::TestRequest::get().uri("/recent").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+    assert!(getall(&app, &api_key, "").await.is_empty());
+    let reply = getall(&app, &api_key, "include_expired=true").await;
+    let listed: Vec<_> = reply.iter().map(|l| l.shortlink.as_str()).collect();
+    assert_eq!(listed, ["recent"]);
+    let (status, _) = add_link(&app, &api_key, "recent", 100, "").await;
+    assert!(status.is_client_error());
+    let (status, _) = add_link(&app, &api_key, "old", 100, "").await;
+    assert!(status.is_success());
+
+    // Cleanup only deletes links once their grace period is over
+    database::cleanup(&db, false, false, 3600);
+    assert_eq!(
+        getall(&app, &api_key, "include_expired=true").await.len(),
+        2
+    );
+
+    let revive = async |payload: &str| {
+        let req = test::TestRequest::put()
+            .uri("/api/revive")
+            .insert_header(("X-API-Key", api_key.as_str()))
+            .set_payload(payload.to_owned())
+            .to_request();
+        test::call_service(&app, req).await.status()
+    };
+    assert!(
+        revive(r#"{"shortlink":"recent","expiry_delay":0}"#)
+            .await
+            .is_success()
+    );
+    let req = test::TestRequest::get().uri("/recent").to_request();
+    assert!(
+        test::call_service(&app, req)
+            .await
+            .status()
+            .is_redirection()
+    );
+    let (_, reply) = expand(&app, &api_key, "recent").await;
+    assert_eq!(reply.expiry_time, 0);
+    // Only expired links can be revived
+    assert_eq!(
+        revive(r#"{"shortlink":"old","expiry_delay":60}"#).await,
+        StatusCode::BAD_REQUEST
+    );
+}
@@ -0,0 +1,284 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+use actix_http::StatusCode;
+use actix_web::{body::to_bytes, test};
+use chrono::NaiveDateTime;
+use flate2::read::GzDecoder;
+use std::io::Read;
+use tokio::time::{Duration, sleep};
+
+use super::utils::*;
+use crate::{background, database, schedule};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[test]
+async fn schedules() {
+    let start = NaiveDateTime::parse_from_str("2026-01-30 10:20:30", "%Y-%m-%d %H:%M:%S").unwrap();
+    for (expression, next) in [
+        ("0 * * * *", "2026-01-30 11:00"),
+        ("*/15 * * * *", "2026-01-30 10:30"),
+        ("0 3 * * *", "2026-01-31 03:00"),
+        ("30 2 * * 1-5", "2026-02-02 02:30"),
+        ("0 0 1,15 * *", "2026-02-01 00:00"),
+        ("0 0 31 * *", "2026-01-31 00:00"),
+        ("0 0 29 2 *", "2028-02-29 00:00"),
+        ("0 4 13 * 5", "2026-02-06 04:00"),
+        ("0 4 * * 7", "2026-02-01 04:00"),
+        // Restricted day fields match either the day of month or the day of week
+        ("0 0 */2 * 1", "2026-01-31 00:00"),
+        ("@weekly", "2026-02-01 00:00"),
+        ("@monthly", "2026-02-01 00:00"),
+    ] {
+        let schedule = schedule::parse(expression).unwrap();
+        let next = NaiveDateTime::parse_from_str(next, "%Y-%m-%d %H:%M").unwrap();
+        assert_eq!(schedule.next_after_naive(start), Some(next), "{expression}");
+    }
+    for expression in [
+        "",
+        "* * * *",
+        "60 * * * *",
+        "*/0 * * * *",
+        "5-1 * * * *",
+        "0 0 30 2 *",
+        "@often",
+    ] {
+        assert!(schedule::parse(expression).is_err(), "{expression}");
+    }
+}
+
+#[test]
+async fn startup_cleanup() {
+    let test = "startup_cleanup";
+    let mut conf = default_config(test);
+    conf.cleanup_schedule = schedule::parse("@yearly").ok();
+    conf.optimize_schedule = schedule::parse("@yearly").ok();
+    let tempdir = tempfile::TempDir::new().unwrap();
+    let db_file = tempdir.path().join(format!("{test}.sqlite"));
+    let writer = Arc::new(Mutex::new(database::open_db(
+        db_file.to_str().unwrap(),
+        false,
+    )));
+    database::init_db(
+        &mut *writer.lock().await,
+        conf.use_wal_mode,
+        conf.ensure_acid,
+        conf.case_insensitive_slugs,
+        &conf.backup_settings,
+    );
+
+    // The cleanup runs right away if it never ran, while the other jobs wait for their schedule
+    let handles = background::spawn_maintenance(Arc::clone(&writer), &conf);
+    sleep(Duration::from_millis(300)).await;
+    let last_runs = database::last_runs(&*writer.lock().await);
+    assert!(last_runs.contains_key("cleanup"));
+    assert!(!last_runs.contains_key("optimize"));
+    for handle in handles {
+        handle.abort();
+    }
+}
+
+#[test]
+async fn backups() {
+    let test = "backups";
+    let conf = default_config(test);
+    let (tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+    let db_file = tempdir.path().join(format!("{test}.sqlite"));
+    let db = database::open_db(db_file.to_str().unwrap(), false);
+
+    let retention = database::BackupSettings {
+        init: 3,
+        daily: 2,
+        weekly: 1,
+        monthly: 0,
+        compress: false,
+    };
+    for _ in 0..3 {
+        assert!(database::backup(&db, &retention));
+    }
+    let backup_dir = tempdir.path().join("backups");
+    let mut backups: Vec<_> = std::fs::read_dir(&backup_dir)
+        .unwrap()
+        .map(|f| f.unwrap().file_name().into_string().unwrap())
+        .collect();
+    backups.sort();
+    // Weekly backups are only made once a week
+    assert_eq!(
+        backups,
+        [
+            "backups.sqlite.daily1",
+            "backups.sqlite.daily1.json",
+            "backups.sqlite.daily2",
+            "backups.sqlite.daily2.json",
+            "backups.sqlite.init1",
+            "backups.sqlite.init1.json",
+            "backups.sqlite.weekly1",
+            "backups.sqlite.weekly1.json",
+        ]
+    );
+
+    database::record_run(&db, "backup");
+    let req = test::TestRequest::get()
+        .uri("/api/maintenance")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    let reply: serde_json::Value = serde_json::from_str(body.as_str()).unwrap();
+    let jobs = reply.as_array().unwrap();
+    assert_eq!(jobs.len(), 4);
+    assert_eq!(jobs[1]["job"], "backup");
+    assert!(jobs[1]["last_run"].as_i64().unwrap() > 0);
+    assert!(jobs[0]["last_run"].is_null());
+}
+
+#[test]
+async fn restore() {
+    let test = "restore";
+    let conf = default_config(test);
+    let (tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+    let db_file = tempdir.path().join(format!("{test}.sqlite"));
+    let db = database::open_db(db_file.to_str().unwrap(), false);
+
+    let (status, _) = add_link(&app, &api_key, "kept", 0, "").await;
+    assert!(status.is_success());
+    let settings = database::BackupSettings {
+        init: 0,
+        daily: 1,
+        weekly: 0,
+        monthly: 0,
+        compress: true,
+    };
+    assert!(database::backup(&db, &settings));
+    let backup_dir = tempdir.path().join("backups");
+    let manifest = std::fs::read_to_string(backup_dir.join("restore.sqlite.daily1.json")).unwrap();
+    let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+    assert_eq!(manifest["links"], 1);
+    assert_eq!(manifest["compressed"], true);
+    assert!(backup_dir.join("restore.sqlite.daily1.gz").is_file());
+    assert!(!backup_dir.join("restore.sqlite.daily1").exists());
+
+    let (status, _) = add_link(&app, &api_key, "dropped", 0, "").await;
+    assert!(status.is_success());
+
+    let restore = |backup: &str| {
+        test::TestRequest::post()
+            .uri("/api/admin/restore")
+            .insert_header(("X-API-Key", api_key.as_str()))
+            .set_payload(format!("{{\"backup\":\"{backup}\"}}"))
+            .to_request()
+    };
+    let maintenance_mode = |enabled: bool| {
+        test::TestRequest::put()
+            .uri("/api/admin/maintenance-mode")
+            .insert_header(("X-API-Key", api_key.as_str()))
+            .set_payload(format!("{{\"enabled\":{enabled}}}"))
+            .to_request()
+    };
+
+    // Restoring needs maintenance mode, which blocks any other changes
+    let resp = test::call_service(&app, restore("restore.sqlite.daily1.gz")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app, maintenance_mode(true)).await;
+    assert!(resp.status().is_success());
+    let (status, _) = add_link(&app, &api_key, "blocked", 0, "").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+    let resp = test::call_service(&app, restore("../restore.sqlite")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app, restore("restore.sqlite.daily2")).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    // Backups which don't match their manifest are rejected
+    std::fs::copy(
+        backup_dir.join("restore.sqlite.daily1.gz"),
+        backup_dir.join("restore.sqlite.daily2.gz"),
+    )
+    .unwrap();
+    std::fs::write(
+        backup_dir.join("restore.sqlite.daily2.json"),
+        manifest
+            .to_string()
+            .replace(manifest["sha256"].as_str().unwrap(), "0"),
+    )
+    .unwrap();
+    let resp = test::call_service(&app, restore("restore.sqlite.daily2.gz")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // Links keep working, but a hit queued before the restore isn't applied to the restored database
+    let req = test::TestRequest::get().uri("/kept").to_request();
+    assert!(
+        test::call_service(&app, req)
+            .await
+            .status()
+            .is_redirection()
+    );
+    let resp = test::call_service(&app, restore("restore.sqlite.daily1.gz")).await;
+    assert!(resp.status().is_success());
+    sleep(Duration::from_millis(800)).await;
+    let resp = test::call_service(&app, maintenance_mode(false)).await;
+    assert!(resp.status().is_success());
+    let links = getall(&app, &api_key, "").await;
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].shortlink, "kept");
+    assert_eq!(links[0].hits, 0);
+    // The database from before the restore is kept as an init backup
+    assert!(backup_dir.join("restore.sqlite.init1.json").is_file());
+}
+
+#[test]
+async fn download_backup() {
+    let test = "download_backup";
+    let conf = default_config(test);
+    let (tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+
+    let (status, _) = add_link(&app, &api_key, "saved", 0, "").await;
+    assert!(status.is_success());
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/backup")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/backup")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let disposition = resp.headers().get("Content-Disposition").unwrap();
+    assert!(disposition.to_str().unwrap().starts_with("attachment"));
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    assert!(body.starts_with(b"SQLite format 3\0"));
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/backup?compress=true")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    let mut decompressed = Vec::new();
+    GzDecoder::new(&body[..])
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert!(decompressed.starts_with(b"SQLite format 3\0"));
+
+    // The snapshot is restorable, and has the link
+    let downloaded = tempdir.path().join("downloaded.sqlite");
+    std::fs::write(&downloaded, &decompressed).unwrap();
+    let db = database::open_db(downloaded.to_str().unwrap(), true);
+    assert!(database::find_url("saved", "", false, &db).is_ok());
+
+    // The temporary files are removed
+    let leftover = std::fs::read_dir(tempdir.path().join("backups"))
+        .unwrap()
+        .map(|f| f.unwrap().file_name().into_string().unwrap())
+        .any(|f| f.contains(".download-"));
+    assert!(!leftover);
+}
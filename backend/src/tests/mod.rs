@@ -5,4 +5,8 @@ pub(crate) mod utils;
 
 mod access;
 mod basics;
+mod health;
 mod insertion;
+mod maintenance;
+mod openapi;
+mod v2;
@@ -0,0 +1,124 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+use actix_web::{body::to_bytes, test};
+use serde_json::Value;
+
+use super::utils::*;
+
+// The fields of a response must match the schema of its type in the OpenAPI document
+fn assert_matches_schema(spec: &Value, schema: &str, value: &Value) {
+    let schema = &spec["components"]["schemas"][schema];
+    let properties = schema["properties"].as_object().unwrap();
+    let fields = value.as_object().unwrap();
+    for field in fields.keys() {
+        assert!(properties.contains_key(field), "{field} is not documented");
+    }
+    for field in schema["required"].as_array().unwrap() {
+        let field = field.as_str().unwrap();
+        assert!(
+            fields.contains_key(field),
+            "{field} is missing in the response"
+        );
+    }
+}
+
+#[test]
+async fn openapi_document() {
+    let test = "openapi-document";
+    let conf = default_config(test);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+
+    let req = test::TestRequest::get()
+        .uri("/api/openapi.json")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    let spec: Value = serde_json::from_str(body.as_str()).unwrap();
+    assert_eq!(spec["info"]["version"], env!("CARGO_PKG_VERSION"));
+
+    // Every route needs to be documented
+    let mut paths: Vec<_> = spec["paths"].as_object().unwrap().keys().collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        [
+            "/api/admin/backup",
+            "/api/admin/maintenance-mode",
+            "/api/admin/restore",
+            "/api/all",
+            "/api/available/{shortlink}",
+            "/api/del/{shortlink}",
+            "/api/edit",
+            "/api/expand",
+            "/api/getconfig",
+            "/api/health-report",
+            "/api/login",
+            "/api/logout",
+            "/api/maintenance",
+            "/api/new",
+            "/api/revive",
+            "/api/siteurl",
+            "/api/suggest",
+            "/api/v2/links",
+            "/api/v2/links/{slug}",
+            "/api/version",
+            "/api/whoami",
+            "/preview/{shortlink}",
+            "/{shortlink}",
+        ]
+    );
+
+    // Query parameters as well
+    let params: Vec<_> = spec["paths"]["/api/all"]["get"]["parameters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["name"].as_str().unwrap())
+        .collect();
+    assert!(params.contains(&"page_size") && params.contains(&"filter"));
+
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .set_payload(r#"{"shortlink":"spec","longlink":"https://example.com","aliases":["alt"]}"#)
+        .to_request();
+    let body = to_bytes(test::call_service(&app, req).await.into_body()).await;
+    let reply: Value = serde_json::from_str(body.unwrap().as_str()).unwrap();
+    assert_matches_schema(&spec, "CreatedURL", &reply);
+
+    let req = test::TestRequest::post()
+        .uri("/api/expand")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .set_payload("spec")
+        .to_request();
+    let body = to_bytes(test::call_service(&app, req).await.into_body()).await;
+    let reply: Value = serde_json::from_str(body.unwrap().as_str()).unwrap();
+    assert_matches_schema(&spec, "LinkInfo", &reply);
+
+    let req = test::TestRequest::get()
+        .uri("/api/all")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .to_request();
+    let body = to_bytes(test::call_service(&app, req).await.into_body()).await;
+    let reply: Value = serde_json::from_str(body.unwrap().as_str()).unwrap();
+    assert_matches_schema(&spec, "DBRow", &reply[0]);
+
+    let req = test::TestRequest::get()
+        .uri("/api/getconfig")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .to_request();
+    let body = to_bytes(test::call_service(&app, req).await.into_body()).await;
+    let reply: Value = serde_json::from_str(body.unwrap().as_str()).unwrap();
+    assert_matches_schema(&spec, "BackendConfig", &reply);
+
+    let req = test::TestRequest::delete()
+        .uri("/api/del/nothing")
+        .insert_header(("X-API-Key", api_key.as_str()))
+        .to_request();
+    let body = to_bytes(test::call_service(&app, req).await.into_body()).await;
+    let reply: Value = serde_json::from_str(body.unwrap().as_str()).unwrap();
+    assert_matches_schema(&spec, "JSONResponse", &reply);
+}
@@ -35,6 +35,18 @@ pub(super) struct URLData {
     pub(super) notes: String,
     #[serde(default)]
     pub(super) reason: String,
+    #[serde(default)]
+    pub(super) aliases: Vec<String>,
+    #[serde(default)]
+    pub(super) created_at: i64,
+    #[serde(default)]
+    pub(super) updated_at: i64,
+    #[serde(default)]
+    pub(super) last_accessed_at: i64,
+    #[serde(default)]
+    pub(super) idle_ttl: i64,
+    #[serde(default)]
+    pub(super) snippet: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -50,10 +62,13 @@ pub(super) fn default_config(test: &str) -> config::Config {
         db_location: format!("/tmp/chhoto-url-test-{test}.sqlite"),
         cache_control_header: None,
         disable_frontend: true,
+        api_docs: false,
         site_url: Some(String::from("https://mydomain.com")),
+        extra_site_urls: Vec::new(),
         public_mode: false,
         public_mode_expiry_delay: None,
         use_temp_redirect: false,
+        always_preview: false,
         allowed_protocols: Vec::from(["http", "https", "ftp", "magnet"].map(|s| s.to_string())),
         password: Some(String::from("testpass")),
         hash_algorithm: config::HashAlgorithm::None,
@@ -61,6 +76,7 @@ pub(super) fn default_config(test: &str) -> config::Config {
             "Z8FNjh2J2v3yfb0xPDIVA58Pj4D0e2jSERVdoqM5pJCbU2w5tmg3PNioD6GUhaQwHHaDLBNZj0EQE8MS4TLKcUyusa05",
         )),
         slug_style: config::SlugStyle::Pair,
+        sequential_slugs: None,
         slug_length: 8,
         try_longer_slug: false,
         allow_capital_letters: false,
@@ -68,6 +84,35 @@ pub(super) fn default_config(test: &str) -> config::Config {
         use_wal_mode: true,
         ensure_acid: false,
         frontend_page_size: 10,
+        geoip_database: None,
+        trusted_proxies: Vec::new(),
+        health_check_interval: None,
+        health_check_concurrency: 4,
+        health_check_rate: 100,
+        health_failure_threshold: 1,
+        health_check_allow_private: true,
+        blocklist: None,
+        allowlist: None,
+        public_blocklist: None,
+        public_allowlist: None,
+        disable_blocked_links: false,
+        disable_idle_links: false,
+        expiry_grace_period: 0,
+        dedupe_links: false,
+        idempotency_window: 86400,
+        cleanup_schedule: None,
+        backup_schedule: None,
+        vacuum_schedule: None,
+        optimize_schedule: None,
+        backup_settings: database::BackupSettings {
+            init: 3,
+            daily: 7,
+            weekly: 0,
+            monthly: 0,
+            compress: false,
+        },
+        reserved_slugs: config::ReservedSlugs::new(None, None),
+        case_insensitive_slugs: false,
     }
 }
 
@@ -88,10 +133,13 @@ pub(super) async fn create_app(
         &mut *writer.lock().await,
         conf.use_wal_mode,
         conf.ensure_acid,
+        conf.case_insensitive_slugs,
+        &conf.backup_settings,
     );
 
-    let (hits_tx, hits_rx) = mpsc::channel::<(String, bool)>(1024);
-    background::spawn_hits_worker(Arc::clone(&writer), hits_rx);
+    let (hits_tx, hits_rx) = mpsc::channel::<background::HitUpdate>(1024);
+    let maintenance = Arc::new(AtomicBool::new(false));
+    background::spawn_hits_worker(Arc::clone(&writer), hits_rx, Arc::clone(&maintenance));
 
     (
         tempdir,
@@ -102,17 +150,39 @@ pub(super) async fn create_app(
                     reader: database::open_db(db_file.to_str().unwrap(), false),
                     writer,
                     config: conf.clone(),
+                    geoip: None,
+                    lists: Arc::new(RwLock::new(Arc::new(
+                        lists::DomainLists::load(conf).unwrap(),
+                    ))),
+                    maintenance,
                 }))
+                .wrap(middleware::from_fn(utils::maintenance_guard))
                 .service(services::siteurl)
                 .service(services::version)
                 .service(services::getconfig)
                 .service(services::add_links)
                 .service(services::getall)
                 .service(services::link_handler)
+                .service(services::preview_handler)
                 .service(services::edit_link)
+                .service(services::revive_link)
                 .service(services::delete_link)
                 .service(services::whoami)
-                .service(services::expand),
+                .service(services::health_report)
+                .service(services::maintenance)
+                .service(services::maintenance_mode)
+                .service(services::restore)
+                .service(services::download_backup)
+                .service(services::expand)
+                .service(services::available)
+                .service(services::suggest)
+                .service(services::openapi_spec)
+                .service(services::v2::list)
+                .service(services::v2::create)
+                .service(services::v2::show)
+                .service(services::v2::create_with_slug)
+                .service(services::v2::update)
+                .service(services::v2::remove),
         )
         .await,
     )
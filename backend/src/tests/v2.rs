@@ -0,0 +1,175 @@
+// SPDX-FileCopyrightText: 2023-2026 Sayantan Santra <sayantan.santra689@gmail.com>
+// SPDX-License-Identifier: MIT
+
+use actix_http::{Request, StatusCode};
+use actix_service::Service;
+use actix_web::{Error, body::to_bytes, dev::ServiceResponse, test};
+use serde_json::Value;
+
+use super::utils::*;
+
+async fn call<T: Service<Request, Response = ServiceResponse, Error = Error>>(
+    app: &T,
+    req: test::TestRequest,
+) -> (StatusCode, Value) {
+    let resp = test::call_service(app, req.to_request()).await;
+    let status = resp.status();
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    (status, serde_json::from_str(body.as_str()).unwrap())
+}
+
+#[test]
+async fn v2_links() {
+    let test = "v2-links";
+    let conf = default_config(test);
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+    let auth = ("X-API-Key", api_key.as_str());
+
+    // The same envelope is used for every error
+    let (status, reply) = call(&app, test::TestRequest::get().uri("/api/v2/links")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(reply["success"], false);
+    assert_eq!(reply["error"]["code"], "unauthorized");
+
+    let new = |slug: &str, body: &str| {
+        test::TestRequest::post()
+            .uri(&format!("/api/v2/links/{slug}"))
+            .insert_header(auth)
+            .set_payload(body.to_owned())
+    };
+    let (status, reply) = call(&app, new("docs", r#"{"long_url":"https://example.com"}"#)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(reply["data"]["slug"], "docs");
+    assert_eq!(reply["data"]["short_url"], "https://mydomain.com/docs");
+    assert_eq!(reply["data"]["long_url"], "https://example.com");
+
+    for (slug, body, status, code, field) in [
+        (
+            "docs",
+            r#"{"long_url":"https://example.com"}"#,
+            409,
+            "conflict",
+            None,
+        ),
+        (
+            "api",
+            r#"{"long_url":"https://example.com"}"#,
+            403,
+            "forbidden",
+            None,
+        ),
+        (
+            "bad",
+            r#"{"long_url":"nothing"}"#,
+            400,
+            "invalid_field",
+            Some("long_url"),
+        ),
+        (
+            "Bad",
+            r#"{"long_url":"https://example.com"}"#,
+            400,
+            "invalid_field",
+            Some("slug"),
+        ),
+        (
+            "old",
+            r#"{"longlink":"https://example.com"}"#,
+            400,
+            "bad_request",
+            None,
+        ),
+    ] {
+        let (got, reply) = call(&app, new(slug, body)).await;
+        assert_eq!(got.as_u16(), status, "{slug}");
+        assert_eq!(reply["error"]["code"], code, "{slug}");
+        assert_eq!(reply["error"]["field"].as_str(), field, "{slug}");
+    }
+
+    // Generated slugs
+    let req = test::TestRequest::post()
+        .uri("/api/v2/links")
+        .insert_header(auth)
+        .set_payload(r#"{"long_url":"https://example.com/other"}"#);
+    let (status, reply) = call(&app, req).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(!reply["data"]["slug"].as_str().unwrap().is_empty());
+
+    // Patching keeps the fields that aren't given
+    let req = test::TestRequest::patch()
+        .uri("/api/v2/links/docs")
+        .insert_header(auth)
+        .set_payload(r#"{"notes":"Manual","aliases":["manual"]}"#);
+    let (status, reply) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(reply["data"]["notes"], "Manual");
+    assert_eq!(reply["data"]["long_url"], "https://example.com");
+    let req = test::TestRequest::get()
+        .uri("/api/v2/links/manual")
+        .insert_header(auth);
+    let (status, reply) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(reply["data"]["slug"], "docs");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v2/links")
+        .insert_header(auth);
+    let (status, reply) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(reply["data"].as_array().unwrap().len(), 2);
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v2/links/docs")
+        .insert_header(auth);
+    let (status, reply) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(reply["success"], true);
+    let req = test::TestRequest::get()
+        .uri("/api/v2/links/docs")
+        .insert_header(auth);
+    let (status, reply) = call(&app, req).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(reply["error"]["code"], "not_found");
+}
+
+#[test]
+async fn v2_public_mode() {
+    let test = "v2-public-mode";
+    let mut conf = default_config(test);
+    conf.public_mode = true;
+    conf.dedupe_links = true;
+    let (_tempdir, app) = create_app(&conf, test).await;
+    let api_key = conf.api_key.clone().unwrap();
+
+    let body = r#"{"long_url":"https://example.com/private","notes":"secret"}"#;
+    let (status, admin) = call(
+        &app,
+        test::TestRequest::post()
+            .uri("/api/v2/links")
+            .insert_header(("X-API-Key", api_key.as_str()))
+            .set_payload(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(admin["data"]["notes"], "secret");
+
+    // Public mode users get a new link, with none of the private details
+    let (status, public) = call(
+        &app,
+        test::TestRequest::post()
+            .uri("/api/v2/links")
+            .set_payload(r#"{"long_url":"https://example.com/private","dedupe":true}"#),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let data = public["data"].as_object().unwrap();
+    assert_ne!(data["slug"], admin["data"]["slug"]);
+    assert_eq!(data["long_url"], "https://example.com/private");
+    let mut fields: Vec<_> = data.keys().map(String::as_str).collect();
+    fields.sort();
+    assert_eq!(
+        fields,
+        ["domain", "expiry_time", "long_url", "short_url", "slug"]
+    );
+}
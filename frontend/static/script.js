@@ -181,6 +181,9 @@ const refreshData = async () => {
         }
         params.append("page_size", CONFIG.frontend_page_size);
         params.append("page_after", LOCAL_DATA.at(-1)["shortlink"]);
+        if (LOCAL_DATA.at(-1)["domain"]) {
+          params.append("page_after_domain", LOCAL_DATA.at(-1)["domain"]);
+        }
       }
 
       return params;
@@ -468,7 +471,32 @@ const formatRelativeTime = (timestamp) => {
   }
 };
 
-const A_SHORT = (s) => `<a href="${SITE_URL}/${s}" target="_blank">${s}</a>`;
+// Links on the extra domains use the matching site URL, or the domain itself
+const siteUrlFor = (domain) => {
+  if (!domain) return SITE_URL;
+  for (const siteUrl of CONFIG?.extra_site_urls ?? []) {
+    const url = new URL(siteUrl);
+    if (url.host == domain) {
+      return siteUrl
+        .replace(url.hostname, punycode.toUnicode(url.hostname))
+        .replace(/\/$/, "");
+    }
+  }
+  return `${window.location.protocol}//${punycode.toUnicode(domain)}`;
+};
+
+const shortUrlFor = (shortlink, domain) =>
+  `${siteUrlFor(domain)}/${shortlink}`;
+
+// Links are identified using both the shortlink and the domain
+const isSameLink = (row, shortlink, domain) =>
+  row["shortlink"] == shortlink && (row["domain"] ?? "") == domain;
+
+const domainQuery = (domain) =>
+  domain ? `?domain=${encodeURIComponent(domain)}` : "";
+
+const A_SHORT = (s, domain) =>
+  `<a href="${shortUrlFor(s, domain)}" target="_blank">${s}</a>`;
 const TD = (s, u) => {
   const td = document.createElement("td");
   const div = document.createElement("div");
@@ -497,8 +525,9 @@ const TR = (i, row) => {
   longTD.appendChild(div);
 
   const shortlink = row["shortlink"];
-  tr.id = shortlink;
-  const shortTD = TD(A_SHORT(shortlink), "Short URL");
+  const domain = row["domain"] ?? "";
+  tr.id = domain ? `${domain}/${shortlink}` : shortlink;
+  const shortTD = TD(A_SHORT(shortlink, domain), "Short URL");
   shortTD.setAttribute("name", "shortColumn");
 
   const hitsTD = TD(row["hits"], null);
@@ -534,11 +563,13 @@ const TR = (i, row) => {
   const btnGrp = document.createElement("div");
   btnGrp.classList.add("pure-button-group");
   btnGrp.role = "group";
-  btnGrp.appendChild(copyButton(shortlink));
-  btnGrp.appendChild(qrCodeButton(shortlink));
-  btnGrp.appendChild(infoButton(shortlink));
-  btnGrp.appendChild(editButton(shortlink, longlink, expiryTime, row["notes"]));
-  btnGrp.appendChild(deleteButton(shortlink));
+  btnGrp.appendChild(copyButton(shortlink, domain));
+  btnGrp.appendChild(qrCodeButton(shortlink, domain));
+  btnGrp.appendChild(infoButton(shortlink, domain));
+  btnGrp.appendChild(
+    editButton(shortlink, domain, longlink, expiryTime, row["notes"]),
+  );
+  btnGrp.appendChild(deleteButton(shortlink, domain));
   actionsTD.appendChild(btnGrp);
 
   for (const td of [numTD, shortTD, longTD, hitsTD, expiryTD, actionsTD]) {
@@ -547,8 +578,8 @@ const TR = (i, row) => {
   return tr;
 };
 
-const copyShortUrl = (shortLink, doCopy) => {
-  const fullLink = `${SITE_URL}/${shortLink}`;
+const copyShortUrl = (shortLink, doCopy, domain = "") => {
+  const fullLink = shortUrlFor(shortLink, domain);
   const linkElt = `<a href=${fullLink} target="_blank">${fullLink}</a>`;
 
   let copyPromise;
@@ -590,7 +621,7 @@ const addHTTPSToLongURL = (id) => {
   input.value = url;
 };
 
-const copyButton = (shortUrl) => {
+const copyButton = (shortUrl, domain) => {
   const btn = document.createElement("button");
   btn.classList.add("svg-button");
   btn.innerHTML = SVG_COPY_BUTTON;
@@ -598,12 +629,12 @@ const copyButton = (shortUrl) => {
 
   btn.onclick = (e) => {
     e.preventDefault();
-    copyShortUrl(shortUrl, true);
+    copyShortUrl(shortUrl, true, domain);
   };
   return btn;
 };
 
-const editButton = (shortUrl, longUrl, expiry, notes) => {
+const editButton = (shortUrl, domain, longUrl, expiry, notes) => {
   const btn = document.createElement("button");
   btn.classList.add("svg-button");
   btn.innerHTML = SVG_EDIT_BUTTON;
@@ -614,8 +645,12 @@ const editButton = (shortUrl, longUrl, expiry, notes) => {
     const editUrlSpan = document.getElementById("edit-link");
     const editedUrl = document.getElementById("edited-url");
     const editedExpiry = document.getElementById("edited-expiry");
-    if (editUrlSpan.textContent != shortUrl) {
+    if (
+      editUrlSpan.textContent != shortUrl ||
+      editUrlSpan.dataset.domain != domain
+    ) {
       editUrlSpan.textContent = shortUrl;
+      editUrlSpan.dataset.domain = domain;
       document.getElementById("edit-checkbox").checked = false;
       editedUrl.value = longUrl;
       document.getElementById("edited-notes").value = notes;
@@ -659,7 +694,7 @@ const editButton = (shortUrl, longUrl, expiry, notes) => {
   return btn;
 };
 
-const infoButton = (shortUrl) => {
+const infoButton = (shortUrl, domain) => {
   const btn = document.createElement("button");
   btn.classList.add("svg-button");
   btn.innerHTML = SVG_INFO_BUTTON;
@@ -668,7 +703,7 @@ const infoButton = (shortUrl) => {
   btn.onclick = () => {
     document.getElementById("container").style.filter = "blur(2px)";
     document.getElementById("info-dialog").showModal();
-    const row = LOCAL_DATA.filter((row) => row.shortlink == shortUrl)[0];
+    const row = LOCAL_DATA.find((row) => isSameLink(row, shortUrl, domain));
     document.getElementById("info-short").textContent = row.shortlink;
     document.getElementById("info-long").textContent = row.longlink;
     document.getElementById("info-hits").innerHTML = row.hits;
@@ -682,7 +717,7 @@ const infoButton = (shortUrl) => {
   return btn;
 };
 
-const qrCodeButton = (shortlink) => {
+const qrCodeButton = (shortlink, domain) => {
   const btn = document.createElement("button");
   btn.classList.add("svg-button");
   btn.innerHTML = SVG_QR_BUTTON;
@@ -697,7 +732,7 @@ const qrCodeButton = (shortlink) => {
   btn.onclick = () => {
     const tmpDiv = document.createElement("div");
     new QRCode(tmpDiv, {
-      text: `${SITE_URL}/${shortlink}`,
+      text: shortUrlFor(shortlink, domain),
       correctLevel: QRCode.CorrectLevel.H,
       height: qrWidth,
       width: qrWidth,
@@ -740,7 +775,7 @@ const qrCodeButton = (shortlink) => {
   return btn;
 };
 
-const deleteButton = (shortUrl) => {
+const deleteButton = (shortUrl, domain) => {
   const btn = document.createElement("button");
   btn.classList.add("svg-button");
   btn.innerHTML = SVG_DELETE_BUTTON;
@@ -750,7 +785,7 @@ const deleteButton = (shortUrl) => {
     e.preventDefault();
     if (confirm("Click OK to delete the entry '" + shortUrl + "'.")) {
       showAlert("&nbsp;", "transparent");
-      fetch(prepSubdir(`/api/del/${shortUrl}`), {
+      fetch(prepSubdir(`/api/del/${shortUrl}${domainQuery(domain)}`), {
         method: "DELETE",
         cache: "no-cache",
       })
@@ -759,7 +794,7 @@ const deleteButton = (shortUrl) => {
             throw new Error("Could not delete.");
           }
           LOCAL_DATA = LOCAL_DATA.filter(
-            (item) => item["shortlink"] != shortUrl,
+            (item) => !isSameLink(item, shortUrl, domain),
           );
           if (
             LOCAL_DATA.length <= CUR_PAGE * CONFIG.frontend_page_size &&
@@ -886,6 +921,7 @@ const submitEdit = () => {
   const editUrlSpan = document.getElementById("edit-link");
   const longUrl = urlInput.value;
   const shortUrl = editUrlSpan.textContent;
+  const domain = editUrlSpan.dataset.domain ?? "";
   const checkBox = document.getElementById("edit-checkbox");
   const notes = document.getElementById("edited-notes").value;
   let expiry = 0;
@@ -896,6 +932,7 @@ const submitEdit = () => {
   if (confirm("Click OK to confirm the edit of '" + shortUrl + "'.")) {
     data = {
       shortlink: shortUrl,
+      domain: domain,
       longlink: longUrl,
       reset_hits: checkBox.checked,
       notes: notes,
@@ -921,8 +958,8 @@ const submitEdit = () => {
           showAlert(JSON.parse(text).reason, "light-dark(red, #a01e1e)");
         } else {
           editUrlSpan.textContent = shortUrl;
-          const editedIndex = LOCAL_DATA.findIndex(
-            (item) => item["shortlink"] == shortUrl,
+          const editedIndex = LOCAL_DATA.findIndex((item) =>
+            isSameLink(item, shortUrl, domain),
           );
           LOCAL_DATA[editedIndex]["longlink"] = longUrl;
           LOCAL_DATA[editedIndex]["notes"] = notes;